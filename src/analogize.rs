@@ -0,0 +1,108 @@
+use crate::{
+    columns::ColumnArgs, embeddings, lock, meta::Meta, pipeline::Pipeline, plugin::PluginArgs,
+    provider::ProviderArgs, ranked, tombstones, Query,
+};
+use clap::Args;
+use polars::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct AnalogizeArgs {
+    input: PathBuf,
+    /// Terms joined by `+` and `-`, e.g. "entropy" - "heat" + "information"
+    #[arg(required = true)]
+    terms: Vec<String>,
+    /// Convert embeddings stored with a foreign dtype (e.g. list[f64]) to list[f32] instead of failing
+    #[arg(long)]
+    cast_embeddings: bool,
+    /// Search even if the dataset metadata can't confirm that the provider serves the same model
+    #[arg(long)]
+    force: bool,
+    #[command(flatten)]
+    provider: ProviderArgs,
+    #[command(flatten)]
+    plugin: PluginArgs,
+    #[command(flatten)]
+    columns: ColumnArgs,
+}
+
+// Split `a - b + c` into the terms with their signs
+fn signed(terms: &[String]) -> PolarsResult<Vec<(f32, &str)>> {
+    let mut signed = vec![(1.0, terms[0].as_str())];
+    for pair in terms[1..].chunks(2) {
+        let sign = match pair[0].as_str() {
+            "+" => 1.0,
+            "-" => -1.0,
+            op => polars_bail!(ComputeError: "expected `+` or `-` between terms, got `{}`", op),
+        };
+        let Some(term) = pair.get(1) else {
+            polars_bail!(ComputeError: "`{}` must be followed by a term", pair[0]);
+        };
+        signed.push((sign, term.as_str()));
+    }
+    Ok(signed)
+}
+
+/// Embed every term, add or subtract the vectors and search with the normalized result
+pub fn analogize(args: AnalogizeArgs) -> PolarsResult<()> {
+    let terms = signed(&args.terms)?;
+
+    let _lock = lock::Lock::shared(&args.input)?;
+    let lf = tombstones::live(&args.input, args.columns.scan(&args.input)?)?;
+    let schema = lf.schema()?;
+    let embeddings = embeddings(&schema, args.cast_embeddings)?;
+    let meta = Meta::load(&args.input)?;
+
+    // Every term is prepared the way search prepares a query
+    let query = Query::new(&args.input, &meta, &args.plugin)?;
+    let terms: Vec<(f32, String)> = terms
+        .into_iter()
+        .map(|(sign, term)| Ok((sign, query.text(term.to_string())?)))
+        .collect::<PolarsResult<_>>()?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    let mut embed = |provider: &ProviderArgs| {
+        let (mut served, mut combined) = (String::new(), Vec::<f32>::new());
+        for (sign, term) in &terms {
+            let embedding = match runtime.block_on(provider.clone().embed(term.clone())) {
+                Ok((model, Some(embedding))) => {
+                    served = model;
+                    embedding
+                }
+                Ok((_, None)) => polars_bail!(ComputeError: "no embedding returned for `{}`", term),
+                Err(e) => polars_bail!(ComputeError: "failed to embed `{}`: {}", term, e),
+            };
+            combined.resize(embedding.len(), 0.0);
+            for (c, e) in combined.iter_mut().zip(embedding) {
+                *c += sign * e;
+            }
+        }
+        // Stored embeddings are unit length, so the similarities stay on the same scale as a plain search
+        let norm = combined.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm == 0.0 {
+            polars_bail!(ComputeError: "the terms cancel each other out");
+        }
+        combined.iter_mut().for_each(|x| *x /= norm);
+        Ok((served, combined))
+    };
+    let vector = query.vector(&meta, &args.provider, None, args.force, &mut embed)?;
+    let similarity = query.similarity(
+        &lf,
+        &meta,
+        &args.provider,
+        args.force,
+        embeddings.clone(),
+        vector,
+        &mut embed,
+    )?;
+
+    std::env::set_var("POLARS_FMT_MAX_ROWS", "20");
+    std::env::set_var("POLARS_FMT_STR_LEN", "50");
+
+    let pipeline = Pipeline::default();
+    let df = ranked(
+        lf, &schema, &meta, embeddings, similarity, None, false, &pipeline,
+    )?;
+    println!("{}", df.head(Some(20)));
+
+    Ok(())
+}
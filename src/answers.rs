@@ -0,0 +1,168 @@
+use crate::{files, manifest, ROW_GROUP_SIZE};
+use polars::prelude::*;
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+};
+
+/// Answers collected by ParseXML, kept in a `<dataset>.answers.parquet` sidecar keyed by the id of their question
+#[derive(Default)]
+pub struct Answers {
+    ids: Vec<String>,
+    parent_ids: Vec<String>,
+    bodies: Vec<String>,
+    scores: Vec<Option<i32>>,
+    creation_dates: Vec<Option<chrono::NaiveDateTime>>,
+    author_ids: Vec<Option<u32>>,
+    authors: Vec<Option<String>>,
+    licenses: Vec<Option<String>>,
+    // Answers of an earlier parse to questions this one didn't see
+    earlier: Option<DataFrame>,
+    // Prepended to the ids of the dump being read, when a dataset holds several sites
+    prefix: String,
+}
+
+fn path(dataset: impl AsRef<Path>) -> PathBuf {
+    let mut path = dataset.as_ref().as_os_str().to_owned();
+    path.push(".answers.parquet");
+    path.into()
+}
+
+impl Answers {
+    /// Add the answer with these attributes and its body stripped of HTML
+    pub fn push<'a>(&mut self, attribute: impl Fn(&str) -> Option<&'a str>, body: String) {
+        let (Some(id), Some(parent_id)) = (attribute("Id"), attribute("ParentId")) else {
+            return;
+        };
+        self.ids.push(format!("{}{}", self.prefix, id));
+        self.parent_ids
+            .push(format!("{}{}", self.prefix, parent_id));
+        self.bodies.push(body);
+        self.scores
+            .push(attribute("Score").and_then(|v| v.parse().ok()));
+        self.creation_dates.push(
+            attribute("CreationDate").and_then(|v| {
+                chrono::NaiveDateTime::parse_from_str(v, "%Y-%m-%dT%H:%M:%S%.f").ok()
+            }),
+        );
+        self.author_ids
+            .push(attribute("OwnerUserId").and_then(|v| v.parse().ok()));
+        self.authors
+            .push(attribute("OwnerDisplayName").map(String::from));
+        self.licenses
+            .push(attribute("ContentLicense").map(String::from));
+    }
+
+    /// Prefix the ids of the answers pushed from now on with `<site>/`
+    pub fn site(&mut self, site: Option<&str>) {
+        self.prefix = site.map(|s| format!("{}/", s)).unwrap_or_default();
+    }
+
+    /// Keep the answers already next to `dataset` to the questions missing from `parsed`, when merging
+    pub fn keep(&mut self, dataset: &Path, parsed: &Series) -> PolarsResult<()> {
+        let path = path(dataset);
+        if !path.exists() {
+            return Ok(());
+        }
+        let parsed: HashSet<&str> = parsed.str()?.into_iter().flatten().collect();
+        let earlier = LazyFrame::scan_parquet(&path, Default::default())?.collect()?;
+        let missing: BooleanChunked = earlier
+            .column("parent_id")?
+            .str()?
+            .into_iter()
+            .map(|p| !p.is_some_and(|p| parsed.contains(p)))
+            .collect();
+        self.earlier = Some(earlier.filter(&missing)?);
+        Ok(())
+    }
+
+    /// Write the answers to the questions in `questions` next to `dataset`, returning how many were kept
+    pub fn write(self, dataset: &Path, questions: &Series) -> PolarsResult<usize> {
+        let mut df = df!(
+            "id" => self.ids,
+            "parent_id" => self.parent_ids,
+            "body" => self.bodies,
+            "score" => self.scores,
+            "creation_date" => self.creation_dates,
+            "author_id" => self.author_ids,
+            "author" => self.authors,
+            "license" => self.licenses,
+        )?;
+        if let Some(earlier) = self.earlier {
+            df.vstack_mut(&earlier.select(df.get_column_names())?)?;
+        }
+        // Answers to skipped questions have nothing to hang off
+        let questions: HashSet<&str> = questions.str()?.into_iter().flatten().collect();
+        let kept: BooleanChunked = df
+            .column("parent_id")?
+            .str()?
+            .into_iter()
+            .map(|p| p.is_some_and(|p| questions.contains(p)))
+            .collect();
+        let mut df = df
+            .filter(&kept)?
+            .lazy()
+            .sort_by_exprs(
+                [col("parent_id"), col("score")],
+                [false, true],
+                false,
+                false,
+            )
+            .collect()?;
+
+        // Sorted by question, so that the statistics narrow the lookup of one question's answers down
+        files::atomic(path(dataset), |file| {
+            ParquetWriter::new(file)
+                .with_statistics(true)
+                .with_row_group_size(Some(ROW_GROUP_SIZE))
+                .finish(&mut df)?;
+            Ok(())
+        })?;
+        manifest::record(dataset, ".answers.parquet")?;
+        Ok(df.height())
+    }
+
+    /// Drop the answers of an earlier parse, which no longer match the dataset
+    pub fn clear(dataset: &Path) -> PolarsResult<()> {
+        match std::fs::remove_file(files::long(&path(dataset))) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => manifest::forget(dataset, ".answers.parquet"),
+        }
+    }
+}
+
+/// Answers to the question `id`, the best scored first, None if the dataset was parsed without them
+pub fn of(dataset: &Path, id: &str) -> PolarsResult<Option<DataFrame>> {
+    let path = path(dataset);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let df = LazyFrame::scan_parquet(&path, Default::default())?
+        .filter(col("parent_id").eq(lit(id.to_string())))
+        .collect()?;
+    Ok(Some(df))
+}
+
+/// Drop the answers to the `questions` deleted from `dataset`
+pub fn drop(dataset: &Path, questions: &BTreeSet<String>) -> PolarsResult<()> {
+    let path = path(dataset);
+    if !path.exists() {
+        return Ok(());
+    }
+    let df = LazyFrame::scan_parquet(&path, Default::default())?.collect()?;
+    let kept: BooleanChunked = df
+        .column("parent_id")?
+        .str()?
+        .into_iter()
+        .map(|p| !p.is_some_and(|p| questions.contains(p)))
+        .collect();
+    let mut df = df.filter(&kept)?;
+    files::atomic(&path, |file| {
+        ParquetWriter::new(file)
+            .with_statistics(true)
+            .with_row_group_size(Some(ROW_GROUP_SIZE))
+            .finish(&mut df)?;
+        Ok(())
+    })?;
+    manifest::record(dataset, ".answers.parquet")
+}
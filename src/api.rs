@@ -0,0 +1,310 @@
+use crate::{
+    answers, audit, date, files, lock, manifest, markdown, merge, meta::Meta, scrub::ScrubArgs,
+    store, write_dataset,
+};
+use clap::Args;
+use polars::prelude::*;
+use serde::Deserialize;
+use std::{io::Read, path::PathBuf, time::Duration};
+
+// Attempts at a page throttled or failed by the server before giving up
+const RETRIES: u32 = 6;
+
+#[derive(Args)]
+pub struct IngestApiArgs {
+    /// The Stack Exchange site to pull questions from, e.g. physics.stackexchange.com
+    site: String,
+    /// The dataset to write, or its object store URL such as s3://bucket/physics.parquet
+    output: PathBuf,
+    /// Only pull questions carrying any of these tags, may be repeated [default: all questions]
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Skip questions asked before this date, e.g. 2020-01-01, in UTC
+    #[arg(long, value_name = "DATE", value_parser = date)]
+    after: Option<chrono::NaiveDateTime>,
+    /// Skip questions asked on or after this date
+    #[arg(long, value_name = "DATE", value_parser = date)]
+    before: Option<chrono::NaiveDateTime>,
+    /// Skip questions scored below this
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    min_score: i32,
+    /// Stop after this many pages of 100 questions per tag
+    #[arg(long)]
+    max_pages: Option<usize>,
+    /// Stack Exchange API key, raising the daily quota from 300 to 10,000 requests [default: $STACK_EXCHANGE_KEY]
+    #[arg(long)]
+    key: Option<String>,
+    /// Base URL of the API, for a mirror or a proxy
+    #[arg(
+        long,
+        value_name = "URL",
+        default_value = "https://api.stackexchange.com/2.3"
+    )]
+    api: String,
+    /// Link to a question with `{id}` in place of its id [default: https://<site>/questions/{id}]
+    #[arg(long, value_name = "TEMPLATE")]
+    url_template: Option<String>,
+    /// Convert bodies to Markdown, keeping code blocks, lists and links, instead of stripping all HTML
+    #[arg(long)]
+    markdown: bool,
+    #[command(flatten)]
+    scrub: ScrubArgs,
+    /// Overwrite the output instead of merging into it, dropping its embeddings
+    #[arg(long)]
+    replace: bool,
+}
+
+// A page of the response wrapper, see https://api.stackexchange.com/docs/wrapper
+#[derive(Deserialize)]
+struct Page {
+    #[serde(default)]
+    items: Vec<Question>,
+    #[serde(default)]
+    has_more: bool,
+    /// Seconds to wait before the next request, the API blocks clients that ignore it
+    backoff: Option<u64>,
+    quota_remaining: Option<u64>,
+    error_id: Option<u32>,
+    error_name: Option<String>,
+    error_message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Question {
+    question_id: u64,
+    title: String,
+    #[serde(default)]
+    body: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    score: i32,
+    answer_count: u32,
+    view_count: Option<u32>,
+    /// Seconds since the epoch
+    creation_date: i64,
+    owner: Option<Owner>,
+    content_license: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Owner {
+    user_id: Option<u32>,
+    display_name: Option<String>,
+    reputation: Option<i32>,
+}
+
+/// Pull the questions of a site from the Stack Exchange API into a dataset of the schema parse-xml writes
+pub fn ingest(args: IngestApiArgs) -> PolarsResult<()> {
+    // Pulled into a local copy of the output, to merge into what is already stored
+    if files::remote(&args.output) {
+        let url = args.output.clone();
+        return store::staged(&url, |local| {
+            ingest(IngestApiArgs {
+                output: local.to_path_buf(),
+                ..args
+            })
+        });
+    }
+    let scrubber = args.scrub.scrubber()?;
+    let _lock = lock::Lock::exclusive(&args.output)?;
+    let merging = !args.replace && args.output.exists();
+    let mut meta = match merging {
+        true => {
+            manifest::verify(&args.output)?;
+            Meta::load(&args.output)?
+        }
+        false => Meta::default(),
+    };
+    meta.set_site(args.site.clone(), args.url_template.clone())?;
+    meta.scrub = Some(meta.scrub.take().unwrap_or_default().union(&args.scrub));
+    // Brew embeds the questions pulled, rather than the physics tags of a dataset parsed before it recorded them
+    meta.brew_tags = match (merging, meta.brew_tags.take()) {
+        (true, None) => None,
+        (true, Some(tags)) if tags.is_empty() => Some(tags),
+        (_, Some(mut tags)) if !args.tags.is_empty() => {
+            tags.extend(
+                args.tags
+                    .iter()
+                    .filter(|t| !tags.contains(t))
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            );
+            Some(tags)
+        }
+        _ => Some(args.tags.clone()),
+    };
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let client = reqwest::Client::new();
+    // The API takes questions carrying all the tags given at once, so every tag is pulled on its own
+    let tags: Vec<Option<&str>> = match args.tags.is_empty() {
+        true => vec![None],
+        false => args.tags.iter().map(|t| Some(t.as_str())).collect(),
+    };
+    let mut questions: Vec<Question> = vec![];
+    let mut seen = std::collections::HashSet::new();
+    let mut quota = None;
+    for tag in tags {
+        for n in 1.. {
+            if args.max_pages.is_some_and(|max| n > max) {
+                break;
+            }
+            let page = runtime.block_on(page(&client, &args, tag, n))?;
+            quota = page.quota_remaining.or(quota);
+            let fetched = page.items.len();
+            questions.extend(
+                page.items
+                    .into_iter()
+                    .filter(|q| q.score >= args.min_score && seen.insert(q.question_id)),
+            );
+            println!(
+                "Fetched page {}{} ({} questions)",
+                n,
+                tag.map(|t| format!(" of <{}>", t)).unwrap_or_default(),
+                fetched
+            );
+            if let Some(seconds) = page.backoff {
+                std::thread::sleep(Duration::from_secs(seconds));
+            }
+            if !page.has_more {
+                break;
+            }
+        }
+    }
+    if let Some(quota) = quota {
+        println!("{} requests left in the daily quota", quota);
+    }
+
+    let text = |html: &str| {
+        let html = match scrubber {
+            Some(ref scrubber) => scrubber.scrub(html),
+            None => html.into(),
+        };
+        match args.markdown {
+            true => markdown::from_html(&html),
+            false => voca_rs::strip::strip_tags(&html),
+        }
+    };
+    let creation_dates: Vec<Option<chrono::NaiveDateTime>> = questions
+        .iter()
+        .map(|q| chrono::DateTime::from_timestamp(q.creation_date, 0).map(|d| d.naive_utc()))
+        .collect();
+    let tags: Vec<Series> = questions.iter().map(|q| Series::new("", &q.tags)).collect();
+    let embeddings = vec![None::<Series>; questions.len()];
+    let df = df!(
+        "id" => questions.iter().map(|q| q.question_id.to_string()).collect::<Vec<_>>(),
+        // Titles come HTML escaped, as in the dumps
+        "title" => questions.iter().map(|q| markdown::unescape(&q.title)).collect::<Vec<_>>(),
+        "body" => questions.iter().map(|q| text(q.body.trim())).collect::<Vec<_>>(),
+        "tags" => tags,
+        "score" => questions.iter().map(|q| q.score).collect::<Vec<_>>(),
+        "answer_count" => questions.iter().map(|q| q.answer_count).collect::<Vec<_>>(),
+        "view_count" => questions.iter().map(|q| q.view_count).collect::<Vec<_>>(),
+        "creation_date" => creation_dates,
+        "author_id" => questions.iter().map(|q| q.owner.as_ref().and_then(|o| o.user_id)).collect::<Vec<_>>(),
+        "author" => questions.iter().map(|q| q.owner.as_ref().and_then(|o| o.display_name.as_deref()).map(markdown::unescape)).collect::<Vec<_>>(),
+        "license" => questions.iter().map(|q| q.content_license.clone()).collect::<Vec<_>>(),
+        "embeddings" => embeddings,
+        "reputation" => questions.iter().map(|q| q.owner.as_ref().and_then(|o| o.reputation)).collect::<Vec<_>>()
+    )?;
+    println!("{}", df);
+    audit::changed(&args.output, df.height());
+
+    let mut df = match merging {
+        true => merge::upsert(df, &args.output)?,
+        false => df,
+    };
+    write_dataset(&mut df, &args.output)?;
+    meta.save(&args.output)?;
+    // The answers of an earlier parse go stale along with the questions they were parsed with
+    if !merging {
+        answers::Answers::clear(&args.output)?;
+    }
+
+    println!("Finished writing");
+
+    Ok(())
+}
+
+// Fetch one page of questions, waiting out throttling and server errors
+async fn page(
+    client: &reqwest::Client,
+    args: &IngestApiArgs,
+    tag: Option<&str>,
+    n: usize,
+) -> PolarsResult<Page> {
+    let mut query = vec![
+        ("site", args.site.clone()),
+        ("page", n.to_string()),
+        ("pagesize", "100".to_string()),
+        ("order", "asc".to_string()),
+        ("sort", "creation".to_string()),
+        // The default filter leaves out the bodies
+        ("filter", "withbody".to_string()),
+    ];
+    if let Some(tag) = tag {
+        query.push(("tagged", tag.to_string()));
+    }
+    if let Some(after) = args.after {
+        query.push(("fromdate", after.and_utc().timestamp().to_string()));
+    }
+    // Inclusive in the API, exclusive here
+    if let Some(before) = args.before {
+        query.push(("todate", (before.and_utc().timestamp() - 1).to_string()));
+    }
+    if let Some(key) = args
+        .key
+        .clone()
+        .or_else(|| std::env::var("STACK_EXCHANGE_KEY").ok())
+    {
+        query.push(("key", key));
+    }
+
+    let url = format!("{}/questions", args.api.trim_end_matches('/'));
+    let mut delay = Duration::from_secs(1);
+    for attempt in 1..=RETRIES {
+        let failed = |e: &dyn std::fmt::Display| polars_err!(ComputeError: "failed to fetch page {} from {}: {}", n, url, e);
+        let retry = match client.get(&url).query(&query).send().await {
+            Ok(response) => {
+                let status = response.status();
+                let bytes = response.bytes().await.map_err(|e| failed(&e))?;
+                let page: Page = serde_json::from_slice(&decompress(&bytes)?)
+                    .map_err(|e| failed(&format!("{} ({})", e, status)))?;
+                match page.error_id {
+                    None => return Ok(page),
+                    // Throttled, or the server failed
+                    Some(502 | 503 | 500) if attempt < RETRIES => format!(
+                        "{}: {}",
+                        page.error_name.unwrap_or_default(),
+                        page.error_message.unwrap_or_default()
+                    ),
+                    Some(id) => polars_bail!(
+                        ComputeError: "the API refused page {} with error {} {}: {}",
+                        n, id, page.error_name.unwrap_or_default(), page.error_message.unwrap_or_default()
+                    ),
+                }
+            }
+            Err(e) if attempt < RETRIES && (e.is_timeout() || e.is_connect()) => e.to_string(),
+            Err(e) => return Err(failed(&e)),
+        };
+        println!(
+            "Retrying page {} in {}s after {}",
+            n,
+            delay.as_secs(),
+            retry
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+    unreachable!("the last attempt returns")
+}
+
+// The API compresses every response, whatever the request accepts
+fn decompress(bytes: &[u8]) -> PolarsResult<Vec<u8>> {
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(bytes.to_vec());
+    }
+    let mut out = vec![];
+    flate2::read::GzDecoder::new(bytes).read_to_end(&mut out)?;
+    Ok(out)
+}
@@ -0,0 +1,247 @@
+use crate::{dedup, embeddings, files, lock, meta::Meta, write_dataset};
+use clap::Args;
+use polars::prelude::*;
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct AttachArgs {
+    input: PathBuf,
+    /// Vectors as a 2D float32/float64 array in an .npy or .safetensors file, one row per id
+    vectors: PathBuf,
+    /// Ids of the rows the vectors belong to, one per line in the order of the vectors
+    #[arg(long)]
+    ids: PathBuf,
+    /// Model the vectors were computed with, recorded in the dataset metadata
+    #[arg(long)]
+    model: String,
+    /// Tensor to read from a .safetensors file holding more than one
+    #[arg(long)]
+    tensor: Option<String>,
+    /// Write to this file instead of updating the input in place
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Convert embeddings stored with a foreign dtype (e.g. list[f64]) to list[f32] instead of failing
+    #[arg(long)]
+    cast_embeddings: bool,
+}
+
+// A row-major matrix of vectors
+struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f32>,
+}
+
+fn floats(bytes: &[u8], dtype: &str, path: &Path) -> PolarsResult<Vec<f32>> {
+    Ok(match dtype {
+        "<f4" | "F32" => bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect(),
+        "<f8" | "F64" => bytes
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+            .collect(),
+        _ => {
+            polars_bail!(ComputeError: "{} holds {} values, expected little-endian float32 or float64", path.display(), dtype)
+        }
+    })
+}
+
+fn matrix(shape: &[usize], data: Vec<f32>, path: &Path) -> PolarsResult<Matrix> {
+    let [rows, cols] = shape else {
+        polars_bail!(ShapeMismatch: "{} holds an array of shape {:?}, expected (rows, dimensions)", path.display(), shape);
+    };
+    if data.len() != rows * cols {
+        polars_bail!(ShapeMismatch: "{} is truncated, expected {} values but found {}", path.display(), rows * cols, data.len());
+    }
+    Ok(Matrix {
+        rows: *rows,
+        cols: *cols,
+        data,
+    })
+}
+
+// The header is a Python dict literal, e.g. {'descr': '<f4', 'fortran_order': False, 'shape': (10, 3072), }
+fn npy(bytes: &[u8], path: &Path) -> PolarsResult<Matrix> {
+    let invalid = || polars_err!(ComputeError: "{} is not a valid .npy file", path.display());
+    if bytes.len() < 10 || &bytes[..6] != b"\x93NUMPY" {
+        return Err(invalid());
+    }
+    let (len, start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        _ if bytes.len() >= 12 => (
+            u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize,
+            12,
+        ),
+        _ => return Err(invalid()),
+    };
+    let header = bytes
+        .get(start..start + len)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or_else(invalid)?;
+    let value = |key: &str| {
+        let rest = &header[header.find(&format!("'{}':", key))? + key.len() + 3..];
+        Some(rest.trim_start())
+    };
+
+    if value("fortran_order").is_some_and(|v| v.starts_with("True")) {
+        polars_bail!(ComputeError: "{} is stored in Fortran order, save it in C order", path.display());
+    }
+    let descr = value("descr")
+        .and_then(|v| v.strip_prefix('\'')?.split('\'').next())
+        .ok_or_else(invalid)?;
+    let shape = value("shape")
+        .and_then(|v| v.strip_prefix('(')?.split(')').next())
+        .ok_or_else(invalid)?
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| d.parse().map_err(|_| invalid()))
+        .collect::<PolarsResult<Vec<usize>>>()?;
+
+    matrix(&shape, floats(&bytes[start + len..], descr, path)?, path)
+}
+
+// An 8 byte header length, a JSON header mapping tensor names to dtype, shape and data offsets, then the data
+fn safetensors(bytes: &[u8], tensor: Option<&str>, path: &Path) -> PolarsResult<Matrix> {
+    let invalid =
+        || polars_err!(ComputeError: "{} is not a valid .safetensors file", path.display());
+    let len = u64::from_le_bytes(bytes.get(..8).ok_or_else(invalid)?.try_into().unwrap()) as usize;
+    let header: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_slice(bytes.get(8..8 + len).ok_or_else(invalid)?)
+            .map_err(|_| invalid())?;
+    let mut tensors = header.iter().filter(|(name, _)| *name != "__metadata__");
+    let (name, info) = match tensor {
+        Some(name) => tensors.find(|(n, _)| *n == name).ok_or_else(
+            || polars_err!(ComputeError: "{} has no tensor `{}`", path.display(), name),
+        )?,
+        None => match (tensors.next(), tensors.next()) {
+            (Some(t), None) => t,
+            _ => {
+                polars_bail!(ComputeError: "{} holds several tensors, pick one with --tensor", path.display())
+            }
+        },
+    };
+
+    let dtype = info["dtype"].as_str().ok_or_else(invalid)?;
+    let shape: Vec<usize> = info["shape"]
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|d| d.as_u64().map(|d| d as usize).ok_or_else(invalid))
+        .collect::<PolarsResult<_>>()?;
+    let offsets = info["data_offsets"].as_array().ok_or_else(invalid)?;
+    let (begin, end) = match offsets.as_slice() {
+        [b, e] => (
+            b.as_u64().ok_or_else(invalid)? as usize,
+            e.as_u64().ok_or_else(invalid)? as usize,
+        ),
+        _ => return Err(invalid()),
+    };
+    let data = bytes
+        .get(8 + len + begin..8 + len + end)
+        .ok_or_else(invalid)?;
+    println!("Reading tensor `{}`", name);
+
+    matrix(&shape, floats(data, dtype, path)?, path)
+}
+
+/// Join vectors computed outside of ada onto a dataset by id
+pub fn attach(args: AttachArgs) -> PolarsResult<()> {
+    let output = args.output.as_deref().unwrap_or(&args.input);
+    let _locks = lock::rewrite(&args.input, output)?;
+
+    let bytes = files::read(&args.vectors)?;
+    let matrix = match args.vectors.extension().and_then(|e| e.to_str()) {
+        Some("npy") => npy(&bytes, &args.vectors)?,
+        Some("safetensors") => safetensors(&bytes, args.tensor.as_deref(), &args.vectors)?,
+        _ => {
+            polars_bail!(ComputeError: "{} is neither an .npy nor a .safetensors file", args.vectors.display())
+        }
+    };
+    drop(bytes);
+
+    let ids: Vec<String> = files::read_to_string(&args.ids)?
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(String::from)
+        .collect();
+    if ids.len() != matrix.rows {
+        polars_bail!(ShapeMismatch: "{} lists {} ids but {} holds {} vectors", args.ids.display(), ids.len(), args.vectors.display(), matrix.rows);
+    }
+
+    let lf = LazyFrame::scan_parquet(&args.input, Default::default())?;
+    let schema = lf.schema()?;
+    let embeddings = embeddings(&schema, args.cast_embeddings)?;
+    if schema.get("id") != Some(&DataType::String) {
+        polars_bail!(SchemaMismatch: "dataset ids are not strings, run the migrate subcommand first");
+    }
+
+    // Vectors of another dimension can't be compared with the ones already stored
+    let stored = lf
+        .clone()
+        .select([embeddings.clone().list().len().alias("dimensions")])
+        .filter(col("dimensions").gt(lit(0)))
+        .limit(1)
+        .collect()?;
+    if let Some(dimensions) = stored
+        .column("dimensions")?
+        .u32()?
+        .into_iter()
+        .flatten()
+        .next()
+    {
+        if dimensions as usize != matrix.cols {
+            polars_bail!(ShapeMismatch: "dataset embeddings have {} dimensions but the vectors have {}", dimensions, matrix.cols);
+        }
+    }
+    let mut meta = Meta::load(&args.input)?;
+    if let Some(recorded) = meta.model.as_ref().filter(|&m| *m != args.model) {
+        polars_bail!(ComputeError: "dataset was embedded with `{}`, not `{}`", recorded, args.model);
+    }
+
+    let mut builder = ListPrimitiveChunkedBuilder::<Float32Type>::new(
+        "attached",
+        matrix.rows,
+        matrix.data.len(),
+        DataType::Float32,
+    );
+    for v in matrix.data.chunks_exact(matrix.cols) {
+        builder.append_slice(v);
+    }
+    let attached = DataFrame::new(vec![
+        Series::new("id", &ids),
+        builder.finish().into_series(),
+    ])?;
+
+    let mut lf = lf
+        .left_join(attached.lazy(), col("id"), col("id"))
+        .with_column(coalesce(&[col("attached"), embeddings]).alias("embeddings"));
+    // A duplicate given a vector of its own stops sharing the one of another row
+    if schema.contains(dedup::COLUMN) {
+        lf = lf.with_column(
+            when(col("attached").is_not_null())
+                .then(lit(NULL).cast(DataType::String))
+                .otherwise(col(dedup::COLUMN))
+                .alias(dedup::COLUMN),
+        );
+    }
+    let df = lf.collect()?;
+    let matched = df.column("attached")?.is_not_null().sum().unwrap_or(0) as usize;
+    if matched != ids.len() {
+        polars_bail!(ComputeError: "{} of the ids are not in the dataset", ids.len() - matched);
+    }
+    let mut df = df.drop("attached")?;
+    println!("{}", df);
+
+    write_dataset(&mut df, output)?;
+    meta.model = Some(args.model);
+    meta.endpoint = Some("external".to_string());
+    meta.save(output)?;
+
+    println!("Finished writing, attached {} vectors", matched);
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use crate::{files, manifest};
+use polars::prelude::*;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+    time::Instant,
+};
+
+/// A change to a dataset, one line of its `<dataset>.audit.jsonl` sidecar. Fields are only ever added, so that
+/// tools reading the log keep working.
+#[derive(Serialize)]
+struct Event<'a> {
+    /// When the command finished, in UTC
+    at: String,
+    /// The subcommand, such as `brew` or `patch`
+    operation: &'a str,
+    /// Its arguments as given on the command line
+    parameters: &'a [String],
+    /// The rows it parsed, embedded, patched, deleted or dropped, when it counts them
+    rows: Option<usize>,
+    duration_ms: u128,
+    /// SHA-256 of the dataset before and after, see the manifest
+    previous_sha256: Option<String>,
+    sha256: Option<String>,
+    /// Who ran it, from the environment
+    user: Option<String>,
+    ada: &'static str,
+    /// Why the command failed after changing the dataset, such as a brew aborted halfway
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct Change {
+    written: bool,
+    previous: Option<String>,
+    rows: Option<usize>,
+}
+
+static STARTED: OnceLock<Instant> = OnceLock::new();
+// The subcommand run and the arguments given to it
+static INVOCATION: OnceLock<(String, Vec<String>)> = OnceLock::new();
+// Datasets changed by this run and not logged yet
+static CHANGED: Mutex<BTreeMap<PathBuf, Change>> = Mutex::new(BTreeMap::new());
+
+fn path(dataset: &Path) -> PathBuf {
+    let mut path = dataset.as_os_str().to_owned();
+    path.push(".audit.jsonl");
+    path.into()
+}
+
+/// Start timing the command, `operation` being the name of the subcommand parsed from the command line
+pub fn start(operation: &str) {
+    STARTED.get_or_init(Instant::now);
+    // Global flags such as --no-verify may come before the subcommand, which only ever follows flags
+    let parameters = std::env::args()
+        .skip(1)
+        .skip_while(|arg| arg != operation)
+        .skip(1)
+        .collect();
+    INVOCATION.get_or_init(|| (operation.to_string(), parameters));
+}
+
+/// The arguments given to the subcommand on the command line
+pub fn parameters() -> &'static [String] {
+    INVOCATION.get().map_or(&[], |(_, parameters)| parameters)
+}
+
+/// Note that `dataset` was written over the version of checksum `previous`, see `record`
+pub fn written(dataset: &Path, previous: Option<String>) {
+    let mut changed = CHANGED.lock().unwrap();
+    let change = changed.entry(dataset.to_path_buf()).or_default();
+    // The first write of a run replaced the version it started from
+    if !change.written {
+        change.written = true;
+        change.previous = previous;
+    }
+}
+
+/// Note that the command changed `rows` rows of `dataset`
+pub fn changed(dataset: &Path, rows: usize) {
+    let mut changed = CHANGED.lock().unwrap();
+    changed.entry(dataset.to_path_buf()).or_default().rows = Some(rows);
+}
+
+/// Append an event to the log of every dataset changed so far, `error` if the command failed after changing them
+pub fn record(error: Option<&PolarsError>) -> PolarsResult<()> {
+    let changed = std::mem::take(&mut *CHANGED.lock().unwrap());
+    let duration = STARTED
+        .get()
+        .map_or(0, |started| started.elapsed().as_millis());
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok();
+    for (dataset, change) in changed {
+        // A dataset staged from an object store that failed to write is gone with its changes
+        if !dataset.exists() {
+            continue;
+        }
+        let sha256 = manifest::checksum(&dataset)?;
+        // Sidecars such as the tombstones change, the dataset itself stays the version it was
+        let previous = match change.written {
+            true => change.previous,
+            false => sha256.clone(),
+        };
+        let event = Event {
+            at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            operation: INVOCATION.get().map_or("", |(operation, _)| operation),
+            parameters: parameters(),
+            rows: change.rows,
+            duration_ms: duration,
+            previous_sha256: previous,
+            sha256,
+            user: user.clone(),
+            ada: env!("CARGO_PKG_VERSION"),
+            error: error.map(|e| e.to_string()),
+        };
+        let line = serde_json::to_string(&event)
+            .map_err(|e| polars_err!(ComputeError: "failed to log the change: {}", e))?;
+        let path = path(&dataset);
+        let mut file = std::fs::File::options()
+            .create(true)
+            .append(true)
+            .open(files::long(&path))
+            .map_err(|e| files::with_path(e, &path))?;
+        file.write_all(format!("{}\n", line).as_bytes())
+            .map_err(|e| files::with_path(e, &path))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use crate::{files, tokens::content_hash};
+use polars::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// Content-addressed store of original documents, each kept once under `<dir>/<hash[..2]>/<hash>`
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new(dir: impl AsRef<Path>) -> PolarsResult<Self> {
+        files::create_dir_all(&dir)?;
+        // Stored in the dataset metadata, so it must resolve from any working directory
+        let dir = std::fs::canonicalize(&dir).map_err(|e| files::with_path(e, dir.as_ref()))?;
+        Ok(Self { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Store `content` unless it is already present, returning its hash
+    pub fn put(&self, content: &str) -> PolarsResult<String> {
+        let hash = content_hash(content);
+        let path = self.dir.join(&hash[..2]).join(&hash);
+        if !path.exists() {
+            files::write(&path, content)?;
+        }
+        Ok(hash)
+    }
+}
+
+/// Path of the original document of each row, from the `blob` hash column of a dataset stored in `dir`
+pub fn original(dir: &str) -> Expr {
+    let sep = std::path::MAIN_SEPARATOR_STR;
+    (lit(format!("{}{}", dir, sep))
+        + col("blob").str().slice(lit(0), lit(2))
+        + lit(sep)
+        + col("blob"))
+    .alias("original")
+}
@@ -0,0 +1,182 @@
+use clap::Args;
+use polars::prelude::*;
+use regex::Regex;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+};
+
+// How many lines at either end of a body are considered for boilerplate
+const EDGE_LINES: usize = 5;
+
+#[derive(Args)]
+pub struct BoilerplateArgs {
+    /// Regex whose matches are removed from bodies before embedding, may be repeated. Remembered for the corpus in its metadata
+    #[arg(long)]
+    strip_pattern: Vec<String>,
+    /// Strip leading and trailing lines that this many bodies share, ignoring case, whitespace and digits.
+    /// Remembered for the corpus in its metadata
+    #[arg(long, value_name = "COUNT")]
+    strip_repeated: Option<usize>,
+}
+
+// Signatures and footers differ in dates, counters and spacing, so compare lines by their shape
+fn normalize(line: &str) -> String {
+    line.split_whitespace()
+        .map(|w| {
+            w.chars()
+                .map(|c| if c.is_ascii_digit() { '0' } else { c })
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn lines(text: &str) -> impl DoubleEndedIterator<Item = &str> {
+    text.lines().filter(|l| !l.trim().is_empty())
+}
+
+// Lines found within EDGE_LINES of an end of at least `min_count` bodies
+fn repeated<'a>(
+    bodies: impl Iterator<Item = &'a str>,
+    edge: impl Fn(&'a str) -> Vec<&'a str>,
+    min_count: usize,
+) -> HashSet<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for body in bodies {
+        let seen: HashSet<String> = edge(body).into_iter().map(normalize).collect();
+        for line in seen {
+            *counts.entry(line).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count >= min_count)
+        .map(|(line, _)| line)
+        .collect()
+}
+
+impl BoilerplateArgs {
+    /// Patterns given on the command line in addition to the ones stored for the corpus
+    pub fn patterns(&self, stored: &[String]) -> Vec<String> {
+        let mut patterns = stored.to_vec();
+        for p in &self.strip_pattern {
+            if !patterns.contains(p) {
+                patterns.push(p.clone());
+            }
+        }
+        patterns
+    }
+
+    /// The threshold given on the command line, else the one stored for the corpus
+    pub fn repeated(&self, stored: Option<usize>) -> Option<usize> {
+        self.strip_repeated.or(stored)
+    }
+
+    /// Returns `None` if there is nothing to strip, the bodies in `lf` are only read to detect lines repeated by
+    /// `strip_repeated` bodies
+    pub fn stripper(
+        &self,
+        patterns: &[String],
+        strip_repeated: Option<usize>,
+        lf: LazyFrame,
+    ) -> PolarsResult<Option<Stripper>> {
+        if patterns.is_empty() && strip_repeated.is_none() {
+            return Ok(None);
+        }
+        let patterns = patterns
+            .iter()
+            .map(|p| {
+                Regex::new(p).map_err(|e| polars_err!(ComputeError: "invalid strip pattern: {}", e))
+            })
+            .collect::<PolarsResult<_>>()?;
+        let (heads, tails) = match strip_repeated {
+            Some(min_count) => {
+                let df = lf.select([col("body")]).collect()?;
+                let bodies = df.column("body")?.str()?;
+                (
+                    repeated(
+                        bodies.into_iter().flatten(),
+                        |b| lines(b).take(EDGE_LINES).collect(),
+                        min_count,
+                    ),
+                    repeated(
+                        bodies.into_iter().flatten(),
+                        |b| lines(b).rev().take(EDGE_LINES).collect(),
+                        min_count,
+                    ),
+                )
+            }
+            None => Default::default(),
+        };
+        if heads.len() + tails.len() > 0 {
+            println!(
+                "Stripping {} leading and {} trailing boilerplate lines",
+                heads.len(),
+                tails.len()
+            );
+        }
+        Ok(Some(Stripper {
+            patterns,
+            heads,
+            tails,
+        }))
+    }
+}
+
+pub struct Stripper {
+    patterns: Vec<Regex>,
+    heads: HashSet<String>,
+    tails: HashSet<String>,
+}
+
+impl Stripper {
+    pub fn strip<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        if !self.heads.is_empty() || !self.tails.is_empty() {
+            // Blank lines are kept, they break the text into paragraphs
+            let all: Vec<&str> = text.lines().collect();
+            let content: Vec<usize> = (0..all.len())
+                .filter(|&i| !all[i].trim().is_empty())
+                .collect();
+            let start = content
+                .iter()
+                .take(EDGE_LINES)
+                .take_while(|&&i| self.heads.contains(&normalize(all[i])))
+                .count();
+            let end = content[start..]
+                .iter()
+                .rev()
+                .take(EDGE_LINES)
+                .take_while(|&&i| self.tails.contains(&normalize(all[i])))
+                .count();
+            if start + end > 0 {
+                let dropped: HashSet<usize> = content[..start]
+                    .iter()
+                    .chain(&content[content.len() - end..])
+                    .copied()
+                    .collect();
+                let kept: Vec<&str> = (0..all.len())
+                    .filter(|i| !dropped.contains(i))
+                    .map(|i| all[i])
+                    .collect();
+                text = Cow::Owned(kept.join("\n"));
+            }
+        }
+        self.patterns
+            .iter()
+            .fold(text, |text, re| match re.replace_all(&text, "") {
+                Cow::Borrowed(_) => text,
+                Cow::Owned(s) => Cow::Owned(s),
+            })
+    }
+
+    /// Apply the stripper to a string expression
+    pub fn expr(self: Arc<Self>, expr: Expr) -> Expr {
+        expr.map(
+            move |c| Ok(Some(c.str()?.apply_values(|t| self.strip(t)).into_series())),
+            GetOutput::same_type(),
+        )
+    }
+}
@@ -0,0 +1,210 @@
+use polars::{lazy::dsl::GetOutput, prelude::*};
+
+/// Parse a boost added to the similarity of every result, an arithmetic expression over the numeric columns
+/// of the dataset such as `0.05 * log1p(score) + 0.01 * answer_count`. It has `+ - * /`, parentheses and the
+/// functions `log`, `log10`, `log1p`, `exp`, `sqrt`, `abs`, `min(a, b)` and `max(a, b)`. Missing values count
+/// as 0, and so does a boost that is not a finite number, such as the log of 0.
+pub fn parse(text: &str, schema: &Schema) -> PolarsResult<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        at: 0,
+        schema,
+        text,
+    };
+    let expr = parser.sum()?;
+    if let Some(token) = parser.tokens.get(parser.at) {
+        polars_bail!(ComputeError: "unexpected `{}` in the boost `{}`", token, text);
+    }
+    Ok(apply(expr, |v| if v.is_finite() { v } else { 0.0 }))
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Symbol(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> PolarsResult<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                // An exponent such as 1e-3 takes its sign along
+                let signed = (c == '-' || c == '+') && text[..i].ends_with(['e', 'E']);
+                if !(c.is_ascii_alphanumeric() || c == '.' || signed) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = &text[start..end];
+            let number = number.parse().map_err(
+                |_| polars_err!(ComputeError: "invalid number `{}` in the boost `{}`", number, text),
+            )?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Name(text[start..end].to_string()));
+        } else if "+-*/(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            polars_bail!(ComputeError: "unexpected `{}` in the boost `{}`", c, text);
+        }
+    }
+    Ok(tokens)
+}
+
+fn apply(expr: Expr, f: fn(f64) -> f64) -> Expr {
+    expr.map(
+        move |s| Ok(Some(s.f64()?.apply_values(f).into_series())),
+        GetOutput::from_type(DataType::Float64),
+    )
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    at: usize,
+    schema: &'a Schema,
+    text: &'a str,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.tokens.get(self.at) == Some(&Token::Symbol(symbol));
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: char) -> PolarsResult<()> {
+        if !self.eat(symbol) {
+            polars_bail!(ComputeError: "expected `{}` in the boost `{}`", symbol, self.text);
+        }
+        Ok(())
+    }
+
+    // Terms added or subtracted
+    fn sum(&mut self) -> PolarsResult<Expr> {
+        let mut expr = self.product()?;
+        loop {
+            if self.eat('+') {
+                expr = expr + self.product()?;
+            } else if self.eat('-') {
+                expr = expr - self.product()?;
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    // Factors multiplied or divided
+    fn product(&mut self) -> PolarsResult<Expr> {
+        let mut expr = self.factor()?;
+        loop {
+            if self.eat('*') {
+                expr = expr * self.factor()?;
+            } else if self.eat('/') {
+                expr = expr / self.factor()?;
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn factor(&mut self) -> PolarsResult<Expr> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(lit(n)),
+            Some(Token::Symbol('-')) => Ok(lit(0.0) - self.factor()?),
+            Some(Token::Symbol('(')) => {
+                let expr = self.sum()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) if self.eat('(') => self.call(&name),
+            Some(Token::Name(name)) => self.column(&name),
+            Some(token) => {
+                polars_bail!(ComputeError: "unexpected `{}` in the boost `{}`", token, self.text)
+            }
+            None => polars_bail!(ComputeError: "the boost `{}` ends too early", self.text),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> PolarsResult<Expr> {
+        let mut args = vec![self.sum()?];
+        while self.eat(',') {
+            args.push(self.sum()?);
+        }
+        self.expect(')')?;
+        let f: fn(f64) -> f64 = match (name, args.len()) {
+            ("min" | "max", 2) => {
+                let (a, b) = (args[0].clone(), args[1].clone());
+                let smaller = a.clone().lt(b.clone());
+                return Ok(match name {
+                    "min" => when(smaller).then(a).otherwise(b),
+                    _ => when(smaller).then(b).otherwise(a),
+                });
+            }
+            ("log", 1) => f64::ln,
+            ("log10", 1) => f64::log10,
+            ("log1p", 1) => f64::ln_1p,
+            ("exp", 1) => f64::exp,
+            ("sqrt", 1) => f64::sqrt,
+            ("abs", 1) => f64::abs,
+            ("min" | "max" | "log" | "log10" | "log1p" | "exp" | "sqrt" | "abs", n) => {
+                polars_bail!(ComputeError: "{} takes {} arguments, not {}, in the boost `{}`", name, if name.starts_with('m') { 2 } else { 1 }, n, self.text)
+            }
+            _ => {
+                polars_bail!(ComputeError: "unknown function `{}` in the boost `{}`", name, self.text)
+            }
+        };
+        Ok(apply(args.pop().unwrap().cast(DataType::Float64), f))
+    }
+
+    fn column(&self, name: &str) -> PolarsResult<Expr> {
+        // The similarity is computed by search rather than stored
+        if name == "similarity" {
+            return Ok(col(name));
+        }
+        match self.schema.get(name) {
+            Some(dtype) if dtype.is_numeric() || *dtype == DataType::Boolean => {
+                Ok(col(name).cast(DataType::Float64).fill_null(lit(0.0)))
+            }
+            Some(dtype) => {
+                polars_bail!(ComputeError: "the column `{}` of the boost is {}, not a number", name, dtype)
+            }
+            None => {
+                polars_bail!(ColumnNotFound: "the boost `{}` refers to `{}`, which the dataset has no column of", self.text, name)
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    centroids::normalize, cite, dedup, dot, embeddings, lock, meta::Meta, quality::percentile,
+    routes, tags, tombstones, vectors,
+};
+use clap::Args;
+use polars::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct CanonicalArgs {
+    input: PathBuf,
+    /// Tag to find the canonical questions of, without the angle brackets
+    #[arg(long)]
+    tag: String,
+    /// Number of questions to show
+    #[arg(short, default_value_t = 20)]
+    n: usize,
+    /// Weight of the post score against the closeness to the tag centroid, from 0 to 1
+    #[arg(long, default_value_t = 0.5)]
+    score_weight: f64,
+    /// Convert embeddings stored with a foreign dtype (e.g. list[f64]) to list[f32] instead of failing
+    #[arg(long)]
+    cast_embeddings: bool,
+    /// Print the questions as citations in this format instead of a table, with their access date
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    export: Option<cite::Export>,
+}
+
+/// Rank the questions of a tag by their centrality within the tag crossed with their score
+pub fn canonical(args: CanonicalArgs) -> PolarsResult<()> {
+    if !(0.0..=1.0).contains(&args.score_weight) {
+        polars_bail!(ComputeError: "--score-weight must be between 0 and 1, got {}", args.score_weight);
+    }
+
+    let _lock = lock::Lock::shared(&args.input)?;
+    let lf = tombstones::live(
+        &args.input,
+        dedup::resolve(LazyFrame::scan_parquet(&args.input, Default::default())?)?,
+    )?;
+    let schema = lf.schema()?;
+    tags::check(lf.clone(), [args.tag.as_str()], "--tag")?;
+    let embeddings = embeddings(&schema, args.cast_embeddings)?;
+    let meta = Meta::load(&args.input)?;
+    let mut selected = vec![
+        meta.url(&schema).alias("id"),
+        col("title"),
+        col("score"),
+        embeddings.clone().alias("embeddings"),
+    ];
+    if args.export.is_some() {
+        selected.extend(cite::columns(&schema));
+    }
+    let lf = lf.filter(tags::any(&schema, [args.tag.as_str()]));
+    routes::single_model(lf.clone(), embeddings.clone(), &meta, "canonical")?;
+    let mut df = lf
+        .select(selected)
+        .filter(col("embeddings").is_not_null())
+        .collect()?;
+    if df.height() == 0 {
+        polars_bail!(NoData: "no embedded questions tagged <{}>", args.tag);
+    }
+
+    let vectors: Vec<Vec<f32>> = vectors(&df, "embeddings")?.into_iter().flatten().collect();
+    let mut centroid = vec![0.0; vectors[0].len()];
+    for v in &vectors {
+        centroid.iter_mut().zip(v).for_each(|(c, x)| *c += x);
+    }
+    normalize(&mut centroid);
+    let centrality: Vec<Option<f64>> = vectors
+        .iter()
+        .map(|v| Some(dot(v, &centroid) as f64))
+        .collect();
+    let score: Vec<Option<f64>> = df
+        .column("score")?
+        .cast(&DataType::Float64)?
+        .f64()?
+        .into_iter()
+        .collect();
+
+    // A weighted geometric mean of the percentile ranks, so that a question has to do well on both
+    let w = args.score_weight;
+    let rank: Float64Chunked = percentile(&centrality)
+        .into_iter()
+        .zip(percentile(&score))
+        .map(|(c, s)| Some(c?.powf(1.0 - w) * s.unwrap_or(0.0).powf(w)))
+        .collect();
+    df.with_column(Series::new("centrality", centrality))?;
+    df.with_column(rank.into_series().with_name("rank"))?;
+
+    std::env::set_var("POLARS_FMT_MAX_ROWS", args.n.to_string());
+    std::env::set_var("POLARS_FMT_STR_LEN", "50");
+
+    let df = df
+        .drop("embeddings")?
+        .sort(["rank"], true, true)?
+        .head(Some(args.n));
+    match args.export {
+        Some(export) => print!("{}", cite::entries(&df, export)?),
+        None => println!("{}", df),
+    }
+
+    Ok(())
+}
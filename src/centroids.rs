@@ -0,0 +1,106 @@
+use crate::{dedup, dot, embeddings, files, lock, meta::Meta, routes, tags, tombstones, vectors};
+use clap::Args;
+use polars::prelude::*;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Args)]
+pub struct CentroidsArgs {
+    input: PathBuf,
+    output: PathBuf,
+    /// Tags with fewer embedded questions are left out
+    #[arg(long, default_value_t = 1)]
+    min_count: usize,
+    /// Convert embeddings stored with a foreign dtype (e.g. list[f64]) to list[f32] instead of failing
+    #[arg(long)]
+    cast_embeddings: bool,
+}
+
+pub fn normalize(v: &mut [f32]) {
+    let norm = dot(v, v).sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// Compute the normalized mean embedding of every tag
+pub fn centroids(args: CentroidsArgs) -> PolarsResult<()> {
+    let _lock = lock::Lock::shared(&args.input)?;
+    let lf = tombstones::live(
+        &args.input,
+        dedup::resolve(LazyFrame::scan_parquet(&args.input, Default::default())?)?,
+    )?;
+    let schema = lf.schema()?;
+    let embeddings = embeddings(&schema, args.cast_embeddings)?;
+    let meta = Meta::load(&args.input)?;
+    let model = routes::single_model(lf.clone(), embeddings.clone(), &meta, "centroids")?;
+    let df = lf
+        .select([
+            tags::column(&schema).alias("tags"),
+            embeddings.alias("embeddings"),
+        ])
+        .filter(col("embeddings").is_not_null())
+        .collect()?;
+
+    let mut sums: BTreeMap<String, (usize, Vec<f32>)> = BTreeMap::new();
+    for (tags, v) in df
+        .column("tags")?
+        .list()?
+        .into_iter()
+        .zip(vectors(&df, "embeddings")?)
+    {
+        let (Some(tags), Some(v)) = (tags, v) else {
+            continue;
+        };
+        for tag in tags.str()?.into_iter().flatten() {
+            let (count, sum) = sums
+                .entry(tag.to_string())
+                .or_insert_with(|| (0, vec![0.0; v.len()]));
+            if sum.len() != v.len() {
+                polars_bail!(ComputeError: "inconsistent embedding dimensions under tag `{}`", tag);
+            }
+            *count += 1;
+            sum.iter_mut().zip(&v).for_each(|(s, x)| *s += x);
+        }
+    }
+
+    let (mut tags, mut counts, mut centroids) = (Vec::new(), Vec::new(), Vec::new());
+    for (tag, (count, mut sum)) in sums.into_iter().filter(|(_, (c, _))| *c >= args.min_count) {
+        normalize(&mut sum);
+        tags.push(tag);
+        counts.push(count as u32);
+        centroids.push(Series::new("", sum));
+    }
+
+    // Recorded so that search only compares them with a query embedded by the same model
+    let models = vec![model; tags.len()];
+    let mut df = df!("tag" => tags, "count" => counts, "centroid" => centroids, "model" => models)?;
+    println!("{}", df);
+
+    files::atomic(&args.output, |file| {
+        ParquetWriter::new(file).finish(&mut df)?;
+        Ok(())
+    })?;
+
+    println!("Finished writing");
+
+    Ok(())
+}
+
+/// Find the tag whose centroid is the closest to the query embedding, embedded by `model`
+pub fn classify(centroids: &Path, query: &[f32], model: Option<&str>) -> PolarsResult<(String, f32)> {
+    let df = ParquetReader::new(files::open(centroids)?).finish()?;
+    // Missing from centroids computed before brew recorded the model of every row
+    if let (Ok(models), Some(model)) = (df.column("model"), model) {
+        if let Some(other) = models.str()?.into_iter().flatten().find(|m| *m != model) {
+            polars_bail!(ComputeError: "the centroids of {} were computed from `{}` embeddings, but the query is embedded with `{}`", centroids.display(), other, model);
+        }
+    }
+    df.column("tag")?
+        .str()?
+        .into_iter()
+        .zip(vectors(&df, "centroid")?)
+        .filter_map(|(tag, v)| Some((tag?.to_string(), dot(&v?, query))))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .ok_or_else(|| polars_err!(NoData: "no centroids in {}", centroids.display()))
+}
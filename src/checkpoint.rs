@@ -0,0 +1,134 @@
+use crate::files;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Questions parsed by parse-xml, flushed every so often into parts in a `<dataset>.checkpoint` directory along
+/// with the position reached in the dumps. A parse that crashes resumes from the last flush on the rerun instead
+/// of from zero.
+pub struct Checkpoint {
+    dir: PathBuf,
+    position: Position,
+    resumed: Position,
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct Position {
+    /// The input as given to parse-xml, a checkpoint of another input is started over
+    input: String,
+    /// Parts written so far, `part-<n>.parquet` for n below this
+    parts: usize,
+    /// The dump being read, in the order of `xml::dumps`
+    dump: usize,
+    /// `<row>`s of that dump read up to the last flush, whatever they turned out to be
+    rows: u64,
+}
+
+impl Checkpoint {
+    fn dir(dataset: &Path) -> PathBuf {
+        let mut path = dataset.as_os_str().to_owned();
+        path.push(".checkpoint");
+        path.into()
+    }
+
+    fn part(&self, n: usize) -> PathBuf {
+        self.dir.join(format!("part-{}.parquet", n))
+    }
+
+    /// The checkpoint of a parse of `input` into `output`, picking up where an interrupted one stopped
+    pub fn open(input: &Path, output: &Path) -> PolarsResult<Self> {
+        let dir = Self::dir(output);
+        let path = dir.join("position.json");
+        let input = input.display().to_string();
+        let found: Option<Position> = match path.exists() {
+            true => Some(serde_json::from_slice(&files::read(&path)?).map_err(
+                |e| polars_err!(ComputeError: "invalid checkpoint {}: {}", path.display(), e),
+            )?),
+            false => None,
+        };
+        let position = match found {
+            Some(position) if position.input == input => {
+                println!(
+                    "Resuming an interrupted parse from row {} of dump {}",
+                    position.rows,
+                    position.dump + 1
+                );
+                position
+            }
+            Some(position) => {
+                println!(
+                    "Starting over, the checkpoint in {} is of {}",
+                    dir.display(),
+                    position.input
+                );
+                Self::clear(output)?;
+                Position {
+                    input,
+                    ..Default::default()
+                }
+            }
+            None => Position {
+                input,
+                ..Default::default()
+            },
+        };
+        Ok(Self {
+            dir,
+            resumed: position.clone(),
+            position,
+        })
+    }
+
+    /// How many `<row>`s of the dump at `index` the interrupted run parsed, none if it was done with the dump
+    pub fn skip(&self, index: usize) -> Option<u64> {
+        match index.cmp(&self.resumed.dump) {
+            std::cmp::Ordering::Less => None,
+            std::cmp::Ordering::Equal => Some(self.resumed.rows),
+            std::cmp::Ordering::Greater => Some(0),
+        }
+    }
+
+    /// Write the questions parsed since the last flush, having read `rows` of the dump at `dump`
+    pub fn flush(&mut self, questions: &mut DataFrame, dump: usize, rows: u64) -> PolarsResult<()> {
+        files::create_dir_all(&self.dir)?;
+        let part = self.part(self.position.parts);
+        files::atomic(&part, |file| {
+            ParquetWriter::new(file).finish(questions)?;
+            Ok(())
+        })?;
+        // The position goes last, a part it doesn't count yet is overwritten by the rerun
+        self.position.parts += 1;
+        self.position.dump = dump;
+        self.position.rows = rows;
+        let position = serde_json::to_vec(&self.position)
+            .map_err(|e| polars_err!(ComputeError: "failed to write the checkpoint: {}", e))?;
+        files::atomic(self.dir.join("position.json"), |file| {
+            use std::io::Write;
+            file.write_all(&position)?;
+            Ok(())
+        })
+    }
+
+    /// The questions flushed by this run and the interrupted one, none if they flushed nothing
+    pub fn parsed(&self) -> PolarsResult<Option<DataFrame>> {
+        let mut parsed: Option<DataFrame> = None;
+        for n in 0..self.position.parts {
+            let df = ParquetReader::new(files::open(self.part(n))?).finish()?;
+            match parsed {
+                Some(ref mut parsed) => {
+                    parsed.vstack_mut(&df)?;
+                }
+                None => parsed = Some(df),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// Drop the checkpoint once its questions are safely stored in `dataset`
+    pub fn clear(dataset: &Path) -> PolarsResult<()> {
+        match std::fs::remove_dir_all(files::long(&Self::dir(dataset))) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use clap::ValueEnum;
+use polars::prelude::*;
+use std::collections::HashSet;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Export {
+    /// `@misc` entries for BibTeX and biblatex
+    Bibtex,
+    /// `ELEC` records for Zotero, EndNote and Mendeley
+    Ris,
+}
+
+/// Columns of the results an entry is made of besides `id`, the link, and `title`
+pub const COLUMNS: [&str; 2] = ["cited_author", "cited_date"];
+
+/// The author name and creation date of every row, named after `COLUMNS`, null where the dataset doesn't have
+/// them. Authors are only named once parse-xml joined Users.xml, otherwise only deleted users keep a name.
+pub fn columns(schema: &Schema) -> [Expr; 2] {
+    let author = match schema.get("author") {
+        Some(DataType::String) => col("author"),
+        _ => lit(NULL).cast(DataType::String),
+    };
+    let date = match schema.get("creation_date") {
+        Some(DataType::Datetime(_, _) | DataType::Date) => {
+            col("creation_date").dt().strftime("%Y-%m-%d")
+        }
+        _ => lit(NULL).cast(DataType::String),
+    };
+    [author.alias(COLUMNS[0]), date.alias(COLUMNS[1])]
+}
+
+// The host of a link, the publisher of the thread
+fn host(url: &str) -> Option<&str> {
+    let rest = url.split_once("://")?.1;
+    let host = rest.split(['/', '?', '#']).next()?;
+    Some(host).filter(|h| !h.is_empty())
+}
+
+// A key made of the site and the last part of the link, e.g. `physics:12345`, unique within the export
+fn key(url: &str, used: &mut HashSet<String>) -> String {
+    let site = host(url)
+        .and_then(|h| h.split('.').next())
+        .filter(|s| *s != "www")
+        .unwrap_or("doc");
+    let last = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or_default();
+    let last: String = last
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let base = format!("{}:{}", site, last.trim_matches('-'));
+    let mut key = base.clone();
+    let mut n = 1;
+    while !used.insert(key.clone()) {
+        n += 1;
+        key = format!("{}-{}", base, n);
+    }
+    key
+}
+
+// Characters BibTeX and LaTeX give a meaning to
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '{' | '}' | '%' | '&' | '$' | '#' | '_' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Format the rows of `df`, with the `id`, `title` and `COLUMNS` columns, as citations accessed today
+pub fn entries(df: &DataFrame, export: Export) -> PolarsResult<String> {
+    let accessed = chrono::Utc::now().date_naive();
+    let urls = df.column("id")?.str()?;
+    let titles = df.column("title")?.str()?;
+    let authors = df.column(COLUMNS[0])?.str()?;
+    let dates = df.column(COLUMNS[1])?.str()?;
+
+    let mut used = HashSet::new();
+    let mut out = String::new();
+    for (((url, title), author), date) in urls.into_iter().zip(titles).zip(authors).zip(dates) {
+        let Some(url) = url else { continue };
+        let title = title.unwrap_or(url);
+        match export {
+            Export::Bibtex => {
+                out.push_str(&format!("@misc{{{},\n", key(url, &mut used)));
+                // Doubled braces keep the capitalization of the title
+                out.push_str(&format!("  title = {{{{{}}}}},\n", escape(title)));
+                if let Some(author) = author {
+                    // Braced as a whole, a display name isn't a first and last name
+                    out.push_str(&format!("  author = {{{{{}}}}},\n", escape(author)));
+                }
+                if let Some(year) = date.and_then(|d| d.get(..4)) {
+                    out.push_str(&format!("  year = {{{}}},\n", year));
+                }
+                if let Some(host) = host(url).filter(|_| !url.starts_with("file:")) {
+                    out.push_str(&format!("  howpublished = {{{}}},\n", escape(host)));
+                }
+                out.push_str(&format!("  url = {{{}}},\n", url));
+                out.push_str(&format!("  urldate = {{{}}},\n", accessed));
+                out.push_str(&format!("  note = {{Accessed {}}}\n}}\n\n", accessed));
+            }
+            Export::Ris => {
+                out.push_str("TY  - ELEC\n");
+                out.push_str(&format!("TI  - {}\n", title));
+                if let Some(author) = author {
+                    out.push_str(&format!("AU  - {}\n", author));
+                }
+                if let Some(date) = date {
+                    out.push_str(&format!("PY  - {}\n", &date[..4.min(date.len())]));
+                    out.push_str(&format!("DA  - {}\n", date.replace('-', "/")));
+                }
+                if let Some(host) = host(url).filter(|_| !url.starts_with("file:")) {
+                    out.push_str(&format!("PB  - {}\n", host));
+                }
+                out.push_str(&format!("UR  - {}\n", url));
+                out.push_str(&format!("Y2  - {}\n", accessed.format("%Y/%m/%d")));
+                out.push_str("ER  - \n\n");
+            }
+        }
+    }
+    Ok(out)
+}
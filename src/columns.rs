@@ -0,0 +1,59 @@
+use crate::dedup;
+use clap::Args;
+use polars::prelude::*;
+use std::path::Path;
+
+#[derive(Args, Clone)]
+pub struct ColumnArgs {
+    /// Read a column of a Parquet file produced elsewhere under the name ada expects, e.g. `--map embeddings=vector`, may be repeated
+    #[arg(long = "map", value_name = "COLUMN=FOREIGN", value_parser = mapping)]
+    map: Vec<(String, String)>,
+}
+
+fn mapping(s: &str) -> Result<(String, String), String> {
+    let (ours, theirs) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected COLUMN=FOREIGN, got `{}`", s))?;
+    // Accept the names other tools commonly use for the same thing
+    let ours = match ours {
+        "embedding" => "embeddings",
+        "text" => "body",
+        ours => ours,
+    };
+    Ok((ours.to_string(), theirs.to_string()))
+}
+
+impl ColumnArgs {
+    /// Scan `path` with the mapped columns renamed, replacing any column already using the name, and the
+    /// embeddings shared among duplicates filled in
+    pub fn scan(&self, path: &Path) -> PolarsResult<LazyFrame> {
+        dedup::resolve(self.scan_stored(path)?)
+    }
+
+    /// Scan `path` like `scan`, leaving the embeddings of duplicates null as they are stored
+    pub fn scan_stored(&self, path: &Path) -> PolarsResult<LazyFrame> {
+        self.rename(LazyFrame::scan_parquet(path, Default::default())?, path)
+    }
+
+    /// Rename the mapped columns of `lf` read from `path`
+    pub fn rename(&self, mut lf: LazyFrame, path: &Path) -> PolarsResult<LazyFrame> {
+        if self.map.is_empty() {
+            return Ok(lf);
+        }
+        let schema = lf.schema()?;
+        for (ours, theirs) in &self.map {
+            if !schema.contains(theirs) {
+                polars_bail!(ColumnNotFound: "{} has no column `{}` to map to `{}`", path.display(), theirs, ours);
+            }
+            if ours != theirs && schema.contains(ours) {
+                lf = lf.drop([ours]);
+            }
+        }
+        let (existing, new): (Vec<_>, Vec<_>) = self
+            .map
+            .iter()
+            .map(|(ours, theirs)| (theirs.as_str(), ours.as_str()))
+            .unzip();
+        Ok(lf.rename(existing, new))
+    }
+}
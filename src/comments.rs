@@ -0,0 +1,36 @@
+use crate::xml;
+use polars::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+/// Join the `n` highest scored comments of every post in `posts` from a Comments.xml dump, best first
+pub fn top(path: &Path, n: usize, posts: &HashSet<&str>) -> PolarsResult<HashMap<String, String>> {
+    // Only the best `n` of every post are held, so the dump is never in memory at once
+    let mut best: HashMap<String, Vec<(i32, String)>> = HashMap::new();
+    xml::rows(path, "Comments.xml", |row| {
+        let (Some(post), Some(text)) = (row.get("PostId"), row.get("Text")) else {
+            return Ok(());
+        };
+        if n == 0 || !posts.contains(post) {
+            return Ok(());
+        }
+        let score = row.get("Score").and_then(|v| v.parse().ok()).unwrap_or(0);
+        let kept = best.entry(post.to_string()).or_default();
+        // Ties keep the earlier comment, which usually came first in the thread
+        let at = kept.partition_point(|(s, _)| *s >= score);
+        if at < n {
+            kept.insert(at, (score, text.trim().to_string()));
+            kept.truncate(n);
+        }
+        Ok(())
+    })?;
+    Ok(best
+        .into_iter()
+        .map(|(post, kept)| {
+            let texts: Vec<_> = kept.into_iter().map(|(_, text)| text).collect();
+            (post, texts.join("\n"))
+        })
+        .collect())
+}
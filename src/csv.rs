@@ -0,0 +1,138 @@
+use crate::{
+    files,
+    jsonl::{self, Documents, Facts},
+    locale::LocaleArgs,
+    scrub::ScrubArgs,
+    store,
+};
+use clap::Args;
+use polars::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct IngestCsvArgs {
+    /// A CSV or TSV file of documents with a header row
+    input: PathBuf,
+    /// The dataset to write, or its object store URL such as s3://bucket/docs.parquet
+    output: PathBuf,
+    /// Column holding the id of a document
+    #[arg(long, value_name = "COLUMN", default_value = "id")]
+    id_col: String,
+    /// Column holding the title [default: title, documents get an empty title when there is none]
+    #[arg(long, value_name = "COLUMN")]
+    title_col: Option<String>,
+    /// Column holding the text
+    #[arg(long, value_name = "COLUMN", default_value = "body")]
+    body_col: String,
+    /// Column holding the tags, as `<tag-a><tag-b>` or `tag-a, tag-b` [default: tags, when there is one]
+    #[arg(long, value_name = "COLUMN")]
+    tags_col: Option<String>,
+    /// Column holding when the document was written, for recency filters and ranking
+    #[arg(long, value_name = "COLUMN")]
+    date_col: Option<String>,
+    /// Column holding the score of the document, such as its votes
+    #[arg(long, value_name = "COLUMN")]
+    score_col: Option<String>,
+    /// Column holding how many times the document was viewed
+    #[arg(long, value_name = "COLUMN")]
+    views_col: Option<String>,
+    #[command(flatten)]
+    locale: LocaleArgs,
+    /// Character between the fields [default: a tab for .tsv and .tab files, a comma otherwise]
+    #[arg(long)]
+    separator: Option<char>,
+    /// Link to a document with `{id}` in place of its id [default: the id itself, for ids that are URLs]
+    #[arg(long, value_name = "TEMPLATE")]
+    url_template: Option<String>,
+    /// Strip HTML from the bodies
+    #[arg(long)]
+    html: bool,
+    #[command(flatten)]
+    scrub: ScrubArgs,
+    /// Overwrite the output instead of merging into it, dropping its embeddings
+    #[arg(long)]
+    replace: bool,
+}
+
+/// Ingest a spreadsheet of documents into a dataset brew and search take like a parsed dump. Every document is
+/// embedded by brew, whatever its tags.
+pub fn ingest(args: IngestCsvArgs) -> PolarsResult<()> {
+    // Ingested into a local copy of the output, to merge into what is already stored
+    if files::remote(&args.output) {
+        let url = args.output.clone();
+        return store::staged(&url, |local| {
+            ingest(IngestCsvArgs {
+                output: local.to_path_buf(),
+                ..args
+            })
+        });
+    }
+    let tsv = args
+        .input
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("tsv") || e.eq_ignore_ascii_case("tab"));
+    let separator = args.separator.unwrap_or(if tsv { '\t' } else { ',' });
+    if !separator.is_ascii() {
+        polars_bail!(ComputeError: "the separator `{}` is not an ASCII character", separator);
+    }
+    // Every field is read as text, so that ids such as 007 keep their zeros
+    let df = CsvReader::new(files::open(&args.input)?)
+        .has_header(true)
+        .with_separator(separator as u8)
+        .infer_schema(Some(0))
+        .finish()?;
+
+    let column = |name: &str, flag: &str| -> PolarsResult<StringChunked> {
+        match df.column(name) {
+            Ok(column) => Ok(column.cast(&DataType::String)?.str()?.clone()),
+            Err(_) => polars_bail!(
+                ColumnNotFound: "{} has no column `{}` for {}, its columns are {}",
+                args.input.display(), name, flag, df.get_column_names().join(", ")
+            ),
+        }
+    };
+    // The title and the tags may be left out unless asked for
+    let optional = |name: &Option<String>, default: &str, flag: &str| match name {
+        Some(name) => column(name, flag).map(Some),
+        None if df.schema().contains(default) => column(default, flag).map(Some),
+        None => Ok(None),
+    };
+    let ids = column(&args.id_col, "--id-col")?;
+    let bodies = column(&args.body_col, "--body-col")?;
+    let titles = optional(&args.title_col, "title", "--title-col")?;
+    let tags = optional(&args.tags_col, "tags", "--tags-col")?;
+    let mapped =
+        |name: &Option<String>, flag: &str| name.as_ref().map(|n| column(n, flag)).transpose();
+    let dates = mapped(&args.date_col, "--date-col")?;
+    let scores = mapped(&args.score_col, "--score-col")?;
+    let views = mapped(&args.views_col, "--views-col")?;
+
+    let mut documents = Documents::new(&args.scrub, args.html)?;
+    let mut blank = 0;
+    for i in 0..df.height() {
+        let (Some(id), Some(body)) = (ids.get(i), bodies.get(i)) else {
+            blank += 1;
+            continue;
+        };
+        let title = titles.as_ref().and_then(|t| t.get(i)).unwrap_or_default();
+        let tags = tags
+            .as_ref()
+            .and_then(|t| t.get(i))
+            .map(jsonl::split_tags)
+            .unwrap_or_default();
+        // Counted from the header as line 1
+        let at = || format!("{}:{}", args.input.display(), i + 2);
+        let facts = Facts::parse(
+            &args.locale,
+            dates.as_ref().and_then(|c| c.get(i)),
+            scores.as_ref().and_then(|c| c.get(i)),
+            views.as_ref().and_then(|c| c.get(i)),
+            &at,
+        )?;
+        documents.push_with(id.trim().to_string(), title.to_string(), body, tags, facts);
+    }
+    if blank > 0 {
+        println!("Skipped {} rows without an id or a body", blank);
+    }
+    documents.write(&args.output, args.url_template, &args.scrub, args.replace)
+}
@@ -0,0 +1,191 @@
+use crate::tokens::content_hash;
+use polars::{lazy::dsl::GetOutput, prelude::*};
+use std::{collections::HashMap, sync::Arc};
+
+/// Column naming the row whose embedding a duplicate shares instead of storing its own, see brew --dedup
+pub const COLUMN: &str = "embedding_of";
+
+// Texts that differ only in case, spacing or punctuation, such as a question cross-posted with its formatting
+// lost, share a key
+fn key(text: &str) -> String {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    content_hash(&words.join(" "))
+}
+
+fn shares(schema: &Schema) -> bool {
+    schema.contains(COLUMN)
+        && matches!(schema.get("embeddings"), Some(DataType::List(inner)) if **inner == DataType::Float32)
+}
+
+/// Fill in the embeddings of the rows sharing the one of another row, leaving datasets without duplicates as
+/// they are
+pub fn resolve(lf: LazyFrame) -> PolarsResult<LazyFrame> {
+    let schema = lf.schema()?;
+    if !shares(&schema) {
+        return Ok(lf);
+    }
+    let targets = lf
+        .clone()
+        .select([col(COLUMN)])
+        .drop_nulls(None)
+        .unique(None, UniqueKeepStrategy::Any)
+        .collect()?;
+    if targets.height() == 0 {
+        return Ok(lf);
+    }
+    let shared = lf
+        .clone()
+        .filter(col("id").is_in(lit(targets.column(COLUMN)?.clone())))
+        .select([col("id"), col("embeddings")])
+        .collect()?;
+    let mut vectors: HashMap<String, Vec<f32>> = HashMap::new();
+    for (id, embedding) in shared
+        .column("id")?
+        .str()?
+        .into_iter()
+        .zip(shared.column("embeddings")?.list()?)
+    {
+        if let (Some(id), Some(embedding)) = (id, embedding) {
+            vectors.insert(
+                id.to_string(),
+                embedding.f32()?.into_iter().flatten().collect(),
+            );
+        }
+    }
+    let vectors = Arc::new(vectors);
+    let shared = col(COLUMN).map(
+        move |s| {
+            let mut builder = ListPrimitiveChunkedBuilder::<Float32Type>::new(
+                "embeddings",
+                s.len(),
+                s.len(),
+                DataType::Float32,
+            );
+            for target in s.str()? {
+                builder.append_opt_slice(target.and_then(|t| vectors.get(t)).map(Vec::as_slice));
+            }
+            Ok(Some(builder.finish().into_series()))
+        },
+        GetOutput::from_type(DataType::List(Box::new(DataType::Float32))),
+    );
+    Ok(lf.with_column(coalesce(&[col("embeddings"), shared]).alias("embeddings")))
+}
+
+/// Decide which rows of `lf`, read as stored, share the embedding of another row instead of storing their own,
+/// as the `embedding_of` column to write. `text` is what brew embeds, and `todo` marks the rows it would embed.
+/// A row keeps sharing as long as its text and the one of the row it shares stay the same, up to case, spacing
+/// and punctuation. With `dedup`, a row to embed whose text is the one of another row shares its embedding too.
+/// Also returns whether any row shares differently than before.
+pub fn assign(lf: LazyFrame, text: Expr, todo: Expr, dedup: bool) -> PolarsResult<(Expr, bool)> {
+    let schema = lf.schema()?;
+    let sharing = match schema.contains(COLUMN) {
+        true => col(COLUMN),
+        false => lit(NULL).cast(DataType::String),
+    };
+    let df = lf
+        .select([
+            col("id").cast(DataType::String),
+            sharing.alias(COLUMN),
+            text.alias("text"),
+            todo.fill_null(lit(false)).alias("todo"),
+            col("embeddings").is_not_null().alias("embedded"),
+        ])
+        .collect()?;
+    let ids = df.column("id")?.str()?;
+    let sharing = df.column(COLUMN)?.str()?;
+    let texts = df.column("text")?.str()?;
+    let todo = df.column("todo")?.bool()?;
+    let embedded = df.column("embedded")?.bool()?;
+    let rows = || {
+        ids.into_iter()
+            .zip(sharing)
+            .zip(texts)
+            .zip(todo)
+            .zip(embedded)
+    };
+
+    // The keys of the rows with an embedding of their own, or about to get one
+    let owners: HashMap<&str, String> = rows()
+        .filter_map(|((((id, sharing), text), todo), embedded)| {
+            let owns = sharing.is_none() && (todo == Some(true) || embedded == Some(true));
+            Some((id?, key(text?))).filter(|_| owns)
+        })
+        .collect();
+    let mut assigned: HashMap<String, String> = HashMap::new();
+    let mut dropped = 0;
+    for ((((id, sharing), text), _), _) in rows() {
+        if let (Some(id), Some(target)) = (id, sharing) {
+            match text.is_some_and(|text| owners.get(target) == Some(&key(text))) {
+                true => {
+                    assigned.insert(id.to_string(), target.to_string());
+                }
+                false => dropped += 1,
+            }
+        }
+    }
+    if dropped > 0 {
+        println!(
+            "{} rows no longer have the text of the row whose embedding they shared, they are embedded again",
+            dropped
+        );
+    }
+
+    let mut duplicates = 0;
+    if dedup {
+        // The rows already embedded are shared first, so that nothing is paid for twice
+        let mut keys: HashMap<String, &str> = HashMap::new();
+        for ((((id, sharing), text), _), embedded) in rows() {
+            if let (Some(id), None, Some(text), Some(true)) = (id, sharing, text, embedded) {
+                keys.entry(key(text)).or_insert(id);
+            }
+        }
+        for ((((id, _), text), todo), _) in rows() {
+            let (Some(id), Some(text), Some(true)) = (id, text, todo) else {
+                continue;
+            };
+            if assigned.contains_key(id) {
+                continue;
+            }
+            match keys.get(key(text).as_str()) {
+                Some(&owner) if owner != id => {
+                    assigned.insert(id.to_string(), owner.to_string());
+                    duplicates += 1;
+                }
+                Some(_) => {}
+                None => {
+                    keys.insert(key(text), id);
+                }
+            }
+        }
+        println!(
+            "Sharing the embeddings of {} duplicates instead of embedding them",
+            duplicates
+        );
+    }
+
+    // A row shared before may now share the embedding of another itself
+    let chained: Vec<(String, String)> = assigned
+        .iter()
+        .filter_map(|(id, target)| assigned.get(target).map(|t| (id.clone(), t.clone())))
+        .collect();
+    assigned.extend(chained);
+    let changed = dropped > 0 || duplicates > 0;
+    let assigned = Arc::new(assigned);
+    let column = col("id").cast(DataType::String).map(
+        move |s| {
+            Ok(Some(
+                s.str()?
+                    .into_iter()
+                    .map(|id| id.and_then(|id| assigned.get(id)).map(String::as_str))
+                    .collect::<StringChunked>()
+                    .into_series(),
+            ))
+        },
+        GetOutput::from_type(DataType::String),
+    );
+    Ok((column, changed))
+}
@@ -0,0 +1,140 @@
+use crate::{dedup, dot, embedding_column, lock, meta::Meta, routes, tombstones, vectors};
+use clap::Args;
+use polars::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+use rayon::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct DriftArgs {
+    input: PathBuf,
+    /// Column holding the embeddings of the current model
+    #[arg(long, default_value = "embeddings")]
+    old: String,
+    /// Column holding the embeddings of the model to migrate to
+    #[arg(long)]
+    new: String,
+    /// Number of corpus rows sampled as queries
+    #[arg(long, default_value_t = 100)]
+    samples: usize,
+    /// Size of the neighbourhoods compared
+    #[arg(short, default_value_t = 10)]
+    k: usize,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Convert embeddings stored with a foreign dtype (e.g. list[f64]) to list[f32] instead of failing
+    #[arg(long)]
+    cast_embeddings: bool,
+}
+
+// Similarity of every other row to the query, in corpus order
+fn scores(vectors: &[Vec<f32>], query: usize) -> Vec<f32> {
+    vectors
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != query)
+        .map(|(_, v)| dot(v, &vectors[query]))
+        .collect()
+}
+
+fn top_k(scores: &[f32], k: usize) -> Vec<usize> {
+    let mut idx: Vec<usize> = (0..scores.len()).collect();
+    idx.sort_unstable_by(|a, b| scores[*b].total_cmp(&scores[*a]));
+    idx.truncate(k);
+    idx
+}
+
+fn ranks(scores: &[f32]) -> Vec<f64> {
+    let mut rank = vec![0.0; scores.len()];
+    for (r, i) in top_k(scores, scores.len()).into_iter().enumerate() {
+        rank[i] = r as f64;
+    }
+    rank
+}
+
+// Spearman's rank correlation of the similarities both models assign to the rest of the corpus
+fn spearman(a: &[f32], b: &[f32]) -> f64 {
+    let n = a.len() as f64;
+    if n < 2.0 {
+        return 1.0;
+    }
+    let d2: f64 = ranks(a)
+        .iter()
+        .zip(ranks(b))
+        .map(|(x, y)| (x - y).powi(2))
+        .sum();
+    1.0 - 6.0 * d2 / (n * (n * n - 1.0))
+}
+
+pub fn drift(args: DriftArgs) -> PolarsResult<()> {
+    let _lock = lock::Lock::shared(&args.input)?;
+    let lf = tombstones::live(
+        &args.input,
+        dedup::resolve(LazyFrame::scan_parquet(&args.input, Default::default())?)?,
+    )?;
+    let schema = lf.schema()?;
+    let old = embedding_column(&schema, &args.old, args.cast_embeddings)?;
+    let new = embedding_column(&schema, &args.new, args.cast_embeddings)?;
+    // Brew only routes rows to other models in `embeddings`, every other column holds the embeddings of one
+    let meta = Meta::load(&args.input)?;
+    for (name, column) in [(&args.old, &old), (&args.new, &new)] {
+        if name == "embeddings" {
+            routes::single_model(lf.clone(), column.clone(), &meta, "drift")?;
+        }
+    }
+
+    let df = lf
+        .select([old.alias("old"), new.alias("new")])
+        .filter(col("old").is_not_null().and(col("new").is_not_null()))
+        .collect()?;
+
+    let old: Vec<Vec<f32>> = vectors(&df, "old")?.into_iter().flatten().collect();
+    let new: Vec<Vec<f32>> = vectors(&df, "new")?.into_iter().flatten().collect();
+    if old.len() <= args.k {
+        polars_bail!(ComputeError: "only {} rows have both embeddings, need more than k = {}", old.len(), args.k);
+    }
+
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let queries = rand::seq::index::sample(&mut rng, old.len(), args.samples.min(old.len()));
+
+    let mut results: Vec<(f64, f64)> = queries
+        .into_vec()
+        .into_par_iter()
+        .map(|q| {
+            let (a, b) = (scores(&old, q), scores(&new, q));
+            let (top_a, top_b) = (top_k(&a, args.k), top_k(&b, args.k));
+            let overlap = top_a.iter().filter(|i| top_b.contains(i)).count() as f64 / args.k as f64;
+            (overlap, spearman(&a, &b))
+        })
+        .collect();
+    results.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+    let n = results.len() as f64;
+    let mean_overlap = results.iter().map(|r| r.0).sum::<f64>() / n;
+    let mean_rho = results.iter().map(|r| r.1).sum::<f64>() / n;
+    let disrupted = results.iter().filter(|r| r.0 < 0.5).count();
+
+    println!(
+        "Compared `{}` against `{}` on {} sampled queries over {} rows",
+        args.old,
+        args.new,
+        results.len(),
+        old.len()
+    );
+    println!(
+        "Neighbourhood overlap@{}: mean {:.3}, median {:.3}, min {:.3}",
+        args.k,
+        mean_overlap,
+        results[results.len() / 2].0,
+        results[0].0
+    );
+    println!("Spearman rank correlation: mean {:.3}", mean_rho);
+    println!(
+        "Queries keeping less than half of their top {}: {} ({:.1}%)",
+        args.k,
+        disrupted,
+        disrupted as f64 / n * 100.0
+    );
+
+    Ok(())
+}
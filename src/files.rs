@@ -0,0 +1,189 @@
+use polars::prelude::*;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+// Name the offending path, an io::Error on its own only says what went wrong
+pub fn with_path(e: io::Error, path: &Path) -> PolarsError {
+    io::Error::new(e.kind(), format!("{}: {}", path.display(), e)).into()
+}
+
+/// Spell out `path` so that Windows accepts it beyond MAX_PATH, a no-op elsewhere
+#[cfg(windows)]
+pub fn long(path: &Path) -> PathBuf {
+    const MAX_PATH: usize = 260;
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    match absolute.to_str() {
+        Some(s) if s.len() >= MAX_PATH && !s.starts_with(r"\\?\") => {
+            match s.strip_prefix(r"\\") {
+                // \\server\share\.. becomes \\?\UNC\server\share\..
+                Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+                None => PathBuf::from(format!(r"\\?\{}", s)),
+            }
+        }
+        _ => absolute,
+    }
+}
+
+#[cfg(not(windows))]
+pub fn long(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+pub fn create_dir_all(path: impl AsRef<Path>) -> PolarsResult<()> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(long(path)).map_err(|e| with_path(e, path))
+}
+
+/// Create the missing parent directories of `path`
+pub fn create_parent(path: impl AsRef<Path>) -> PolarsResult<()> {
+    match path.as_ref().parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(parent) => create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// Create `path` for writing along with any missing parent directories
+pub fn create(path: impl AsRef<Path>) -> PolarsResult<File> {
+    let path = path.as_ref();
+    create_parent(path)?;
+    File::create(long(path)).map_err(|e| with_path(e, path))
+}
+
+// Removes a temporary file left behind by an error or a panic, a no-op once it has been renamed
+struct Temp(PathBuf);
+
+impl Drop for Temp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(long(&self.0));
+    }
+}
+
+/// Write `path` through `write` into a temporary file next to it, renamed over `path` once synced to disk.
+/// A crash or a full disk midway leaves the previous file intact instead of a truncated one
+pub fn atomic(
+    path: impl AsRef<Path>,
+    write: impl FnOnce(&mut File) -> PolarsResult<()>,
+) -> PolarsResult<()> {
+    let path = path.as_ref();
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp = Temp(path.with_file_name(name));
+
+    let mut file = create(&temp.0)?;
+    write(&mut file)?;
+    file.sync_all().map_err(|e| with_path(e, &temp.0))?;
+    std::fs::rename(long(&temp.0), long(path)).map_err(|e| with_path(e, path))?;
+
+    // Persist the rename itself, directories can't be opened for syncing on Windows
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        File::open(parent)
+            .and_then(|dir| dir.sync_all())
+            .map_err(|e| with_path(e, parent))?;
+    }
+    Ok(())
+}
+
+/// SHA-256 of the contents of `path`, read in blocks
+pub fn sha256(path: &Path) -> PolarsResult<String> {
+    let mut file = open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        match file.read(&mut buf).map_err(|e| with_path(e, path))? {
+            0 => break,
+            n => hasher.update(&buf[..n]),
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn open(path: impl AsRef<Path>) -> PolarsResult<File> {
+    let path = path.as_ref();
+    File::open(long(path)).map_err(|e| with_path(e, path))
+}
+
+pub fn read(path: impl AsRef<Path>) -> PolarsResult<Vec<u8>> {
+    let path = path.as_ref();
+    std::fs::read(long(path)).map_err(|e| with_path(e, path))
+}
+
+pub fn read_to_string(path: impl AsRef<Path>) -> PolarsResult<String> {
+    let path = path.as_ref();
+    std::fs::read_to_string(long(path)).map_err(|e| with_path(e, path))
+}
+
+/// Write `contents` to `path` along with any missing parent directories
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> PolarsResult<()> {
+    use std::io::Write;
+    create(&path)?
+        .write_all(contents.as_ref())
+        .map_err(|e| with_path(e, path.as_ref()))
+}
+
+/// The files under the directory `input` with any of `extensions`, in any case, sorted, or `input` itself if it
+/// is a file. Also returns the directory their paths are relative to.
+pub fn find(input: &Path, extensions: &[&str]) -> PolarsResult<(PathBuf, Vec<PathBuf>)> {
+    if !input.is_dir() {
+        let root = input.parent().unwrap_or(Path::new("")).to_path_buf();
+        return Ok((root, vec![input.to_path_buf()]));
+    }
+    let options = glob::MatchOptions {
+        case_sensitive: false,
+        ..Default::default()
+    };
+    let mut found = vec![];
+    for extension in extensions {
+        let pattern = input.join("**").join(format!("*.{}", extension));
+        found.extend(
+            glob::glob_with(&pattern.to_string_lossy(), options)
+                .map_err(|e| polars_err!(ComputeError: "invalid path {}: {}", input.display(), e))?
+                .filter_map(Result::ok),
+        );
+    }
+    found.sort();
+    found.dedup();
+    Ok((input.to_path_buf(), found))
+}
+
+/// Link to the files under `root` with `{id}` in place of their path within it
+pub fn url_template(root: &Path) -> PolarsResult<String> {
+    let root = root.canonicalize().map_err(|e| with_path(e, root))?;
+    Ok(format!(
+        "file://{}/{{id}}",
+        root.to_string_lossy().trim_end_matches('/')
+    ))
+}
+
+/// Whether `path` is the URL of an object store or web server, e.g. s3://bucket/corpus.parquet, that Polars
+/// reads by ranges instead of from disk
+pub fn remote(path: &Path) -> bool {
+    polars::io::is_cloud_url(path)
+}
+
+/// The object at the URL `path`, none if there is no such object
+pub async fn fetch(path: &Path) -> PolarsResult<Option<Vec<u8>>> {
+    let url = path.to_string_lossy();
+    let (location, store) = polars::io::cloud::build_object_store(&url, None).await?;
+    let failed =
+        |e: object_store::Error| polars_err!(ComputeError: "failed to fetch {}: {}", url, e);
+    let object =
+        object_store::path::Path::from_url_path(&location.prefix).map_err(|e| failed(e.into()))?;
+    match store.get(&object).await {
+        Ok(result) => Ok(Some(result.bytes().await.map_err(failed)?.to_vec())),
+        Err(object_store::Error::NotFound { .. }) => Ok(None),
+        Err(e) => Err(failed(e)),
+    }
+}
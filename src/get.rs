@@ -0,0 +1,55 @@
+use crate::{answers, lock, tombstones};
+use clap::Args;
+use polars::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct GetArgs {
+    input: PathBuf,
+    id: String,
+}
+
+/// Print every field of the row with the given id, the body in full
+pub fn get(args: GetArgs) -> PolarsResult<()> {
+    let _lock = lock::Lock::shared(&args.input)?;
+    if tombstones::load(&args.input)?.contains(&args.id) {
+        polars_bail!(ComputeError: "{} was deleted, it is only kept until the dataset is compacted", args.id);
+    }
+    let lf = LazyFrame::scan_parquet(&args.input, Default::default())?;
+
+    // Comparing the column as stored lets the predicate prune row groups by their id statistics
+    let matching = match lf.schema()?.get("id") {
+        Some(DataType::String) => col("id").eq(lit(args.id.clone())),
+        Some(_) => col("id").cast(DataType::String).eq(lit(args.id.clone())),
+        None => polars_bail!(ColumnNotFound: "dataset has no `id` column"),
+    };
+    let df = lf.filter(matching).limit(1).collect()?;
+    if df.height() == 0 {
+        polars_bail!(ComputeError: "no row with id {}", args.id);
+    }
+
+    for s in df.get_columns() {
+        match (s.name(), s.get(0)?) {
+            ("body", _) => {}
+            (name, AnyValue::List(v)) if v.dtype().is_numeric() => {
+                println!("{}: {} dimensions", name, v.len())
+            }
+            (name, _) => println!("{}: {}", name, s.str_value(0)?),
+        }
+    }
+    if let Ok(body) = df.column("body") {
+        println!("\n{}", body.str_value(0)?);
+    }
+    if let Some(answers) = answers::of(&args.input, &args.id)? {
+        for i in 0..answers.height() {
+            println!(
+                "\n--- answer {} (score {})\n\n{}",
+                answers.column("id")?.str_value(i)?,
+                answers.column("score")?.str_value(i)?,
+                answers.column("body")?.str_value(i)?
+            );
+        }
+    }
+
+    Ok(())
+}
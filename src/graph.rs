@@ -0,0 +1,198 @@
+use crate::{dedup, dot, embeddings, files, lock, meta::Meta, routes, tags, tombstones, vectors};
+use clap::{Args, ValueEnum};
+use itertools::Itertools;
+use polars::prelude::*;
+use rayon::prelude::*;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Graphml,
+    Gexf,
+}
+
+#[derive(Args)]
+pub struct GraphArgs {
+    input: PathBuf,
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Number of nearest neighbours linked from every question
+    #[arg(short, default_value_t = 10)]
+    k: usize,
+    #[arg(long, value_enum, default_value_t = Format::Graphml)]
+    format: Format,
+    /// Convert embeddings stored with a foreign dtype (e.g. list[f64]) to list[f32] instead of failing
+    #[arg(long)]
+    cast_embeddings: bool,
+}
+
+/// Exact k nearest neighbours of every vector by dot product, excluding the vector itself
+pub fn knn(vectors: &[Vec<f32>], k: usize) -> Vec<Vec<(usize, f32)>> {
+    vectors
+        .par_iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let mut scores: Vec<(usize, f32)> = vectors
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(j, w)| (j, dot(v, w)))
+                .collect();
+            let k = k.min(scores.len());
+            if k > 0 {
+                scores.select_nth_unstable_by(k - 1, |a, b| b.1.total_cmp(&a.1));
+            }
+            scores.truncate(k);
+            scores.sort_unstable_by(|a, b| b.1.total_cmp(&a.1));
+            scores
+        })
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Export the kNN similarity graph with titles and tags as node attributes
+pub fn graph(args: GraphArgs) -> PolarsResult<()> {
+    let _lock = lock::Lock::shared(&args.input)?;
+    let lf = tombstones::live(
+        &args.input,
+        dedup::resolve(LazyFrame::scan_parquet(&args.input, Default::default())?)?,
+    )?;
+    let schema = lf.schema()?;
+    let embeddings = embeddings(&schema, args.cast_embeddings)?;
+    let meta = Meta::load(&args.input)?;
+    routes::single_model(lf.clone(), embeddings.clone(), &meta, "graph")?;
+    let df = lf
+        .select([
+            col("id").cast(DataType::String),
+            col("title"),
+            tags::column(&schema).alias("tags"),
+            embeddings.alias("embeddings"),
+        ])
+        .filter(col("embeddings").is_not_null())
+        .collect()?;
+
+    let vectors: Vec<Vec<f32>> = vectors(&df, "embeddings")?.into_iter().flatten().collect();
+    let neighbours = knn(&vectors, args.k);
+
+    // Ids can be any string, such as paths or the `Foo#Bar&part=2` of a wiki, escaped like every attribute
+    let ids: Vec<String> = df
+        .column("id")?
+        .str()?
+        .into_iter()
+        .flatten()
+        .map(escape)
+        .collect();
+    let titles = df.column("title")?.str()?;
+    // Semicolon separated as Gephi expects for multi-valued attributes
+    let tags: Vec<String> = df
+        .column("tags")?
+        .list()?
+        .into_iter()
+        .map(|tags| match tags {
+            Some(tags) => Ok(tags.str()?.into_iter().flatten().join(";")),
+            None => Ok(String::new()),
+        })
+        .collect::<PolarsResult<_>>()?;
+
+    let mut w = BufWriter::new(files::create(&args.output)?);
+    let (mut edge, mut nodes) = (0, 0);
+    match args.format {
+        Format::Graphml => {
+            writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(
+                w,
+                r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+            )?;
+            writeln!(
+                w,
+                r#"  <key id="title" for="node" attr.name="title" attr.type="string"/>"#
+            )?;
+            writeln!(
+                w,
+                r#"  <key id="tags" for="node" attr.name="tags" attr.type="string"/>"#
+            )?;
+            writeln!(
+                w,
+                r#"  <key id="weight" for="edge" attr.name="weight" attr.type="double"/>"#
+            )?;
+            writeln!(w, r#"  <graph id="G" edgedefault="directed">"#)?;
+            for (i, id) in ids.iter().enumerate() {
+                writeln!(w, r#"    <node id="{}">"#, id)?;
+                writeln!(
+                    w,
+                    r#"      <data key="title">{}</data>"#,
+                    escape(titles.get(i).unwrap_or_default())
+                )?;
+                writeln!(w, r#"      <data key="tags">{}</data>"#, escape(&tags[i]))?;
+                writeln!(w, "    </node>")?;
+                nodes += 1;
+            }
+            for (i, ns) in neighbours.iter().enumerate() {
+                for (j, score) in ns {
+                    writeln!(
+                        w,
+                        r#"    <edge id="e{}" source="{}" target="{}"><data key="weight">{}</data></edge>"#,
+                        edge, ids[i], ids[*j], score
+                    )?;
+                    edge += 1;
+                }
+            }
+            writeln!(w, "  </graph>")?;
+            writeln!(w, "</graphml>")?;
+        }
+        Format::Gexf => {
+            writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+            writeln!(w, r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#)?;
+            writeln!(w, r#"  <graph defaultedgetype="directed">"#)?;
+            writeln!(w, r#"    <attributes class="node">"#)?;
+            writeln!(
+                w,
+                r#"      <attribute id="tags" title="tags" type="string"/>"#
+            )?;
+            writeln!(w, "    </attributes>")?;
+            writeln!(w, "    <nodes>")?;
+            for (i, id) in ids.iter().enumerate() {
+                writeln!(
+                    w,
+                    r#"      <node id="{}" label="{}"><attvalues><attvalue for="tags" value="{}"/></attvalues></node>"#,
+                    id,
+                    escape(titles.get(i).unwrap_or_default()),
+                    escape(&tags[i])
+                )?;
+                nodes += 1;
+            }
+            writeln!(w, "    </nodes>")?;
+            writeln!(w, "    <edges>")?;
+            for (i, ns) in neighbours.iter().enumerate() {
+                for (j, score) in ns {
+                    writeln!(
+                        w,
+                        r#"      <edge id="{}" source="{}" target="{}" weight="{}"/>"#,
+                        edge, ids[i], ids[*j], score
+                    )?;
+                    edge += 1;
+                }
+            }
+            writeln!(w, "    </edges>")?;
+            writeln!(w, "  </graph>")?;
+            writeln!(w, "</gexf>")?;
+        }
+    }
+    w.flush()?;
+
+    println!(
+        "Wrote {} nodes and {} edges to {}",
+        nodes,
+        edge,
+        args.output.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use crate::{files, tokens::content_hash};
+use polars::prelude::*;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Embeddings appended as they arrive during a brew, keyed by content hash and model.
+/// A brew that crashes before writing its output picks them up on the rerun instead of paying for them again.
+pub struct Journal {
+    model: String,
+    done: HashMap<String, Vec<f32>>,
+    file: Mutex<File>,
+}
+
+impl Journal {
+    fn path(dataset: impl AsRef<Path>) -> PathBuf {
+        let mut path = dataset.as_ref().as_os_str().to_owned();
+        path.push(".journal.jsonl");
+        path.into()
+    }
+
+    /// Load the entries for `model` left by earlier runs on either dataset, and append new ones to the journal of `output`
+    pub fn open(input: &Path, output: &Path, model: &str) -> PolarsResult<Self> {
+        let mut done = HashMap::new();
+        for dataset in [input, output] {
+            let Ok(file) = File::open(files::long(&Self::path(dataset))) else {
+                continue;
+            };
+            for line in BufReader::new(file).lines() {
+                // The last line may be cut short by the crash the journal exists for
+                let Ok(serde_json::Value::Array(entry)) = serde_json::from_str(&line?) else {
+                    continue;
+                };
+                if let [hash, m, embedding] = entry.as_slice() {
+                    if m.as_str() == Some(model) {
+                        let embedding = serde_json::from_value(embedding.clone()).ok();
+                        if let (Some(hash), Some(embedding)) = (hash.as_str(), embedding) {
+                            done.insert(hash.to_string(), embedding);
+                        }
+                    }
+                }
+            }
+        }
+        if !done.is_empty() {
+            println!("Reusing {} embeddings from an interrupted brew", done.len());
+        }
+        let path = Self::path(output);
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(files::long(&path))
+            .map_err(|e| files::with_path(e, &path))?;
+        Ok(Self {
+            model: model.to_string(),
+            done,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn get(&self, text: &str) -> Option<&[f32]> {
+        self.done.get(&content_hash(text)).map(|v| v.as_slice())
+    }
+
+    pub fn record(&self, text: &str, embedding: &[f32]) -> PolarsResult<()> {
+        let line = serde_json::json!([content_hash(text), self.model, embedding]);
+        // A single append, the journals of several models brewed together share the file
+        let line = format!("{}\n", line);
+        self.file.lock().unwrap().write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Drop the journal once its embeddings are safely stored in `dataset`
+    pub fn clear(dataset: &Path) -> PolarsResult<()> {
+        match std::fs::remove_file(files::long(&Self::path(dataset))) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use polars::prelude::*;
+use serde_json::{Map, Number, Value};
+use std::io::Write;
+
+fn value(v: AnyValue) -> Value {
+    match v {
+        AnyValue::Null => Value::Null,
+        AnyValue::Boolean(b) => Value::Bool(b),
+        AnyValue::String(s) => Value::String(s.to_string()),
+        AnyValue::StringOwned(s) => Value::String(s.to_string()),
+        AnyValue::Float32(f) => Number::from_f64(f as f64).map_or(Value::Null, Value::Number),
+        AnyValue::Float64(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        AnyValue::List(s) => Value::Array(s.iter().map(value).collect()),
+        v if v.is_signed_integer() => v.extract::<i64>().map_or(Value::Null, Value::from),
+        v if v.is_unsigned_integer() => v.extract::<u64>().map_or(Value::Null, Value::from),
+        v => Value::String(v.to_string()),
+    }
+}
+
+/// Write the frame as a JSON array of row objects
+pub fn write_records(df: &DataFrame, writer: impl Write) -> PolarsResult<()> {
+    let names = df.get_column_names();
+    let rows: Vec<Value> = (0..df.height())
+        .map(|i| {
+            let row = df.get_row(i)?;
+            Ok(Value::Object(
+                names
+                    .iter()
+                    .zip(row.0)
+                    .map(|(name, v)| (name.to_string(), value(v)))
+                    .collect::<Map<_, _>>(),
+            ))
+        })
+        .collect::<PolarsResult<_>>()?;
+    serde_json::to_writer_pretty(writer, &rows).map_err(|e| polars_err!(ComputeError: "{}", e))
+}
@@ -0,0 +1,406 @@
+use crate::{
+    answers, audit, files,
+    locale::LocaleArgs,
+    lock, manifest, merge,
+    meta::Meta,
+    scrub::{ScrubArgs, Scrubber},
+    store, tags, write_dataset,
+};
+use clap::Args;
+use polars::prelude::*;
+use serde_json::Value;
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+};
+
+#[derive(Args)]
+pub struct IngestJsonlArgs {
+    /// A file of one JSON document per line
+    input: PathBuf,
+    /// The dataset to write, or its object store URL such as s3://bucket/docs.parquet
+    output: PathBuf,
+    /// Field holding the id of a document, a dotted path such as `meta.id` for nested objects
+    #[arg(long, value_name = "FIELD", default_value = "id")]
+    id: String,
+    /// Field holding the title, documents without one get an empty title
+    #[arg(long, value_name = "FIELD", default_value = "title")]
+    title: String,
+    /// Field holding the text
+    #[arg(long, value_name = "FIELD", default_value = "body")]
+    body: String,
+    /// Field holding the tags, an array or a string of `<tag-a><tag-b>` or `tag-a, tag-b`
+    #[arg(long, value_name = "FIELD", default_value = "tags")]
+    tags: String,
+    /// Field holding when the document was written, a date string or Unix seconds, for recency filters and ranking
+    #[arg(long, value_name = "FIELD")]
+    date: Option<String>,
+    /// Field holding the score of the document, such as its votes
+    #[arg(long, value_name = "FIELD")]
+    score: Option<String>,
+    /// Field holding how many times the document was viewed
+    #[arg(long, value_name = "FIELD")]
+    views: Option<String>,
+    #[command(flatten)]
+    locale: LocaleArgs,
+    /// Link to a document with `{id}` in place of its id [default: the id itself, for ids that are URLs]
+    #[arg(long, value_name = "TEMPLATE")]
+    url_template: Option<String>,
+    /// Strip HTML from the bodies
+    #[arg(long)]
+    html: bool,
+    #[command(flatten)]
+    scrub: ScrubArgs,
+    /// Overwrite the output instead of merging into it, dropping its embeddings
+    #[arg(long)]
+    replace: bool,
+}
+
+// The value at a dotted path of `document`
+fn field<'a>(document: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(document, |value, key| value.get(key))
+        .filter(|value| !value.is_null())
+}
+
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn tag_list(value: &Value) -> Option<Vec<String>> {
+    match value {
+        Value::Array(tags) => tags.iter().map(text).collect(),
+        Value::String(s) => Some(split_tags(s)),
+        _ => None,
+    }
+}
+
+/// The tags of a string of `<tag-a><tag-b>` or `tag-a, tag-b`
+pub fn split_tags(s: &str) -> Vec<String> {
+    match s.contains('<') {
+        true => tags::split(s).map(String::from).collect(),
+        false => s
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(String::from)
+            .collect(),
+    }
+}
+
+/// What is known of a document besides its text, stored in the `creation_date`, `score` and `view_count`
+/// columns parse-xml writes for questions
+#[derive(Default)]
+pub struct Facts {
+    /// In UTC
+    pub creation_date: Option<chrono::NaiveDateTime>,
+    pub score: Option<i32>,
+    pub view_count: Option<u32>,
+}
+
+impl Facts {
+    /// The facts written as text in fields mapped to them, read the way `locale` says. `at` names the document
+    /// in errors.
+    pub fn parse(
+        locale: &LocaleArgs,
+        date: Option<&str>,
+        score: Option<&str>,
+        views: Option<&str>,
+        at: &dyn Fn() -> String,
+    ) -> PolarsResult<Self> {
+        let number = |name: &str, s: &str| match locale.number(s) {
+            Some(n) => Ok(n.round()),
+            None => {
+                polars_bail!(ComputeError: "the {} `{}` at {} is not a number, see --locale", name, s, at())
+            }
+        };
+        let creation_date = date
+            .filter(|s| !s.trim().is_empty())
+            .map(|s| match locale.date(s) {
+                Some(date) => Ok(date),
+                None => polars_bail!(ComputeError: "the date `{}` at {} has no known format, see --locale and --date-format", s, at()),
+            })
+            .transpose()?;
+        let score = score.filter(|s| !s.trim().is_empty());
+        let views = views.filter(|s| !s.trim().is_empty());
+        Ok(Self {
+            creation_date,
+            score: score
+                .map(|s| number("score", s))
+                .transpose()?
+                .map(|n| n as i32),
+            view_count: views
+                .map(|s| number("view count", s))
+                .transpose()?
+                .map(|n| n.max(0.0) as u32),
+        })
+    }
+}
+
+/// Documents gathered by an ingest subcommand, cleaned the way it was asked to
+pub struct Documents {
+    scrubber: Option<Scrubber>,
+    html: bool,
+    seen: HashSet<String>,
+    // The tags of the documents, and how many have none
+    tagged: BTreeSet<String>,
+    untagged: usize,
+    duplicates: usize,
+    ids: Vec<String>,
+    titles: Vec<String>,
+    bodies: Vec<String>,
+    tags: Vec<Series>,
+    facts: Vec<Facts>,
+}
+
+impl Documents {
+    pub fn new(scrub: &ScrubArgs, html: bool) -> PolarsResult<Self> {
+        Ok(Self {
+            scrubber: scrub.scrubber()?,
+            html,
+            seen: HashSet::new(),
+            tagged: BTreeSet::new(),
+            untagged: 0,
+            duplicates: 0,
+            ids: vec![],
+            titles: vec![],
+            bodies: vec![],
+            tags: vec![],
+            facts: vec![],
+        })
+    }
+
+    /// Add a document, unless one of the same id was added before: the first is kept, as merge keeps ids unique
+    pub fn push(&mut self, id: String, title: String, body: &str, tags: Vec<String>) {
+        self.push_with(id, title, body, tags, Facts::default())
+    }
+
+    /// Add a document like `push`, along with what is known of it
+    pub fn push_with(
+        &mut self,
+        id: String,
+        title: String,
+        body: &str,
+        tags: Vec<String>,
+        facts: Facts,
+    ) {
+        if !self.seen.insert(id.clone()) {
+            self.duplicates += 1;
+            return;
+        }
+        let body = match self.scrubber {
+            Some(ref scrubber) => scrubber.scrub(body),
+            None => body.into(),
+        };
+        let body = match self.html {
+            true => voca_rs::strip::strip_tags(&body),
+            false => body.into_owned(),
+        };
+        match tags.is_empty() {
+            true => self.untagged += 1,
+            false => self.tagged.extend(tags.iter().cloned()),
+        }
+        self.ids.push(id);
+        self.titles.push(title);
+        self.bodies.push(body.trim().to_string());
+        self.tags.push(Series::new("", tags));
+        self.facts.push(facts);
+    }
+
+    /// Write the documents to `output`, merging into it unless `replace`. Brew embeds every one of them, whatever
+    /// its tags, and they link to `url_template`, by default the id itself for ids that are URLs.
+    pub fn write(
+        self,
+        output: &Path,
+        url_template: Option<String>,
+        scrub: &ScrubArgs,
+        replace: bool,
+    ) -> PolarsResult<()> {
+        if self.duplicates > 0 {
+            println!(
+                "Skipped {} documents repeating the id of an earlier one",
+                self.duplicates
+            );
+        }
+        let url_template = url_template.unwrap_or_else(|| "{id}".into());
+        if !url_template.contains("{id}") {
+            polars_bail!(ComputeError: "URL template `{}` has no `{{id}}` placeholder", url_template);
+        }
+
+        let _lock = lock::Lock::exclusive(output)?;
+        let merging = !replace && output.exists();
+        let mut meta = match merging {
+            true => {
+                manifest::verify(output)?;
+                Meta::load(output)?
+            }
+            false => Meta::default(),
+        };
+        // Merged into a corpus of another source, the documents keep its links for theirs
+        if !merging {
+            meta.site = None;
+            meta.url_template = Some(url_template.clone());
+        }
+        let urls: Vec<String> = self
+            .ids
+            .iter()
+            .map(|id| url_template.replace("{id}", id))
+            .collect();
+        meta.scrub = Some(meta.scrub.take().unwrap_or_default().union(scrub));
+        // Merged into a corpus that brew only embeds some tags of, such as a parsed dump, the documents are
+        // embedded by their tags too
+        meta.brew_tags = match (merging, meta.brew_tags.take()) {
+            (false, _) => Some(vec![]),
+            (true, Some(tags)) if tags.is_empty() => Some(tags),
+            (true, tags) => {
+                let mut tags: BTreeSet<String> = tags
+                    .unwrap_or_else(|| crate::TAGS.iter().map(|t| t.to_string()).collect())
+                    .into_iter()
+                    .collect();
+                tags.extend(self.tagged);
+                if self.untagged > 0 {
+                    println!(
+                        "Warning: brew only embeds some tags of {}, it skips the {} documents without tags",
+                        output.display(),
+                        self.untagged
+                    );
+                }
+                Some(tags.into_iter().collect())
+            }
+        };
+
+        let embeddings = vec![None::<Series>; self.ids.len()];
+        let mut df = df!(
+            "id" => self.ids,
+            "title" => self.titles,
+            "body" => self.bodies,
+            "tags" => self.tags,
+            "url" => urls,
+            "embeddings" => embeddings
+        )?;
+        // Only the facts some document has, a column of nulls would hide the one of a corpus merged into
+        let facts = &self.facts;
+        if facts.iter().any(|f| f.creation_date.is_some()) {
+            let dates: Vec<_> = facts.iter().map(|f| f.creation_date).collect();
+            df.with_column(Series::new("creation_date", dates))?;
+        }
+        if facts.iter().any(|f| f.score.is_some()) {
+            let scores: Vec<_> = facts.iter().map(|f| f.score).collect();
+            df.with_column(Series::new("score", scores))?;
+        }
+        if facts.iter().any(|f| f.view_count.is_some()) {
+            let views: Vec<_> = facts.iter().map(|f| f.view_count).collect();
+            df.with_column(Series::new("view_count", views))?;
+        }
+        println!("{}", df);
+        audit::changed(output, df.height());
+
+        let mut df = match merging {
+            true => merge::upsert(df, output)?,
+            false => df,
+        };
+        write_dataset(&mut df, output)?;
+        meta.save(output)?;
+        // The answers of an earlier parse belong to questions that are gone
+        if !merging {
+            answers::Answers::clear(output)?;
+        }
+
+        println!("Finished writing");
+
+        Ok(())
+    }
+}
+
+/// Ingest a collection of arbitrary documents into a dataset brew and search take like a parsed dump. Every
+/// document is embedded by brew, whatever its tags.
+pub fn ingest(args: IngestJsonlArgs) -> PolarsResult<()> {
+    // Ingested into a local copy of the output, to merge into what is already stored
+    if files::remote(&args.output) {
+        let url = args.output.clone();
+        return store::staged(&url, |local| {
+            ingest(IngestJsonlArgs {
+                output: local.to_path_buf(),
+                ..args
+            })
+        });
+    }
+    let mut documents = Documents::new(&args.scrub, args.html)?;
+    let input = files::read_to_string(&args.input)?;
+    for (n, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let at = || format!("{}:{}", args.input.display(), n + 1);
+        let document: Value = serde_json::from_str(line)
+            .map_err(|e| polars_err!(ComputeError: "invalid JSON at {}: {}", at(), e))?;
+        let get = |name: &str, path: &str| -> PolarsResult<Option<String>> {
+            match field(&document, path) {
+                Some(value) => match text(value) {
+                    Some(text) => Ok(Some(text)),
+                    None => {
+                        polars_bail!(ComputeError: "the {} `{}` at {} is not a string", name, path, at())
+                    }
+                },
+                None => Ok(None),
+            }
+        };
+        let Some(id) = get("id", &args.id)? else {
+            polars_bail!(ComputeError: "no id `{}` at {}", args.id, at());
+        };
+        let Some(body) = get("body", &args.body)? else {
+            polars_bail!(ComputeError: "no body `{}` at {}", args.body, at());
+        };
+        let tags = match field(&document, &args.tags) {
+            Some(value) => match tag_list(value) {
+                Some(tags) => tags,
+                None => {
+                    polars_bail!(ComputeError: "the tags `{}` at {} are neither an array nor a string", args.tags, at())
+                }
+            },
+            None => vec![],
+        };
+        let title = get("title", &args.title)?.unwrap_or_default();
+        // Numbers are read as JSON writes them whatever the locale, dates as Unix seconds like the API of
+        // Stack Exchange gives them
+        let number = |path: &Option<String>| {
+            let value = field(&document, path.as_deref()?)?;
+            value.as_f64().map(|n| (value, n))
+        };
+        let written = |name: &str, path: &Option<String>| match (path, number(path)) {
+            (Some(path), None) => get(name, path),
+            _ => Ok(None),
+        };
+        let mut facts = Facts::parse(
+            &args.locale,
+            written("date", &args.date)?.as_deref(),
+            written("score", &args.score)?.as_deref(),
+            written("views", &args.views)?.as_deref(),
+            &at,
+        )?;
+        if let Some((value, _)) = number(&args.date) {
+            let date = value
+                .as_i64()
+                .and_then(|n| chrono::DateTime::from_timestamp(n, 0));
+            match date {
+                Some(date) => facts.creation_date = Some(date.naive_utc()),
+                None => {
+                    polars_bail!(ComputeError: "the date {} at {} is not a time in Unix seconds", value, at())
+                }
+            }
+        }
+        if let Some((_, n)) = number(&args.score) {
+            facts.score = Some(n.round() as i32);
+        }
+        if let Some((_, n)) = number(&args.views) {
+            facts.view_count = Some(n.round().max(0.0) as u32);
+        }
+        documents.push_with(id, title, &body, tags, facts);
+    }
+    documents.write(&args.output, args.url_template, &args.scrub, args.replace)
+}
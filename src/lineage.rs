@@ -0,0 +1,201 @@
+use crate::{audit, files, manifest, meta::Meta};
+use clap::Args;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[derive(Args)]
+pub struct LineageArgs {
+    dataset: PathBuf,
+}
+
+/// How a version of a dataset was produced, one line of the catalog
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    dataset: PathBuf,
+    sha256: String,
+    /// When it was written, in UTC
+    at: String,
+    /// The ada command line that wrote it
+    command: Vec<String>,
+    /// The embedding models of its columns once written, by column
+    models: BTreeMap<String, String>,
+    /// Files it was made from that ada didn't write, such as dumps, with their size in bytes
+    sources: Vec<(PathBuf, u64)>,
+    /// Datasets it was made from, as the versions they were then
+    parents: Vec<(PathBuf, String)>,
+}
+
+// Datasets written by this run, catalogued once it succeeds and their metadata is saved too
+static WRITTEN: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// The catalog of every dataset written on this machine, `ADA_CATALOG` or `~/.ada/catalog.jsonl`
+fn catalog() -> PathBuf {
+    if let Some(path) = std::env::var_os("ADA_CATALOG") {
+        return path.into();
+    }
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    PathBuf::from(home.unwrap_or_default())
+        .join(".ada")
+        .join("catalog.jsonl")
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn load() -> PolarsResult<Vec<Entry>> {
+    let path = catalog();
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    // A line cut short by a crash is skipped rather than hiding the rest
+    Ok(files::read_to_string(&path)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Note that `dataset` was written, see `record`
+pub fn written(dataset: &Path) {
+    WRITTEN.lock().unwrap().push(dataset.to_path_buf());
+}
+
+/// Catalog the datasets written by this run. Every path on the command line that is a dataset ada wrote is a
+/// parent, and every other file a source.
+pub fn record() -> PolarsResult<()> {
+    let written: Vec<PathBuf> = std::mem::take(&mut *WRITTEN.lock().unwrap());
+    if written.is_empty() {
+        return Ok(());
+    }
+    let command: Vec<String> = std::env::args().collect();
+    let arguments: Vec<PathBuf> = audit::parameters()
+        .iter()
+        .map(|arg| match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => value,
+            _ => arg.as_str(),
+        })
+        .map(|arg| canonical(Path::new(arg)))
+        .filter(|path| path.is_file())
+        .collect();
+    let entries = load()?;
+
+    let mut lines = String::new();
+    let mut seen = HashSet::new();
+    for dataset in written {
+        let dataset = canonical(&dataset);
+        let Some(sha256) = manifest::checksum(&dataset)? else {
+            continue;
+        };
+        if !seen.insert(dataset.clone()) {
+            continue;
+        }
+        let (mut sources, mut parents) = (vec![], vec![]);
+        for path in &arguments {
+            if *path == dataset {
+                // Rewritten in place, the parent is the version catalogued before
+                let previous = entries.iter().rev().find(|e| e.dataset == dataset);
+                if let Some(previous) = previous {
+                    parents.push((dataset.clone(), previous.sha256.clone()));
+                }
+            } else if let Some(sha256) = manifest::checksum(path)? {
+                parents.push((path.clone(), sha256));
+            } else {
+                let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+                sources.push((path.clone(), size));
+            }
+        }
+        let meta = Meta::load(&dataset)?;
+        let mut models = meta.models.clone();
+        if let Some(model) = meta.model {
+            models.insert("embeddings".to_string(), model);
+        }
+        let entry = Entry {
+            dataset,
+            sha256,
+            at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            command: command.clone(),
+            models,
+            sources,
+            parents,
+        };
+        let line = serde_json::to_string(&entry)
+            .map_err(|e| polars_err!(ComputeError: "failed to catalog: {}", e))?;
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+
+    let path = catalog();
+    files::create_parent(&path)?;
+    let mut file = std::fs::File::options()
+        .create(true)
+        .append(true)
+        .open(files::long(&path))
+        .map_err(|e| files::with_path(e, &path))?;
+    file.write_all(lines.as_bytes())
+        .map_err(|e| files::with_path(e, &path))
+}
+
+/// Print how `dataset` was produced, and how each dataset it was made from was in turn
+pub fn lineage(args: LineageArgs) -> PolarsResult<()> {
+    let entries = load()?;
+    let dataset = canonical(&args.dataset);
+    let current = manifest::checksum(&dataset)?;
+    let entry = entries
+        .iter()
+        .rev()
+        .filter(|e| e.dataset == dataset)
+        .find(|e| current.is_none() || current.as_ref() == Some(&e.sha256));
+    let Some(entry) = entry else {
+        polars_bail!(ComputeError: "{} is not in the catalog {}, it was written by another machine or before ada kept one", dataset.display(), catalog().display());
+    };
+    let mut shown = HashSet::new();
+    show(&entries, entry, 0, &mut shown);
+    Ok(())
+}
+
+fn show<'a>(entries: &'a [Entry], entry: &'a Entry, depth: usize, shown: &mut HashSet<&'a str>) {
+    let indent = "  ".repeat(depth);
+    println!(
+        "{}{} ({})",
+        indent,
+        entry.dataset.display(),
+        &entry.sha256[..12.min(entry.sha256.len())]
+    );
+    if !shown.insert(&entry.sha256) {
+        println!("{}  (shown above)", indent);
+        return;
+    }
+    println!(
+        "{}  written {} by: {}",
+        indent,
+        entry.at,
+        entry.command.join(" ")
+    );
+    for (column, model) in &entry.models {
+        println!("{}  {} embedded with {}", indent, column, model);
+    }
+    for (source, size) in &entry.sources {
+        println!("{}  from {} ({} bytes)", indent, source.display(), size);
+    }
+    for (parent, sha256) in &entry.parents {
+        match entries
+            .iter()
+            .rev()
+            .find(|e| e.dataset == *parent && e.sha256 == *sha256)
+        {
+            Some(parent) => show(entries, parent, depth + 1, shown),
+            None => println!(
+                "{}  from {} ({}), not in the catalog",
+                indent,
+                parent.display(),
+                &sha256[..12.min(sha256.len())]
+            ),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use crate::xml;
+use polars::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+/// The links of a post to others
+#[derive(Default)]
+pub struct Links {
+    /// The question it was closed as a duplicate of
+    pub duplicate_of: Option<String>,
+    /// The posts it links to, in the order of the dump
+    pub related: Vec<String>,
+}
+
+/// Read the links of every post in `posts` from a PostLinks.xml dump. The posts linked to may not be questions
+/// of the dataset, such as answers or questions scored below the cut
+pub fn load(path: &Path, posts: &HashSet<&str>) -> PolarsResult<HashMap<String, Links>> {
+    let mut links: HashMap<String, Links> = HashMap::new();
+    xml::rows(path, "PostLinks.xml", |row| {
+        let (Some(post), Some(target)) = (row.get("PostId"), row.get("RelatedPostId")) else {
+            return Ok(());
+        };
+        if !posts.contains(post) || post == target {
+            return Ok(());
+        }
+        let links = links.entry(post.to_string()).or_default();
+        match row.get("LinkTypeId") {
+            // A question closed as the duplicate of several keeps the first
+            Some("3") if links.duplicate_of.is_none() => {
+                links.duplicate_of = Some(target.to_string())
+            }
+            Some("3") => {}
+            _ if !links.related.iter().any(|r| r == target) => {
+                links.related.push(target.to_string())
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+    Ok(links)
+}
@@ -0,0 +1,194 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use clap::{Args, ValueEnum};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Locale {
+    /// 1,234.5 and 12/31/2020
+    En,
+    /// 1,234.5 and 31/12/2020
+    EnGb,
+    /// 1.234,5 and 31.12.2020
+    De,
+    /// 1 234,5 and 31/12/2020
+    Fr,
+}
+
+impl Locale {
+    // Separators of thousands and of decimals
+    fn separators(self) -> (&'static [char], char) {
+        match self {
+            Locale::En | Locale::EnGb => (&[','], '.'),
+            Locale::De => (&['.'], ','),
+            // Spaces are often non-breaking, even narrow ones
+            Locale::Fr => (&[' ', '\u{a0}', '\u{202f}'], ','),
+        }
+    }
+
+    fn date_formats(self) -> &'static [&'static str] {
+        match self {
+            Locale::En => &[
+                "%m/%d/%Y %I:%M:%S %p",
+                "%m/%d/%Y %I:%M %p",
+                "%m/%d/%Y %H:%M:%S",
+                "%m/%d/%Y %H:%M",
+                "%m/%d/%Y",
+            ],
+            Locale::EnGb | Locale::Fr => &["%d/%m/%Y %H:%M:%S", "%d/%m/%Y %H:%M", "%d/%m/%Y"],
+            Locale::De => &["%d.%m.%Y %H:%M:%S", "%d.%m.%Y %H:%M", "%d.%m.%Y"],
+        }
+    }
+}
+
+// Dates and times read whatever the locale, with their UTC offset first
+const OFFSET_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f%:z",
+    "%Y-%m-%d %H:%M:%S%.f%:z",
+    "%Y-%m-%dT%H:%M:%S%.f%z",
+];
+const ISO_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d",
+];
+
+#[derive(Args, Clone)]
+pub struct LocaleArgs {
+    /// Conventions the numbers and dates of the input are written in. ISO 8601 dates such as 2020-12-31 are read
+    /// whatever the locale
+    #[arg(long, value_enum, default_value_t = Locale::En)]
+    locale: Locale,
+    /// strftime format of the dates, e.g. `%d %b %Y %H:%M`, tried before the ones of the locale
+    #[arg(long, value_name = "FORMAT")]
+    date_format: Option<String>,
+    /// Time zone of the dates written without a UTC offset, e.g. Europe/Berlin. Every date is stored in UTC
+    #[arg(long, value_name = "ZONE", default_value = "UTC", value_parser = zone)]
+    timezone: Tz,
+}
+
+fn zone(s: &str) -> Result<Tz, String> {
+    s.parse().map_err(|_| {
+        format!(
+            "expected a time zone such as UTC or Europe/Berlin, got `{}`",
+            s
+        )
+    })
+}
+
+impl LocaleArgs {
+    /// The number written as `s`, none if it isn't one
+    pub fn number(&self, s: &str) -> Option<f64> {
+        let (thousands, decimal) = self.locale.separators();
+        let s: String = s
+            .trim()
+            .chars()
+            .filter(|c| !thousands.contains(c))
+            .map(|c| if c == decimal { '.' } else { c })
+            .collect();
+        s.parse().ok().filter(|n: &f64| n.is_finite())
+    }
+
+    /// The moment written as `s` in UTC, none if it isn't a date of any known format
+    pub fn date(&self, s: &str) -> Option<NaiveDateTime> {
+        let s = s.trim();
+        if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+            return Some(date.naive_utc());
+        }
+        let custom = self.date_format.as_deref();
+        for format in custom.into_iter().chain(OFFSET_FORMATS.iter().copied()) {
+            if let Ok(date) = DateTime::parse_from_str(s, format) {
+                return Some(date.naive_utc());
+            }
+        }
+        let formats = custom
+            .into_iter()
+            .chain(ISO_FORMATS.iter().copied())
+            .chain(self.locale.date_formats().iter().copied());
+        let local = formats.into_iter().find_map(|format| {
+            NaiveDateTime::parse_from_str(s, format)
+                .or_else(|_| {
+                    NaiveDate::parse_from_str(s, format).map(|d| d.and_time(Default::default()))
+                })
+                .ok()
+        })?;
+        // A time repeated by the change back from daylight saving time is taken the first time round, one
+        // skipped by the change to it is read with the offset that follows
+        let date = match self.timezone.from_local_datetime(&local).earliest() {
+            Some(date) => date.naive_utc(),
+            None => local - self.timezone.offset_from_utc_datetime(&local).fix(),
+        };
+        Some(date)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Locale, LocaleArgs};
+    use chrono::NaiveDateTime;
+    use chrono_tz::{Europe::Berlin, Tz, UTC};
+
+    fn args(locale: Locale, timezone: Tz) -> LocaleArgs {
+        LocaleArgs {
+            locale,
+            date_format: None,
+            timezone,
+        }
+    }
+
+    fn utc(s: &str) -> Option<NaiveDateTime> {
+        Some(NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap())
+    }
+
+    #[test]
+    fn numbers_follow_the_locale() {
+        assert_eq!(args(Locale::En, UTC).number("1,234.5"), Some(1234.5));
+        assert_eq!(args(Locale::De, UTC).number("1.234,5"), Some(1234.5));
+        assert_eq!(args(Locale::Fr, UTC).number("1\u{202f}234,5"), Some(1234.5));
+        assert_eq!(args(Locale::De, UTC).number(" -3 "), Some(-3.0));
+        assert_eq!(args(Locale::En, UTC).number("many"), None);
+        assert_eq!(args(Locale::En, UTC).number("inf"), None);
+    }
+
+    #[test]
+    fn dates_follow_the_locale() {
+        let en = args(Locale::En, UTC);
+        assert_eq!(en.date("12/31/2020 11:00 PM"), utc("2020-12-31 23:00"));
+        assert_eq!(en.date("2020-12-31"), utc("2020-12-31 00:00"));
+        assert_eq!(en.date("31/12/2020"), None);
+        let de = args(Locale::De, UTC);
+        assert_eq!(de.date("31.12.2020 23:30"), utc("2020-12-31 23:30"));
+        assert_eq!(de.date("yesterday"), None);
+    }
+
+    #[test]
+    fn dates_with_an_offset_ignore_the_timezone() {
+        let berlin = args(Locale::En, Berlin);
+        assert_eq!(
+            berlin.date("2020-01-01T10:00:00+02:00"),
+            utc("2020-01-01 08:00")
+        );
+        assert_eq!(berlin.date("2020-01-01T10:00:00Z"), utc("2020-01-01 10:00"));
+    }
+
+    #[test]
+    fn dates_are_normalized_to_utc_across_daylight_saving_time() {
+        let berlin = args(Locale::De, Berlin);
+        // Winter and summer time
+        assert_eq!(berlin.date("31.12.2020 23:30"), utc("2020-12-31 22:30"));
+        assert_eq!(berlin.date("01.07.2021 12:00"), utc("2021-07-01 10:00"));
+        // 02:30 never happened on the night clocks went forward, it is read with the summer offset
+        assert_eq!(berlin.date("2021-03-28 02:30"), utc("2021-03-28 00:30"));
+        // 02:30 happened twice on the night clocks went back, the first time counts
+        assert_eq!(berlin.date("2021-10-31 02:30"), utc("2021-10-31 00:30"));
+        assert_eq!(berlin.date("2021-10-31 03:30"), utc("2021-10-31 02:30"));
+    }
+
+    #[test]
+    fn custom_formats_come_first() {
+        let mut custom = args(Locale::En, UTC);
+        custom.date_format = Some("%d %b %Y %H:%M".into());
+        assert_eq!(custom.date("31 Dec 2020 09:15"), utc("2020-12-31 09:15"));
+    }
+}
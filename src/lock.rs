@@ -0,0 +1,79 @@
+use crate::manifest;
+use fs4::FileExt;
+use polars::prelude::*;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+/// Advisory lock on a dataset, released when dropped.
+/// The lock is taken on a `<dataset>.lock` sidecar rather than the dataset itself, since writers replace the dataset file.
+pub struct Lock {
+    _file: Option<File>,
+}
+
+fn path(dataset: impl AsRef<Path>) -> PathBuf {
+    let mut path = dataset.as_ref().as_os_str().to_owned();
+    path.push(".lock");
+    path.into()
+}
+
+fn open(dataset: &Path) -> PolarsResult<File> {
+    File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(crate::files::long(&path(dataset)))
+        .map_err(|e| polars_err!(ComputeError: "failed to open the lock of {}: {}", dataset.display(), e))
+}
+
+impl Lock {
+    /// Lock `dataset` for reading, waiting for a writer to finish, and verify its files once they are settled.
+    /// Reading needs no write access: an existing lock is opened read-only, and a dataset on a read-only mount
+    /// that has none is read unlocked.
+    pub fn shared(dataset: impl AsRef<Path>) -> PolarsResult<Self> {
+        let dataset = dataset.as_ref();
+        let existing = File::open(crate::files::long(&path(dataset)));
+        let file = match existing.or_else(|_| open(dataset)) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                println!("Warning: reading {} unlocked, {}", dataset.display(), e);
+                None
+            }
+        };
+        if let Some(file) = &file {
+            if file.try_lock_shared().is_err() {
+                println!("Waiting for {} to be written", dataset.display());
+                file.lock_shared()?;
+            }
+        }
+        manifest::verify(dataset)?;
+        Ok(Self { _file: file })
+    }
+
+    /// Lock `dataset` for writing, waiting for readers and other writers to finish
+    pub fn exclusive(dataset: impl AsRef<Path>) -> PolarsResult<Self> {
+        crate::files::create_parent(&dataset)?;
+        let file = open(dataset.as_ref())?;
+        if file.try_lock_exclusive().is_err() {
+            println!("Waiting for {} to be released", dataset.as_ref().display());
+            file.lock_exclusive()?;
+        }
+        Ok(Self { _file: Some(file) })
+    }
+}
+
+/// Lock `output` for writing and `input` for reading, once if they are the same dataset, and verify `input`
+pub fn rewrite(input: impl AsRef<Path>, output: impl AsRef<Path>) -> PolarsResult<Vec<Lock>> {
+    let (input, output) = (input.as_ref(), output.as_ref());
+    let same = match (input.canonicalize(), output.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => input == output,
+    };
+    let mut locks = vec![Lock::exclusive(output)?];
+    match same {
+        true => manifest::verify(input)?,
+        false => locks.push(Lock::shared(input)?),
+    }
+    Ok(locks)
+}
@@ -1,11 +1,99 @@
-use async_openai::{types::CreateEmbeddingRequestArgs, Client};
-use clap::{Parser, Subcommand};
+use async_openai::error::OpenAIError;
+use boilerplate::BoilerplateArgs;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use columns::ColumnArgs;
+use journal::Journal;
+use meta::Meta;
+use pipeline::Pipeline;
+use plugin::PluginArgs;
 use polars::{lazy::dsl::GetOutput, prelude::*};
-use std::path::Path;
+use provider::ProviderArgs;
+use sample::SampleArgs;
+use sanitize::SanitizeArgs;
+use scrub::ScrubArgs;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokens::TokenCache;
+use translate::TranslateArgs;
+
+mod analogize;
+mod answers;
+mod api;
+mod attach;
+mod audit;
+mod blobs;
+mod boilerplate;
+mod boost;
+mod canonical;
+mod centroids;
+mod checkpoint;
+mod cite;
+mod columns;
+mod comments;
+mod csv;
+mod dedup;
+mod drift;
+mod files;
+mod get;
+mod graph;
+mod journal;
+mod json;
+mod jsonl;
+mod lineage;
+mod links;
+mod locale;
+mod lock;
+mod manifest;
+mod markdown;
+mod merge;
+mod meta;
+mod migrate;
+mod models;
+mod notes;
+mod pack;
+mod pairs;
+mod patch;
+mod pdf;
+mod pipeline;
+mod plugin;
+mod provider;
+mod quality;
+mod regress;
+mod routes;
+mod sample;
+mod sanitize;
+mod scrub;
+mod selftest;
+mod snippet;
+mod spell;
+mod split;
+mod store;
+mod synonyms;
+mod tags;
+mod tokens;
+mod tombstones;
+mod trace;
+mod translate;
+mod trends;
+mod users;
+mod validate;
+mod wiki;
+mod xml;
 
 const MAX_TOKEN: usize = 8100;
 const CHUNK_SIZE: usize = 256;
-const TAGS: &str = "<quantum-mechanics>|<statistical-mechanics>|<thermodynamics>|<electromagnetism>|<electrodynamics>";
+// Upper bound of tokens sent by one batch of concurrent requests, matching the tokens-per-minute limit of the API
+const CHUNK_TOKENS: usize = 1_000_000;
+// USD per million tokens of text-embedding-3-large, used for the dry-run estimation
+const PRICE_PER_MTOK: f64 = 0.13;
+const TAGS: &[&str] = &[
+    "quantum-mechanics",
+    "statistical-mechanics",
+    "thermodynamics",
+    "electromagnetism",
+    "electrodynamics",
+];
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -13,247 +101,1780 @@ const TAGS: &str = "<quantum-mechanics>|<statistical-mechanics>|<thermodynamics>
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Open datasets without checking their files against the checksums recorded when they were written
+    #[arg(long, global = true)]
+    no_verify: bool,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Parse and cleanup the XML into Parquet
-    // This should update the parquet file in place incrementally
-    ParseXML { input: String, output: String },
+    /// Parse and cleanup the XML into Parquet, merging into the output if it exists
+    ParseXML(ParseXmlArgs),
     /// Generate the embedding for the given Parquet input
-    // This should update the parquet incrementally
-    Brew { input: String, output: String },
+    Brew(BrewArgs),
     /// Vector search the database with text
-    Search { input: String, text: String },
+    Search(SearchArgs),
+    /// Compare the neighbourhoods of two embedding columns to assess a model migration
+    Drift(drift::DriftArgs),
+    /// Compute the centroid embedding of every tag
+    Centroids(centroids::CentroidsArgs),
+    /// Score the questions on proxy signals of quality
+    Quality(quality::QualityArgs),
+    /// Report how the volume of every tag changes over time
+    Trends(trends::TrendsArgs),
+    /// Export the kNN similarity graph for network analysis tools
+    Graph(graph::GraphArgs),
+    /// Convert a dataset written by an older version to the current schema
+    Migrate(migrate::MigrateArgs),
+    /// Print a single row of the dataset by id
+    Get(get::GetArgs),
+    /// Join embeddings computed outside of ada onto a dataset by id
+    AttachEmbeddings(attach::AttachArgs),
+    /// List the models of a provider, their dimensions and context limits
+    Models(models::ModelsArgs),
+    /// Search with the sum and difference of the embeddings of several terms, e.g. "entropy" - "heat" + "information"
+    Analogize(analogize::AnalogizeArgs),
+    /// Find the reference questions of a tag, close to its centroid and well scored
+    Canonical(canonical::CanonicalArgs),
+    /// Fail if the recall of evaluation queries dropped below a recorded baseline
+    Regress(regress::RegressArgs),
+    /// Bundle a dataset with its sidecars and original documents into one compressed artifact
+    PackRelease(pack::PackArgs),
+    /// Unpack an artifact written by pack-release
+    Unpack(pack::UnpackArgs),
+    /// Hide rows from search and exports by id, until the dataset is compacted
+    Delete(tombstones::DeleteArgs),
+    /// Drop the deleted rows from the dataset for good
+    Compact(tombstones::CompactArgs),
+    /// Correct fields of rows by id from a JSON Lines file, leaving the embeddings of changed text to brew again
+    Patch(patch::PatchArgs),
+    /// Pull the questions of a site from the Stack Exchange API into a dataset, without waiting for a dump
+    IngestApi(api::IngestApiArgs),
+    /// Ingest arbitrary documents from a JSON Lines file, mapping their fields to id, title, body and tags
+    IngestJsonl(jsonl::IngestJsonlArgs),
+    /// Ingest documents from a CSV or TSV file, mapping its columns to id, title, body and tags
+    IngestCsv(csv::IngestCsvArgs),
+    /// Ingest the text of a directory of PDF files such as lecture notes and papers, a row per page or section
+    IngestPdf(pdf::IngestPdfArgs),
+    /// Ingest a directory of Markdown and text files such as personal notes, a row per file
+    IngestDir(notes::IngestDirArgs),
+    /// Ingest the articles of a Wikipedia or other MediaWiki XML dump, a row per section or part of a long one
+    IngestWiki(wiki::IngestWikiArgs),
+    /// Split a dataset into disjoint training and evaluation sets, keeping the share of every tag in both
+    Split(split::SplitArgs),
+    /// Export duplicate and highly similar questions as query–positive pairs to fine-tune an embedding model
+    ExportPairs(pairs::ExportPairsArgs),
+    /// Check the columns, dtypes, embeddings, ids and bodies of a dataset, failing with a report of the problems
+    Validate(validate::ValidateArgs),
+    /// Show how a dataset was produced, from the catalog of every dataset ada wrote
+    Lineage(lineage::LineageArgs),
+    /// Run parse, brew and search on a bundled corpus with a mock provider to check the installation
+    Selftest,
+}
+
+#[derive(Args)]
+struct ParseXmlArgs {
+    /// Posts.xml, or the .7z archive of a Stack Exchange site holding it. A directory or glob of the archives or
+    /// directories of several sites, named after them, stacks them into one dataset with a `site` column and ids
+    /// of `<site>/<id>`
+    input: PathBuf,
+    /// The dataset to write, or its object store URL such as s3://bucket/physics.parquet
+    output: PathBuf,
+    #[command(flatten)]
+    scrub: ScrubArgs,
+    /// Keep the original HTML of every body in a content-addressed store in this directory
+    #[arg(long, value_name = "DIR")]
+    blobs: Option<PathBuf>,
+    /// Also keep the answers to the kept questions, in a `<output>.answers.parquet` sidecar
+    #[arg(long)]
+    answers: bool,
+    /// Stack Exchange site the dump comes from, used to link questions and authors, when it is a single one
+    #[arg(long, default_value = "physics.stackexchange.com")]
+    site: String,
+    /// Link to a question with `{id}` in place of its id, and `{site}` of its site when there are several
+    /// [default: https://<site>/questions/{id}]
+    #[arg(long, value_name = "TEMPLATE")]
+    url_template: Option<String>,
+    /// Comments.xml of the same dump or its .7z archive, to add the best comments of every question in a `comments` column
+    #[arg(long, value_name = "FILE")]
+    comments: Option<PathBuf>,
+    /// How many of the highest scored comments to keep per question
+    #[arg(long, default_value_t = 3, requires = "comments")]
+    max_comments: usize,
+    /// Users.xml of the same dump or its .7z archive, to name every author and add their reputation in a `reputation` column
+    #[arg(long, value_name = "FILE")]
+    users: Option<PathBuf>,
+    /// PostLinks.xml of the same dump or its .7z archive, to add the question every question duplicates in a
+    /// `duplicate_of` column and the posts it links to in a `related_ids` column
+    #[arg(long, value_name = "FILE")]
+    links: Option<PathBuf>,
+    /// Add the body of the answer accepted for every question in an `accepted_answer` column, embedded with it
+    #[arg(long)]
+    accepted_answer: bool,
+    /// Convert bodies to Markdown, keeping code blocks, lists and links, instead of stripping all HTML
+    #[arg(long)]
+    markdown: bool,
+    #[command(flatten)]
+    sanitize: SanitizeArgs,
+    /// Overwrite the output instead of merging into it, dropping its embeddings
+    #[arg(long)]
+    replace: bool,
+    /// Skip questions scored below this, questions kept by an earlier parse stay unless --replace is given
+    #[arg(long, default_value_t = 0, allow_negative_numbers = true)]
+    min_score: i32,
+    /// Skip questions asked before this date, e.g. 2020-01-01 or 2020-01-01T12:00:00 in UTC, questions kept by
+    /// an earlier parse stay unless --replace is given
+    #[arg(long, value_name = "DATE", value_parser = date)]
+    after: Option<chrono::NaiveDateTime>,
+    /// Skip questions asked on or after this date
+    #[arg(long, value_name = "DATE", value_parser = date)]
+    before: Option<chrono::NaiveDateTime>,
+    /// What to do with questions whose id the dumps repeat, such as a site listed twice
+    #[arg(long, value_enum, default_value_t = Duplicates::Last)]
+    duplicates: Duplicates,
+    /// Flush the questions parsed so far to a `<output>.checkpoint` directory every this many questions, and
+    /// resume from the last flush of an interrupted parse of the same input instead of starting over
+    #[arg(long, value_name = "QUESTIONS", conflicts_with_all = ["answers", "accepted_answer", "limit"])]
+    checkpoint: Option<usize>,
+    /// Keep only some of the questions, the first or a random pick of them
+    #[command(flatten)]
+    sample: SampleArgs,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Duplicates {
+    /// Keep the question read last
+    Last,
+    /// Fail without writing anything
+    Error,
+}
+
+#[derive(Args)]
+struct BrewArgs {
+    input: PathBuf,
+    /// The dataset to write, or its object store URL such as s3://bucket/physics.parquet
+    output: PathBuf,
+    /// Abort after this many consecutive provider failures of the same class
+    #[arg(long, default_value_t = 8)]
+    max_failures: usize,
+    /// Only report the rows and tokens that would be embedded
+    #[arg(long)]
+    dry_run: bool,
+    /// Convert embeddings stored with a foreign dtype (e.g. list[f64]) to list[f32] instead of failing
+    #[arg(long)]
+    cast_embeddings: bool,
+    /// Only embed questions whose quality (see the quality subcommand) reaches this value
+    #[arg(long)]
+    min_quality: Option<f32>,
+    /// Embed with each of these models in the same pass, the first into `embeddings` and every other into an
+    /// `embeddings_<model>` column, e.g. `--model text-embedding-3-large --model text-embedding-3-small`
+    #[arg(long = "model", value_name = "MODEL")]
+    models: Vec<String>,
+    /// Embed the rows of some tags with other models into the same column, recording the model of every row in a
+    /// `model` column, from a JSON file of `{"routes": [{"tags": [...], "model": "..."}]}`
+    #[arg(long, value_name = "FILE", conflicts_with = "models")]
+    routes: Option<PathBuf>,
+    /// Embed a text only once among rows that only differ in case, spacing and punctuation, the others naming the
+    /// row whose embedding they share in an `embedding_of` column instead of storing their own
+    #[arg(long, conflicts_with_all = ["models", "routes"])]
+    dedup: bool,
+    /// Stream the dataset through in batches sized to stay roughly within this much memory, e.g. 8G.
+    /// The rows are written in the order of the input instead of being sorted by id
+    #[arg(long, value_name = "SIZE", value_parser = byte_size)]
+    max_memory: Option<usize>,
+    /// Mine the vocabulary of the titles and bodies into a sidecar for search --correct, which otherwise mines it
+    /// on every search
+    #[arg(long)]
+    vocabulary: bool,
+    /// Embed only some of the rows yet to embed, the first or a random pick of them
+    #[command(flatten)]
+    sample: SampleArgs,
+    /// Scrub the text before it is sent to the embedding API
+    #[command(flatten)]
+    scrub: ScrubArgs,
+    /// Strip boilerplate from the bodies before they are sent to the embedding API
+    #[command(flatten)]
+    boilerplate: BoilerplateArgs,
+    /// Prepare the text with a WebAssembly module before it is sent to the embedding API
+    #[command(flatten)]
+    plugin: PluginArgs,
+    #[command(flatten)]
+    provider: ProviderArgs,
+    #[command(flatten)]
+    columns: ColumnArgs,
+}
+
+#[derive(Args)]
+struct SearchArgs {
+    /// The dataset, or its URL such as s3://bucket/physics.parquet or https://example.com/physics.parquet
+    input: PathBuf,
+    text: String,
+    /// Convert embeddings stored with a foreign dtype (e.g. list[f64]) to list[f32] instead of failing
+    #[arg(long)]
+    cast_embeddings: bool,
+    /// Classify the query against the tag centroids in this file and restrict results to the closest tag
+    #[arg(long, value_name = "CENTROIDS")]
+    auto_tag: Option<PathBuf>,
+    /// Boost results carrying the auto-detected tag by this amount instead of restricting to them
+    #[arg(long, requires = "auto_tag")]
+    tag_boost: Option<f64>,
+    /// Add the question quality (see the quality subcommand) times this weight to the similarity
+    #[arg(long)]
+    quality_weight: Option<f64>,
+    /// Add this expression over the numeric columns to the similarity, e.g. `0.05 * log1p(score)`, with `+ - * /`,
+    /// parentheses and the functions log, log10, log1p, exp, sqrt, abs, min and max
+    #[arg(long, value_name = "EXPR", allow_hyphen_values = true)]
+    boost: Option<String>,
+    /// Spell out the aliases listed in this file as `alias = expansion` lines, e.g. `QM = quantum mechanics`
+    #[arg(long, value_name = "FILE")]
+    synonyms: Option<PathBuf>,
+    /// Correct misspelled query words against the vocabulary of the corpus, as mined by brew --vocabulary or else
+    /// on every search
+    #[arg(long)]
+    correct: bool,
+    /// Search even if the dataset metadata can't confirm that the provider serves the same model
+    #[arg(long)]
+    force: bool,
+    /// Show the sentences of every result that best match the query
+    #[arg(long)]
+    show_snippet: bool,
+    /// Retrieve, filter and rerank as described by this JSON file, see `pipeline.rs` for the stages
+    #[arg(long, value_name = "FILE")]
+    pipeline: Option<PathBuf>,
+    /// Write what every stage of the pipeline admitted, removed and scored for the query to this JSON file, up to
+    /// the 1000 most similar or best ranked rows of each
+    #[arg(long, value_name = "FILE")]
+    trace_retrieval: Option<PathBuf>,
+    /// Link results to this Stack Exchange site instead of the one recorded by parse-xml
+    #[arg(long)]
+    site: Option<String>,
+    /// Link results to this prefix followed by their id, e.g. https://stats.stackexchange.com/q/
+    #[arg(long, value_name = "URL")]
+    url_prefix: Option<String>,
+    /// Print the results as citations in this format instead of a table, with their access date
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    export: Option<cite::Export>,
+    #[command(flatten)]
+    provider: ProviderArgs,
+    #[command(flatten)]
+    translate: TranslateArgs,
+    /// Prepare the query with a WebAssembly module, by default the one recorded by brew
+    #[command(flatten)]
+    plugin: PluginArgs,
+    #[command(flatten)]
+    columns: ColumnArgs,
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    manifest::VERIFY.store(!cli.no_verify, std::sync::atomic::Ordering::Relaxed);
+    audit::start(matches.subcommand_name().unwrap_or_default());
 
-    if let Err(e) = match cli.command {
-        Commands::ParseXML { input, output } => parse_xml(input, output),
-        Commands::Brew { input, output } => brew(input, output),
-        Commands::Search { input, text } => search(input, text),
-    } {
+    let result = run(cli.command);
+    // A command that fails after writing, such as an aborted brew, still changed the dataset
+    if let Err(e) = audit::record(result.as_ref().err()) {
+        println!("Warning: failed to log the changes: {}", e);
+    }
+    if let Err(e) = result {
         println!("{}", e);
+        std::process::exit(1);
+    }
+    // The datasets are written already, a catalog that can't be is no reason to fail
+    if let Err(e) = lineage::record() {
+        println!("Warning: failed to record the lineage: {}", e);
     }
 }
 
-#[tokio::main]
-async fn search(input: String, text: String) -> PolarsResult<()> {
-    std::env::set_var("POLARS_FMT_MAX_ROWS", "20");
-    std::env::set_var("POLARS_FMT_STR_LEN", "50");
-
-    let text_embedding = Series::new("embedding", get_embedding(text).await.unwrap());
-
-    let df = LazyFrame::scan_parquet(input, Default::default())?
-        .with_columns([
-            (lit("https://physics.stackexchange.com/questions/")
-                + col("id").cast(DataType::String))
-            .alias("id"),
-            col("embeddings")
-                .map(
-                    move |c| {
-                        Ok(Some(ChunkedArray::<Float64Type>::into_series(
-                            c.list()?
-                                .apply_nonnull_values_generic(DataType::Float64, |e| {
-                                    Series::from_arrow("embedding", e)
-                                        .unwrap()
-                                        .dot(&text_embedding)
-                                        .unwrap()
-                                }),
-                        )))
-                    },
-                    GetOutput::from_type(DataType::Float64),
-                )
-                .alias("score"),
-        ])
-        .sort(
-            "score",
-            SortOptions {
-                descending: true,
-                nulls_last: true,
-                ..Default::default()
-            },
-        )
-        .select([cols(["id", "title", "score"])])
-        .collect()?;
+fn run(command: Commands) -> PolarsResult<()> {
+    match command {
+        Commands::ParseXML(args) => parse_xml(args),
+        Commands::Brew(args) => brew(args),
+        Commands::Search(args) => search(args),
+        Commands::Drift(args) => drift::drift(args),
+        Commands::Centroids(args) => centroids::centroids(args),
+        Commands::Quality(args) => quality::quality(args),
+        Commands::Trends(args) => trends::trends(args),
+        Commands::Graph(args) => graph::graph(args),
+        Commands::Migrate(args) => migrate::migrate(args),
+        Commands::Get(args) => get::get(args),
+        Commands::AttachEmbeddings(args) => attach::attach(args),
+        Commands::Models(args) => models::models(args),
+        Commands::Analogize(args) => analogize::analogize(args),
+        Commands::Canonical(args) => canonical::canonical(args),
+        Commands::Regress(args) => regress::regress(args),
+        Commands::PackRelease(args) => pack::pack(args),
+        Commands::Unpack(args) => pack::unpack(args),
+        Commands::Delete(args) => tombstones::delete(args),
+        Commands::Compact(args) => tombstones::compact(args),
+        Commands::Patch(args) => patch::patch(args),
+        Commands::IngestApi(args) => api::ingest(args),
+        Commands::IngestJsonl(args) => jsonl::ingest(args),
+        Commands::IngestCsv(args) => csv::ingest(args),
+        Commands::IngestPdf(args) => pdf::ingest(args),
+        Commands::IngestDir(args) => notes::ingest(args),
+        Commands::IngestWiki(args) => wiki::ingest(args),
+        Commands::Split(args) => split::split(args),
+        Commands::ExportPairs(args) => pairs::export_pairs(args),
+        Commands::Validate(args) => validate::validate(args),
+        Commands::Lineage(args) => lineage::lineage(args),
+        Commands::Selftest => selftest::selftest(),
+    }
+}
+
+// Embeddings are stored as list[f32] on disk, brew produces them and search scores against them as such
+fn embedding_dtype() -> DataType {
+    DataType::List(Box::new(DataType::Float32))
+}
+
+fn embeddings(schema: &Schema, cast: bool) -> PolarsResult<Expr> {
+    embedding_column(schema, "embeddings", cast)
+}
+
+// Validate the stored embedding dtype up front, as a mismatch otherwise surfaces as a confusing error deep inside Polars
+fn embedding_column(schema: &Schema, name: &str, cast: bool) -> PolarsResult<Expr> {
+    match schema.get(name) {
+        None => polars_bail!(ColumnNotFound: "dataset has no `{}` column", name),
+        // list[null] is what ParseXML writes before anything is brewed
+        Some(DataType::List(inner)) if matches!(**inner, DataType::Float32 | DataType::Null) => {
+            Ok(col(name))
+        }
+        Some(dtype @ (DataType::List(_) | DataType::Array(..))) if cast => {
+            println!("Casting {} from {} to {}", name, dtype, embedding_dtype());
+            Ok(col(name).cast(embedding_dtype()))
+        }
+        Some(dtype) => polars_bail!(
+            SchemaMismatch: "{} are stored as {}, expected {}; pass --cast-embeddings to convert them on read",
+            name, dtype, embedding_dtype()
+        ),
+    }
+}
 
-    println!("{}", df.head(Some(20)));
+// Rows per row group, small enough for the statistics to narrow an id lookup down to a few of them
+const ROW_GROUP_SIZE: usize = 4096;
+
+/// Write a dataset sorted by id, so that `get` can skip row groups based on their statistics
+fn write_dataset(df: &mut DataFrame, path: &Path) -> PolarsResult<()> {
+    if df.schema().contains("id") {
+        df.sort_in_place(["id"], false, true)?;
+    }
+    files::atomic(path, |file| {
+        // Use the default zstd compression
+        ParquetWriter::new(file)
+            .with_statistics(true)
+            .with_row_group_size(Some(ROW_GROUP_SIZE))
+            .finish(df)?;
+        Ok(())
+    })?;
+    manifest::record(path, "")
+}
+
+// Materialize an embedding column for computations outside of Polars
+fn vectors(df: &DataFrame, name: &str) -> PolarsResult<Vec<Option<Vec<f32>>>> {
+    df.column(name)?
+        .list()?
+        .into_iter()
+        .map(|v| {
+            v.map(|s| Ok(s.f32()?.into_iter().flatten().collect()))
+                .transpose()
+        })
+        .collect()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// Results printed by search
+const SHOWN: usize = 20;
+
+fn search(args: SearchArgs) -> PolarsResult<()> {
+    std::env::set_var("POLARS_FMT_MAX_ROWS", SHOWN.to_string());
+    // Leave room for the snippets, which are already cut to a couple of sentences
+    let width = if args.show_snippet { "420" } else { "50" };
+    std::env::set_var("POLARS_FMT_STR_LEN", width);
+
+    match args.export {
+        Some(export) => print!(
+            "{}",
+            cite::entries(&results(args)?.head(Some(SHOWN)), export)?
+        ),
+        None => println!("{}", results(args)?.head(Some(SHOWN))),
+    }
 
     Ok(())
 }
 
-// FIXME: Could use lazy_static etc.
-async fn get_embedding(
-    //    client: &Client<OpenAIConfig>,
-    //    tokenizer: &CoreBPE,
-    input: String,
-) -> Option<Vec<f32>> {
-    let client = Client::new();
-    let tokenizer = tiktoken_rs::cl100k_base().unwrap();
-
-    let token_len = tokenizer.encode_ordinary(&input).len();
-    if token_len > MAX_TOKEN {
-        println!("Token too long, len: {}, prompt: {}", token_len, input);
-        return None;
-    }
-
-    let req = CreateEmbeddingRequestArgs::default()
-        .model("text-embedding-3-large")
-        .input(input)
-        .build()
-        .ok()?;
-
-    Some(
-        client
-            .embeddings()
-            .create(req)
-            .await
-            .map_err(|x| dbg!(x))
-            .ok()?
-            .data
-            .pop()?
-            .embedding,
+/// All rows of the dataset ranked by similarity to the query
+fn results(args: SearchArgs) -> PolarsResult<DataFrame> {
+    let SearchArgs {
+        input,
+        text,
+        cast_embeddings,
+        auto_tag,
+        tag_boost,
+        quality_weight,
+        boost: custom,
+        synonyms,
+        correct,
+        force,
+        show_snippet,
+        pipeline,
+        trace_retrieval,
+        site,
+        url_prefix,
+        export,
+        provider,
+        translate,
+        plugin,
+        columns,
+    } = args;
+
+    let pipeline = match pipeline {
+        Some(path) => Pipeline::load(&path)?,
+        None => Pipeline::default(),
+    };
+    // Polars reads remote datasets on a runtime of its own, which can't be started from within this one
+    let runtime = tokio::runtime::Runtime::new()?;
+    // A shared corpus is read by ranges where it is stored, it can't be locked or verified from here
+    let (_lock, deleted, mut meta) = match files::remote(&input) {
+        true => (
+            None,
+            runtime.block_on(tombstones::fetch(&input))?,
+            runtime.block_on(Meta::fetch(&input))?,
+        ),
+        false => (
+            Some(lock::Lock::shared(&input)?),
+            tombstones::load(&input)?,
+            Meta::load(&input)?,
+        ),
+    };
+    let lf = tombstones::exclude(deleted, columns.scan(&input)?)?;
+    let schema = lf.schema()?;
+    let embeddings = embeddings(&schema, cast_embeddings)?;
+    // Checked before the query is embedded, a typo shouldn't cost a request
+    let custom = custom.map(|b| boost::parse(&b, &schema)).transpose()?;
+    pipeline.check(lf.clone(), &schema)?;
+    if quality_weight.is_some() && !schema.contains("quality") {
+        polars_bail!(ColumnNotFound: "dataset has no `quality` column, run the quality subcommand first");
+    }
+
+    meta.relink(site, url_prefix)?;
+    let text = runtime.block_on(translate.translate(text))?;
+
+    // Before spell correction, which would otherwise take abbreviations for typos
+    let text = match synonyms {
+        Some(path) => {
+            let expanded = synonyms::Synonyms::load(&path)?.expand(&text);
+            if expanded != text {
+                println!("Expanded query: {}", expanded);
+            }
+            expanded
+        }
+        None => text,
+    };
+
+    let text = if correct {
+        let corrected = spell::Speller::load(&input)?.correct(&text);
+        if corrected != text {
+            println!("Searching for: {}", corrected);
+        }
+        corrected
+    } else {
+        text
+    };
+
+    let query = Query::new(&input, &meta, &plugin)?;
+    let text = query.text(text)?;
+    let mut embed =
+        |provider: &ProviderArgs| match runtime.block_on(provider.clone().embed(text.clone())) {
+            Ok((model, Some(embedding))) => Ok((model, embedding)),
+            Ok((_, None)) => polars_bail!(ComputeError: "no embedding returned for the query"),
+            Err(e) => polars_bail!(ComputeError: "failed to embed the query: {}", e),
+        };
+    let text_embedding = query.vector(&meta, &provider, None, force, &mut embed)?;
+
+    let (lf, boost) = match auto_tag {
+        Some(centroids) => {
+            let (tag, similarity) = centroids::classify(&centroids, &text_embedding, meta.model.as_deref())?;
+            println!(
+                "Auto-tagged query as <{}> (similarity {:.3})",
+                tag, similarity
+            );
+            let matching = tags::any(&schema, [tag.as_str()]);
+            match tag_boost {
+                Some(boost) => (lf, when(matching).then(lit(boost)).otherwise(lit(0.0))),
+                None => (lf.filter(matching), lit(0.0)),
+            }
+        }
+        None => (lf, lit(0.0)),
+    };
+    let boost = match quality_weight {
+        Some(weight) => boost + lit(weight) * col("quality").fill_null(lit(0.0)),
+        None => boost,
+    };
+    let boost = match custom {
+        Some(custom) => boost + custom,
+        None => boost,
+    };
+    let pipeline = match trace_retrieval {
+        Some(path) => pipeline.boost(boost).trace(path, &text),
+        None => pipeline.boost(boost),
+    };
+    let similarity = query.similarity(
+        &lf,
+        &meta,
+        &provider,
+        force,
+        embeddings.clone(),
+        text_embedding,
+        &mut embed,
+    )?;
+    let snippet = show_snippet.then_some(text.as_str());
+    ranked(
+        lf,
+        &schema,
+        &meta,
+        embeddings,
+        similarity,
+        snippet,
+        export.is_some(),
+        &pipeline,
     )
 }
 
+/// How a query is prepared to meet the documents of a dataset, redacted, run through a plugin and normalized the
+/// way brew prepared them as recorded in its metadata
+struct Query {
+    scrubber: Option<scrub::Scrubber>,
+    plugin: Option<plugin::Plugin>,
+    normalized: bool,
+}
+
+impl Query {
+    fn new(input: &Path, meta: &Meta, plugin: &PluginArgs) -> PolarsResult<Self> {
+        // Redacted the way the documents were, an email in the query should meet the [EMAIL] in the text
+        let (scrubber, normalized) = match (&meta.scrub, meta.normalized) {
+            (Some(scrub), Some(normalized)) => (scrub.scrubber()?, normalized),
+            _ => {
+                println!(
+                    "Warning: {} doesn't record how its text and vectors were prepared, the query is embedded as is",
+                    input.display()
+                );
+                (None, false)
+            }
+        };
+        Ok(Self {
+            scrubber,
+            plugin: plugin.load(meta.plugin.as_deref(), plugin::Hook::Query)?,
+            normalized,
+        })
+    }
+
+    /// The text to embed for `text`
+    fn text(&self, text: String) -> PolarsResult<String> {
+        let text = match &self.scrubber {
+            Some(scrubber) => scrubber.scrub(&text).into_owned(),
+            None => text,
+        };
+        let text = match &self.plugin {
+            Some(plugin) => plugin.apply(&text)?,
+            None => text,
+        };
+        let token_len = tiktoken_rs::cl100k_base()
+            .unwrap()
+            .encode_ordinary(&text)
+            .len();
+        if token_len > MAX_TOKEN {
+            polars_bail!(ComputeError: "query is too long to embed, len: {}", token_len);
+        }
+        Ok(text)
+    }
+
+    /// The vector `embed` gets from `provider`, checked to come from the model of the dataset, or from `routed`
+    /// for the rows brew embedded with another
+    fn vector(
+        &self,
+        meta: &Meta,
+        provider: &ProviderArgs,
+        routed: Option<&str>,
+        force: bool,
+        embed: &mut impl FnMut(&ProviderArgs) -> PolarsResult<(String, Vec<f32>)>,
+    ) -> PolarsResult<Vec<f32>> {
+        let (served, mut embedding) = embed(provider)?;
+        match routed {
+            Some(model) if served != model && !force => {
+                polars_bail!(ComputeError: "rows were embedded with `{}` but {} serves `{}`, pass --force to use it anyway", model, provider.endpoint(), served)
+            }
+            Some(_) => {}
+            None => meta.check(provider, &served, force)?,
+        }
+        if self.normalized {
+            centroids::normalize(&mut embedding);
+        }
+        Ok(embedding)
+    }
+
+    /// The similarity of every row of `lf` to the query, `vector` as embedded by the model of the dataset. Rows
+    /// that brew routed to another model are compared with the query embedded by theirs.
+    #[allow(clippy::too_many_arguments)]
+    fn similarity(
+        &self,
+        lf: &LazyFrame,
+        meta: &Meta,
+        provider: &ProviderArgs,
+        force: bool,
+        embeddings: Expr,
+        vector: Vec<f32>,
+        embed: &mut impl FnMut(&ProviderArgs) -> PolarsResult<(String, Vec<f32>)>,
+    ) -> PolarsResult<Expr> {
+        let mut similarity = similarity(embeddings.clone(), vector);
+        if !lf.schema()?.contains("model") {
+            return Ok(similarity);
+        }
+        let models = lf.clone().select([col("model").unique()]).collect()?;
+        for model in models.column("model")?.str()?.into_iter().flatten() {
+            if meta.model.as_deref() == Some(model) {
+                continue;
+            }
+            let routed = provider.with_model(model)?;
+            let vector = self.vector(meta, &routed, Some(model), force, embed)?;
+            similarity = when(col("model").eq(lit(model.to_string())))
+                .then(self::similarity(embeddings.clone(), vector))
+                .otherwise(similarity);
+        }
+        Ok(similarity)
+    }
+}
+
+/// Dot product of every embedding with `query`, their cosine similarity as both are unit length
+fn similarity(embeddings: Expr, query: Vec<f32>) -> Expr {
+    let query = Series::new("embedding", query);
+    embeddings.map(
+        move |c| {
+            Ok(Some(ChunkedArray::<Float64Type>::into_series(
+                c.list()?
+                    .apply_nonnull_values_generic(DataType::Float64, |e| {
+                        Series::from_arrow("embedding", e)
+                            .unwrap()
+                            .dot(&query)
+                            .unwrap()
+                    }),
+            )))
+        },
+        GetOutput::from_type(DataType::Float64),
+    )
+}
+
+/// Rank the rows of `lf` by their `similarity` to the query through the stages of `pipeline`, with what a citation
+/// needs when `cited`
+#[allow(clippy::too_many_arguments)]
+fn ranked(
+    lf: LazyFrame,
+    schema: &Schema,
+    meta: &Meta,
+    embeddings: Expr,
+    similarity: Expr,
+    snippet: Option<&str>,
+    cited: bool,
+    pipeline: &Pipeline,
+) -> PolarsResult<DataFrame> {
+    // Point at the original document when the dataset keeps them
+    let mut shown = vec![
+        cols(["id", "title", "similarity"]),
+        cols(["author", "license"]),
+    ];
+    let lf = match meta.blobs {
+        Some(ref dir) if schema.contains("blob") => {
+            shown.push(col("original"));
+            lf.with_column(blobs::original(dir))
+        }
+        _ => lf,
+    };
+
+    let mut linked = vec![
+        meta.url(schema).alias("id"),
+        attribution(schema, meta),
+        license(schema),
+    ];
+    // Read alongside the attribution, which turns the author into a link
+    if cited {
+        linked.extend(cite::columns(schema));
+        shown.push(cols(cite::COLUMNS));
+    }
+    let lf = pipeline
+        .run(
+            lf.with_column(similarity.alias("similarity")),
+            schema,
+            embeddings,
+        )?
+        .with_columns(linked);
+    // Splitting every body into sentences is only worth it for the results that get shown
+    let lf = match snippet {
+        Some(query) => {
+            shown.push(snippet::expr(col("body"), query).alias("snippet"));
+            lf.limit(SHOWN as IdxSize)
+        }
+        None => lf,
+    };
+    lf.select(shown).collect()
+}
+
+// The CC BY-SA license of the content requires crediting the author and the license with every reuse.
+// Datasets parsed before attribution was recorded yield nulls so that they can still be searched.
+fn attribution(schema: &Schema, meta: &Meta) -> Expr {
+    if !schema.contains("author_id") {
+        return lit(NULL).cast(DataType::String).alias("author");
+    }
+    coalesce(&[meta.author_url(), col("author")]).alias("author")
+}
+
+fn license(schema: &Schema) -> Expr {
+    if schema.contains("license") {
+        col("license")
+    } else {
+        lit(NULL).cast(DataType::String).alias("license")
+    }
+}
+
+#[tokio::main]
+async fn probe(provider: ProviderArgs) -> Result<String, OpenAIError> {
+    Ok(provider.embed("probe".to_string()).await?.0)
+}
+
+// Group provider errors so that an outage or a bad key can be told apart from a one-off hiccup
+fn failure_class(e: &OpenAIError) -> String {
+    match e {
+        OpenAIError::ApiError(e) => e
+            .code
+            .as_ref()
+            .map(|c| c.to_string())
+            .or_else(|| e.r#type.clone())
+            .unwrap_or_else(|| "api".to_string()),
+        OpenAIError::Reqwest(e) => e
+            .status()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| "http".to_string()),
+        OpenAIError::JSONDeserialize(_) => "deserialize".to_string(),
+        _ => "client".to_string(),
+    }
+}
+
+// Once `max_failures` consecutive failures of the same class are seen, the reason is stored in `tripped` and no further requests are issued.
+// Rows left out this way stay null so that the next brew picks them up.
 #[tokio::main]
-async fn get_embeddings(series: &mut [Series]) -> PolarsResult<Option<Series>> {
-    use itertools::Itertools;
+async fn get_embeddings(
+    series: &mut [Series],
+    provider: &ProviderArgs,
+    max_failures: usize,
+    tripped: &Mutex<Option<String>>,
+    journal: &Journal,
+) -> PolarsResult<Option<Series>> {
+    use itertools::{izip, Itertools};
 
-    let mut results: Vec<Option<Series>> = Vec::new();
+    let mut results: Vec<Option<Vec<f32>>> = Vec::new();
+    let mut reason = tripped.lock().unwrap().clone();
+    let mut failures: Option<(String, usize)> = None;
 
-    let zipped = series[0].str()?.iter().zip(
+    let mut rows = izip!(
+        series[0].str()?.iter(),
         series[1]
             .bool()?
             .iter()
             .map(|x| x.expect("mask must be non-null")),
-    );
+        series[2].u32()?.iter().map(|x| x.unwrap_or(0) as usize),
+    )
+    .peekable();
 
-    for xs in zipped.chunks(CHUNK_SIZE).into_iter() {
+    while rows.peek().is_some() {
+        if reason.is_some() {
+            results.extend(rows.map(|_| None));
+            break;
+        }
+        // Batch by row count and by the token budget, a single oversized row still makes up a batch on its own
+        let (mut len, mut used) = (0, 0);
+        let xs: Vec<_> = rows
+            .peeking_take_while(|(_, mask, tokens)| {
+                let tokens = if *mask { *tokens } else { 0 };
+                let accept = len < CHUNK_SIZE && (len == 0 || used + tokens <= CHUNK_TOKENS);
+                if accept {
+                    len += 1;
+                    used += tokens;
+                }
+                accept
+            })
+            .collect();
         let handles: Vec<_> = xs
-            .map(|(text, mask)| {
+            .into_iter()
+            .map(|(text, mask, _)| {
                 if mask {
-                    Some(tokio::spawn(get_embedding(text.unwrap().to_string())))
+                    let text = text.unwrap();
+                    Some((text, tokio::spawn(provider.clone().embed(text.to_string()))))
                 } else {
                     None
                 }
             })
             .collect();
         for handle in handles {
-            results.push(if let Some(handle) = handle {
-                handle.await.unwrap().map(|x| Series::new("embedding", x))
-            } else {
-                None
+            results.push(match handle {
+                Some((_, handle)) if reason.is_some() => {
+                    handle.abort();
+                    None
+                }
+                Some((text, handle)) => match handle.await.unwrap() {
+                    Ok((_, mut x)) => {
+                        failures = None;
+                        // Providers such as Ollama don't return unit vectors, the dot product needs them
+                        if let Some(ref mut x) = x {
+                            centroids::normalize(x);
+                            journal.record(text, x)?;
+                        }
+                        x
+                    }
+                    Err(e) => {
+                        let class = failure_class(&e);
+                        let count = match failures {
+                            Some((ref last, count)) if *last == class => count + 1,
+                            _ => 1,
+                        };
+                        if count >= max_failures {
+                            reason = Some(format!(
+                                "{} consecutive `{}` failures, last error: {}",
+                                count, class, e
+                            ));
+                        }
+                        failures = Some((class, count));
+                        None
+                    }
+                },
+                None => None,
             });
         }
     }
 
-    Ok(Some(
-        ChunkedArray::<ListType>::from_iter(results.into_iter()).into_series(),
-    ))
+    *tripped.lock().unwrap() = reason;
+
+    // Build with an explicit inner type, otherwise a run without any embedding yields list[null]
+    let mut builder = ListPrimitiveChunkedBuilder::<Float32Type>::new(
+        "embedding",
+        results.len(),
+        results.len(),
+        DataType::Float32,
+    );
+    for x in results {
+        builder.append_opt_slice(x.as_deref());
+    }
+    Ok(Some(builder.finish().into_series()))
 }
 
-// Currently, you have to modify the code here to filter what you want to brew
-fn brew(input: String, output: String) -> PolarsResult<()> {
-    let filtering = col("tags")
-        .str()
-        .contains(lit(TAGS), false)
-        .and(col("embeddings").is_null());
+fn combined(body: Expr, schema: &Schema) -> Expr {
+    let text = lit("Title: ") + col("title") + lit(" Body: ") + body;
+    // Comments often hold the clarification that a question is searched by
+    let text = match schema.contains("comments") {
+        true => when(col("comments").is_null())
+            .then(text.clone())
+            .otherwise(text + lit(" Comments: ") + col("comments")),
+        false => text,
+    };
+    // The accepted answer holds the words of whoever searches for the solution rather than the problem
+    match schema.contains("accepted_answer") {
+        true => when(col("accepted_answer").is_null())
+            .then(text.clone())
+            .otherwise(text + lit(" Accepted answer: ") + col("accepted_answer")),
+        false => text,
+    }
+}
 
-    // The when-then-otherwise is not lazy, so we need to manually return if filtering indicates no update is needed
-    if LazyFrame::scan_parquet(&input, Default::default())?
-        .filter(filtering.clone())
-        .collect()?
-        .height()
-        == 0
+fn byte_size(s: &str) -> Result<usize, String> {
+    let upper = s.trim().to_ascii_uppercase();
+    let digits = upper.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let shift = match upper[digits.len()..]
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
     {
-        println!("No update needed");
-        return Ok(());
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("expected a size such as 512M or 8G, got `{}`", s)),
+    };
+    match digits.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n << shift),
+        _ => Err(format!("expected a size such as 512M or 8G, got `{}`", s)),
     }
+}
 
-    let mut df = LazyFrame::scan_parquet(input, Default::default())?
-        .with_columns([
-            (lit("Title: ") + col("title") + lit(" Body: ") + col("body")).alias("combined"),
-        ])
-        .with_column(filtering.alias("mask"))
-        .with_column(
-            // NOTE: If we create filter such that there is no update then we will get an error on not being able to convert the return type.
-            map_multiple(
-                get_embeddings,
-                &[col("combined"), col("mask")],
-                GetOutput::from_type(DataType::List(DataType::Float32.boxed())),
+// A day, from its midnight, or a time of day, as dumps write them in UTC
+fn date(s: &str) -> Result<chrono::NaiveDateTime, String> {
+    let s = s.trim();
+    chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f"))
+        .or_else(|_| {
+            chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_time(Default::default()))
+        })
+        .map_err(|_| {
+            format!(
+                "expected a date such as 2020-01-01 or 2020-01-01T12:00:00, got `{}`",
+                s
             )
-            .alias("masked_updates"),
-        )
-        // This by default updates the "embeddings" column
-        .with_column(coalesce(&[col("embeddings"), col("masked_updates")]))
-        .select([cols(["id", "title", "body", "tags", "embeddings"])])
+        })
+}
+
+// Bytes of the largest embeddings brew produces, 3072 float32 dimensions of text-embedding-3-large
+const EMBEDDING_BYTES: usize = 3072 * 4;
+
+/// Run `pipeline` over the input a batch of rows at a time, appending each to the output as it completes
+fn brew_batches(
+    input: &Path,
+    output: &Path,
+    budget: usize,
+    columns: &ColumnArgs,
+    pipeline: impl Fn(LazyFrame) -> LazyFrame,
+) -> PolarsResult<()> {
+    // A batch is held about three times over, as read, as the combined text sent out and as written
+    let sample = columns
+        .scan_stored(input)?
+        .limit(ROW_GROUP_SIZE as IdxSize)
         .collect()?;
+    let row = sample.estimated_size() / sample.height().max(1) + EMBEDDING_BYTES;
+    let rows = (budget / (3 * row)).max(1);
+    println!("Brewing in batches of {} rows", rows);
 
-    println!("{}", df);
+    let schema = pipeline(columns.scan_stored(input)?).schema()?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    let mut reader = ParquetReader::new(files::open(input)?).batched(rows)?;
+    let (mut written, mut embedded) = (0, 0);
+    files::atomic(output, |file| {
+        let mut writer = ParquetWriter::new(file)
+            .with_statistics(true)
+            .batched(&schema)?;
+        // Row groups are only read whole, ada writes them ROW_GROUP_SIZE rows long
+        let groups = (rows / ROW_GROUP_SIZE).max(1);
+        while let Some(batches) = runtime.block_on(reader.next_batches(groups))? {
+            if batches.is_empty() {
+                break;
+            }
+            let fetched = concat(
+                batches.into_iter().map(|df| df.lazy()).collect::<Vec<_>>(),
+                Default::default(),
+            )?
+            .collect()?;
+            for offset in (0..fetched.height()).step_by(rows) {
+                let batch = fetched.slice(offset as i64, rows).lazy();
+                let df = pipeline(columns.rename(batch, input)?).collect()?;
+                embedded += df.column("embeddings")?.is_not_null().sum().unwrap_or(0) as usize;
+                for offset in (0..df.height()).step_by(ROW_GROUP_SIZE) {
+                    writer.write_batch(&df.slice(offset as i64, ROW_GROUP_SIZE))?;
+                }
+                written += df.height();
+                println!("Wrote {} rows, {} embedded", written, embedded);
+            }
+        }
+        writer.finish()?;
+        Ok(())
+    })?;
+    manifest::record(output, "")
+}
+
+fn brew(args: BrewArgs) -> PolarsResult<()> {
+    // Brewed in a local copy of the output, which is also the input when brewing in place
+    if files::remote(&args.output) {
+        let url = args.output.clone();
+        return store::staged(&url, |local| {
+            let input = match args.input == url {
+                true => local.to_path_buf(),
+                false => args.input.clone(),
+            };
+            brew(BrewArgs {
+                input,
+                output: local.to_path_buf(),
+                ..args
+            })
+        });
+    }
+    let BrewArgs {
+        input,
+        output,
+        max_failures,
+        dry_run,
+        cast_embeddings,
+        min_quality,
+        models,
+        routes,
+        dedup,
+        max_memory,
+        vocabulary,
+        sample,
+        scrub,
+        boilerplate,
+        plugin,
+        provider,
+        columns,
+    } = args;
+
+    let _locks = lock::rewrite(&input, &output)?;
 
-    let mut file = std::fs::File::create(output).unwrap();
-    // Use the default zstd compression
-    ParquetWriter::new(&mut file).finish(&mut df)?;
+    let schema = columns.scan_stored(&input)?.schema()?;
+
+    // Every model gets its own column and provider, with the first one standing in for --embedding-model
+    let mut targets = vec![(
+        "embeddings".to_string(),
+        embeddings(&schema, cast_embeddings)?,
+    )];
+    for model in models.iter().skip(1) {
+        let name = format!(
+            "embeddings_{}",
+            model.replace(|c: char| !c.is_alphanumeric(), "_")
+        );
+        let existing = match schema.get(&name) {
+            Some(_) => embedding_column(&schema, &name, cast_embeddings)?,
+            None => lit(NULL).cast(embedding_dtype()),
+        };
+        targets.push((name, existing));
+    }
+    let mut providers = match models.as_slice() {
+        [] => vec![provider.clone()],
+        models => models
+            .iter()
+            .map(|m| provider.with_model(m))
+            .collect::<PolarsResult<_>>()?,
+    };
+    // Routed models take turns filling the one column, each with the rows of its route
+    let routes = routes.map(|path| routes::Routes::load(&path)).transpose()?;
+    let mut routed = vec![lit(true)];
+    if let Some(ref routes) = routes {
+        tags::check(columns.scan_stored(&input)?, routes.tags(), "--routes")?;
+        routed[0] = col("route").eq(lit(provider.model().to_string()));
+        for model in routes
+            .models()
+            .into_iter()
+            .filter(|&m| m != provider.model())
+        {
+            targets.push(targets[0].clone());
+            providers.push(provider.with_model(model)?);
+            routed.push(col("route").eq(lit(model.to_string())));
+        }
+    }
+    let missing = targets
+        .iter()
+        .map(|(_, existing)| existing.clone().is_null())
+        .reduce(|a, b| a.or(b))
+        .unwrap();
+
+    let mut meta = Meta::load(&input)?;
+    let filtering = match &meta.brew_tags {
+        None => tags::any(&schema, TAGS.iter().copied()),
+        Some(wanted) if wanted.is_empty() => lit(true),
+        Some(wanted) => tags::any(&schema, wanted.iter().map(String::as_str)),
+    };
+    let filtering = match min_quality {
+        Some(q) if schema.contains("quality") => filtering.and(col("quality").gt_eq(lit(q))),
+        Some(_) => {
+            polars_bail!(ColumnNotFound: "dataset has no `quality` column, run the quality subcommand first")
+        }
+        None => filtering,
+    };
+
+    meta.boilerplate = boilerplate.patterns(&meta.boilerplate);
+    meta.strip_repeated = boilerplate.repeated(meta.strip_repeated);
+    // Recorded so that search prepares queries the way the documents were
+    if meta.model.is_some() && meta.normalized != Some(true) {
+        println!(
+            "Warning: {} holds embeddings that were not normalized, they rank differently from the ones embedded now",
+            input.display()
+        );
+    }
+    meta.scrub = Some(meta.scrub.take().unwrap_or_default().union(&scrub));
+    meta.normalized = Some(true);
+    let body = match boilerplate.stripper(
+        &meta.boilerplate,
+        meta.strip_repeated,
+        columns.scan_stored(&input)?,
+    )? {
+        Some(stripper) => Arc::new(stripper).expr(col("body")),
+        None => col("body"),
+    };
+
+    // Scrub before counting tokens so that the counts match what is actually sent
+    let combined = match scrub.scrubber()? {
+        Some(scrubber) => Arc::new(scrubber).expr(combined(body, &schema)),
+        None => combined(body, &schema),
+    };
+    let combined = match plugin.load(meta.plugin.as_deref(), plugin::Hook::Document)? {
+        Some(plugin) => Arc::new(plugin).expr(combined),
+        None => combined,
+    };
+    if let Some(path) = plugin.path(meta.plugin.as_deref()) {
+        let path = path
+            .canonicalize()
+            .map_err(|e| files::with_path(e, &path))?;
+        meta.plugin = Some(path.display().to_string());
+    }
+
+    // Rows sharing the embedding of another are written without one of their own
+    let (sharing, reshared) = match dedup || schema.contains(dedup::COLUMN) {
+        true => {
+            let todo = filtering.clone().and(missing.clone());
+            let (sharing, reshared) =
+                dedup::assign(columns.scan_stored(&input)?, combined.clone(), todo, dedup)?;
+            (Some(sharing), reshared)
+        }
+        false => (None, false),
+    };
+    let shared = |lf: LazyFrame| match sharing {
+        Some(ref sharing) => lf.with_column(sharing.clone().alias(dedup::COLUMN)),
+        None => lf,
+    };
+    let filtering = match sharing {
+        Some(_) => filtering.and(col(dedup::COLUMN).is_null()),
+        None => filtering,
+    };
+    // Picked among the rows yet to embed, a rerun embeds as many more
+    let filtering = match sample.is_set() {
+        true => {
+            let candidates = shared(columns.scan_stored(&input)?)
+                .filter(filtering.clone().and(missing.clone()))
+                .select([col("id")])
+                .collect()?;
+            let picked = sample.apply(candidates)?;
+            filtering.and(col("id").is_in(lit(picked.column("id")?.clone())))
+        }
+        false => filtering,
+    };
+
+    let todo = shared(columns.scan_stored(&input)?)
+        .filter(filtering.clone().and(missing))
+        .select([combined.clone().alias("combined")])
+        .collect()?;
+
+    // Token counts are cached by content hash, so only new or changed rows are tokenized
+    let mut cache = TokenCache::load(&[&input, &output])?;
+    cache.fill(todo.column("combined")?.str()?.into_iter().flatten());
+
+    let counts: Vec<usize> = todo
+        .column("combined")?
+        .str()?
+        .into_iter()
+        .flatten()
+        .filter_map(|text| cache.get(text))
+        .map(|tokens| tokens as usize)
+        .collect();
+    let too_long = counts.iter().filter(|&&t| t > MAX_TOKEN).count();
+    let total: usize = counts.iter().filter(|&&t| t <= MAX_TOKEN).sum();
+
+    if dry_run {
+        println!(
+            "Rows to embed: {}, tokens: {}, estimated cost: ${:.2}, skipped as too long: {}",
+            counts.len() - too_long,
+            total,
+            total as f64 / 1e6 * PRICE_PER_MTOK,
+            too_long
+        );
+        return Ok(());
+    }
+    // Saved on a real brew only, a dry run leaves the output as it is
+    cache.save(&output)?;
+
+    if too_long > 0 {
+        println!("Skipping {} rows exceeding {} tokens", too_long, MAX_TOKEN);
+    }
+
+    // The when-then-otherwise is not lazy, so we need to manually return if filtering indicates no update is needed
+    if counts.len() == too_long && !reshared {
+        println!("No update needed");
+        // Still mined for a dataset brewed before, when brewing it in place
+        if vocabulary && input == output {
+            spell::Speller::write(&output)?;
+        }
+        return Ok(());
+    }
+    audit::changed(&output, counts.len() - too_long);
+
+    // Probe the provider so that a column never mixes embeddings from different models
+    let mut journals = Vec::new();
+    let mut served = Vec::new();
+    for (i, ((name, _), provider)) in targets.iter().zip(&providers).enumerate() {
+        let model = match probe(provider.clone()) {
+            Ok(model) => model,
+            Err(e) => polars_bail!(ComputeError: "failed to reach the embedding provider: {}", e),
+        };
+        served.push(model.clone());
+        // A routed row records its own model, only the rows no route takes must match the recorded one
+        if i > 0 && routes.is_some() {
+            journals.push(Arc::new(Journal::open(&input, &output, &model)?));
+            continue;
+        }
+        let recorded = match name.as_str() {
+            "embeddings" => meta.model.as_ref(),
+            name => meta.models.get(name),
+        };
+        if let Some(recorded) = recorded.filter(|&m| *m != model) {
+            polars_bail!(
+                ComputeError: "{} were embedded with `{}` but {} serves `{}`",
+                name, recorded, provider.endpoint(), model
+            );
+        }
+        journals.push(Arc::new(Journal::open(&input, &output, &model)?));
+        match name.as_str() {
+            "embeddings" => meta.model = Some(model),
+            name => {
+                meta.models.insert(name.to_string(), model);
+            }
+        }
+    }
+    meta.endpoint = Some(provider.endpoint());
+    if routes.is_some() {
+        meta.routes = routes.clone();
+    }
+
+    let cache = Arc::new(cache);
+    let tripped = Arc::new(Mutex::new(None));
+    let breaker = tripped.clone();
+
+    let pipeline = |lf: LazyFrame| {
+        let cache = cache.clone();
+        let mut lf = shared(lf)
+            .with_columns([combined.clone().alias("combined")])
+            .with_column(
+                col("combined")
+                    .map(
+                        move |c| {
+                            Ok(Some(
+                                c.str()?
+                                    .into_iter()
+                                    .map(|text| text.and_then(|text| cache.get(text)))
+                                    .collect::<UInt32Chunked>()
+                                    .into_series(),
+                            ))
+                        },
+                        GetOutput::from_type(DataType::UInt32),
+                    )
+                    .alias("tokens"),
+            );
+        if let Some(ref routes) = routes {
+            lf = lf.with_column(routes.expr(&schema, provider.model()).alias("route"));
+            if !schema.contains("model") {
+                lf = lf.with_column(lit(NULL).cast(DataType::String).alias("model"));
+            }
+        }
+        // The tokens and the filtering are shared, only the requests differ between models
+        for ((((name, existing), provider), journal), (routed, served)) in targets
+            .iter()
+            .zip(&providers)
+            .zip(&journals)
+            .zip(routed.iter().zip(&served))
+        {
+            let (journaled, journal) = (journal.clone(), journal.clone());
+            let (provider, breaker) = (provider.clone(), breaker.clone());
+            lf = lf
+                .with_column(
+                    col("combined")
+                        .map(
+                            move |c| {
+                                let mut builder = ListPrimitiveChunkedBuilder::<Float32Type>::new(
+                                    "journaled",
+                                    c.len(),
+                                    c.len(),
+                                    DataType::Float32,
+                                );
+                                for text in c.str()? {
+                                    builder.append_opt_slice(text.and_then(|t| journaled.get(t)));
+                                }
+                                Ok(Some(builder.finish().into_series()))
+                            },
+                            GetOutput::from_type(embedding_dtype()),
+                        )
+                        .alias("journaled"),
+                )
+                .with_column(
+                    filtering
+                        .clone()
+                        .and(routed.clone())
+                        .and(existing.clone().is_null())
+                        .and(col("tokens").lt_eq(lit(MAX_TOKEN as u32)))
+                        .and(col("journaled").is_null())
+                        .fill_null(lit(false))
+                        .alias("mask"),
+                )
+                .with_column(
+                    // NOTE: If we create filter such that there is no update then we will get an error on not being able to convert the return type.
+                    map_multiple(
+                        move |s| get_embeddings(s, &provider, max_failures, &breaker, &journal),
+                        &[col("combined"), col("mask"), col("tokens")],
+                        GetOutput::from_type(embedding_dtype()),
+                    )
+                    .alias("masked_updates"),
+                );
+            if routes.is_some() {
+                let embedded = existing
+                    .clone()
+                    .is_null()
+                    .and(coalesce(&[col("journaled"), col("masked_updates")]).is_not_null());
+                lf = lf.with_column(
+                    when(embedded)
+                        .then(lit(served.clone()))
+                        .otherwise(col("model"))
+                        .alias("model"),
+                );
+            }
+            lf = lf
+                // Updates the column of the model, "embeddings" for the first one
+                .with_column(
+                    coalesce(&[existing.clone(), col("journaled"), col("masked_updates")])
+                        .alias(name),
+                )
+                .drop(["journaled", "mask", "masked_updates"]);
+        }
+        if sharing.is_some() {
+            lf = lf.with_column(
+                when(col(dedup::COLUMN).is_not_null())
+                    .then(lit(NULL).cast(embedding_dtype()))
+                    .otherwise(col("embeddings"))
+                    .alias("embeddings"),
+            );
+        }
+        match routes {
+            Some(_) => lf.drop(["combined", "tokens", "route"]),
+            None => lf.drop(["combined", "tokens"]),
+        }
+    };
+
+    match max_memory {
+        Some(budget) => brew_batches(&input, &output, budget, &columns, pipeline)?,
+        None => {
+            let mut df = pipeline(columns.scan_stored(&input)?).collect()?;
+            println!("{}", df);
+            write_dataset(&mut df, &output)?;
+        }
+    }
+    meta.save(&output)?;
+    Journal::clear(&output)?;
+    // Mined here rather than by search --correct, which only holds the shared lock
+    if vocabulary {
+        spell::Speller::write(&output)?;
+    }
 
     println!("Finished writing");
 
+    // The output written above doubles as the checkpoint, rerunning brew on it resumes from the rows left null
+    if let Some(reason) = tripped.lock().unwrap().take() {
+        println!("==================== BREW ABORTED ====================");
+        println!("Root cause: {}", reason);
+        println!(
+            "Embeddings obtained so far have been written, rerun brew on the output to resume"
+        );
+        println!("======================================================");
+        polars_bail!(ComputeError: "brew aborted after {}", reason);
+    }
+
     Ok(())
 }
 
-fn parse_xml(input: impl AsRef<Path>, output: impl AsRef<Path>) -> PolarsResult<()> {
-    use roxmltree::Document;
+// The columns of the questions parse-xml keeps, taken out as a frame at the end or when flushed to a checkpoint
+#[derive(Default)]
+struct Questions {
+    ids: Vec<String>,
+    titles: Vec<String>,
+    bodies: Vec<String>,
+    tags: Vec<Series>,
+    scores: Vec<i32>,
+    answer_counts: Vec<u32>,
+    view_counts: Vec<Option<u32>>,
+    creation_dates: Vec<chrono::NaiveDateTime>,
+    author_ids: Vec<Option<u32>>,
+    authors: Vec<Option<String>>,
+    licenses: Vec<Option<String>>,
+    sites: Vec<Option<String>>,
+    blobs: Vec<String>,
+}
 
-    let mut df = DataFrame::default();
-    let text = std::fs::read_to_string(input).unwrap();
+impl Questions {
+    fn take(&mut self, by_site: bool, blobs: bool) -> PolarsResult<DataFrame> {
+        let Self {
+            ids,
+            titles,
+            bodies,
+            tags,
+            scores,
+            answer_counts,
+            view_counts,
+            creation_dates,
+            author_ids,
+            authors,
+            licenses,
+            sites,
+            blobs: hashes,
+        } = std::mem::take(self);
+        // An empty list of tags would be list[null], unlike the ones of a flush with questions
+        let tags = Series::new("tags", tags).cast(&DataType::List(Box::new(DataType::String)))?;
+        let mut df = df!("id" => ids, "title" => titles, "body" => bodies, "tags" => tags, "score" => scores, "answer_count" => answer_counts, "view_count" => view_counts, "creation_date" => creation_dates, "author_id" => author_ids, "author" => authors, "license" => licenses)?;
+        if by_site {
+            df.with_column(Series::new("site", sites))?;
+        }
+        if blobs {
+            df.with_column(Series::new("blob", hashes))?;
+        }
+        Ok(df)
+    }
+}
 
-    for node in Document::parse(&text).unwrap().descendants() {
-        // Make sure we have got a valid question post
-        if node
-            .attributes()
-            .any(|a| (a.name() == "PostTypeId") && (a.value() == "1"))
-            && node
-                .attributes()
-                .any(|a| (a.name() == "Score") && (a.value().parse::<isize>().unwrap() >= 0))
-        {
-            // By definition these attributes must exists as we have got a question already
-            let id: u32 = node
-                .attributes()
-                .find(|a| a.name() == "Id")
-                .expect("Question Post expects Id")
-                .value()
-                .parse()
-                .expect("Question Id should be u32");
-            // Remove HTML tags and trim the text
-            let body = voca_rs::strip::strip_tags(
-                node.attributes()
-                    .find(|a| a.name() == "Body")
+fn parse_xml(args: ParseXmlArgs) -> PolarsResult<()> {
+    // Parsed into a local copy of the output, to merge into what is already stored
+    if files::remote(&args.output) {
+        // Staged in a temporary directory that goes with the process
+        if args.checkpoint.is_some() {
+            polars_bail!(ComputeError: "--checkpoint keeps its flushes next to the output, parse into a local file to resume");
+        }
+        let url = args.output.clone();
+        return store::staged(&url, |local| {
+            parse_xml(ParseXmlArgs {
+                output: local.to_path_buf(),
+                ..args
+            })
+        });
+    }
+    let ParseXmlArgs {
+        input,
+        output,
+        scrub,
+        blobs,
+        answers,
+        site,
+        url_template,
+        comments,
+        max_comments,
+        users,
+        links,
+        accepted_answer,
+        markdown,
+        sanitize,
+        replace,
+        min_score,
+        after,
+        before,
+        duplicates,
+        checkpoint,
+        sample,
+    } = args;
+    if let (Some(after), Some(before)) = (after, before) {
+        if after >= before {
+            polars_bail!(ComputeError: "--after {} leaves no question before --before {}", after, before);
+        }
+    }
+    if checkpoint == Some(0) {
+        polars_bail!(ComputeError: "--checkpoint needs at least one question between flushes");
+    }
+    let scrubber = scrub.scrubber()?;
+    let blobs = blobs.map(blobs::BlobStore::new).transpose()?;
+    let _lock = lock::Lock::exclusive(&output)?;
+    // Merging keeps the embeddings of the unchanged rows, and with them the model they were brewed with
+    let merging = !replace && output.exists();
+    let mut meta = match merging {
+        true => {
+            manifest::verify(&output)?;
+            Meta::load(&output)?
+        }
+        false => Meta::default(),
+    };
+    if let Some(ref blobs) = blobs {
+        meta.blobs = Some(blobs.dir().display().to_string());
+    }
+    let dumps = xml::dumps(&input)?;
+    let by_site = dumps.iter().any(|(site, _)| site.is_some());
+    match by_site {
+        true => meta.set_sites(url_template)?,
+        false => meta.set_site(site, url_template)?,
+    }
+    if by_site && (comments.is_some() || users.is_some() || links.is_some()) {
+        polars_bail!(ComputeError: "--comments, --users and --links take the file of a single dump, parse the sites one at a time to add them");
+    }
+    meta.scrub = Some(meta.scrub.take().unwrap_or_default().union(&scrub));
+    let mut checkpoint = checkpoint
+        .map(|every| Ok::<_, PolarsError>((every, checkpoint::Checkpoint::open(&input, &output)?)))
+        .transpose()?;
+
+    let mut questions = Questions::default();
+    let mut kept = 0;
+    let sanitizer = sanitize.sanitizer(markdown)?;
+    let text = |html: &str| {
+        let html = match sanitizer {
+            Some(ref sanitizer) => sanitizer.sanitize(html),
+            None => html.into(),
+        };
+        match markdown {
+            true => markdown::from_html(&html),
+            false => voca_rs::strip::strip_tags(&html),
+        }
+    };
+    let mut answers = answers.then(answers::Answers::default);
+    // The question every accepted answer belongs to, and the body of those seen so far
+    let (mut accepted, mut accepted_bodies) = (HashMap::new(), HashMap::new());
+    for (index, (site, path)) in dumps.iter().enumerate() {
+        // The rows an interrupted parse flushed already, the whole dump if it went past it
+        let skip = match checkpoint {
+            Some((_, ref checkpoint)) => match checkpoint.skip(index) {
+                Some(rows) => rows,
+                None => continue,
+            },
+            None => 0,
+        };
+        let prefix = site.as_ref().map(|s| format!("{}/", s)).unwrap_or_default();
+        if let Some(ref mut answers) = answers {
+            answers.site(site.as_deref());
+        }
+        let mut read = 0;
+        xml::rows(path, "Posts.xml", |node| {
+            read += 1;
+            if read <= skip {
+                return Ok(());
+            }
+            let attribute = |name: &str| node.get(name);
+
+            // Make sure we have got a valid question post
+            // The rest of the dump is still read through for the answers of the questions kept
+            if attribute("PostTypeId") == Some("1")
+                && sample.limit.is_some_and(|limit| kept >= limit)
+            {
+                return Ok(());
+            }
+            let score = match attribute("PostTypeId") {
+                Some("1") => attribute("Score")
+                    .map(|v| {
+                        v.parse::<i32>().map_err(|e| {
+                            polars_err!(ComputeError: "question {}{} has an invalid Score {:?}: {}", prefix, attribute("Id").unwrap_or("?"), v, e)
+                        })
+                    })
+                    .transpose()?,
+                _ => None,
+            };
+            if let Some(score) = score.filter(|&score| score >= min_score) {
+                // By definition these attributes must exists as we have got a question already
+                let creation_date = chrono::NaiveDateTime::parse_from_str(
+                    attribute("CreationDate").expect("Question Post expects CreationDate"),
+                    "%Y-%m-%dT%H:%M:%S%.f",
+                )
+                .expect("Question CreationDate should be an ISO 8601 timestamp");
+                if after.is_some_and(|after| creation_date < after)
+                    || before.is_some_and(|before| creation_date >= before)
+                {
+                    return Ok(());
+                }
+                let answer_count: u32 = attribute("AnswerCount")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                // Kept as a string, so that corpora identified by paths, UUIDs or arXiv ids share the schema
+                let id = format!(
+                    "{}{}",
+                    prefix,
+                    attribute("Id").expect("Question Post expects Id")
+                );
+                let html = attribute("Body")
                     .expect("Question Post expects Body")
-                    .value()
-                    .trim(),
-            );
-            let tags = node
-                .attributes()
-                .find(|a| a.name() == "Tags")
-                .expect("Question Post expects Tags")
-                .value();
-            let title = node
-                .attributes()
-                .find(|a| a.name() == "Title")
-                .expect("Question Post expects Title")
-                .value();
+                    .trim();
+                let html = match scrubber {
+                    Some(ref scrubber) => scrubber.scrub(html),
+                    None => html.into(),
+                };
+                // Remove HTML tags, or turn them into Markdown, and trim the text
+                let body = text(&html);
+                if let Some(ref blobs) = blobs {
+                    questions.blobs.push(blobs.put(&html)?);
+                }
+
+                if let (true, Some(answer)) = (accepted_answer, attribute("AcceptedAnswerId")) {
+                    accepted.insert(format!("{}{}", prefix, answer), id.clone());
+                }
+                kept += 1;
+                questions.ids.push(id);
+                questions.sites.push(site.clone());
+                questions.titles.push(
+                    attribute("Title")
+                        .expect("Question Post expects Title")
+                        .to_string(),
+                );
+                questions.bodies.push(body);
+                let tagged = attribute("Tags").expect("Question Post expects Tags");
+                let tagged = Series::new("", tags::split(tagged).collect::<Vec<_>>());
+                questions.tags.push(tagged);
+                questions.scores.push(score);
+                questions.answer_counts.push(answer_count);
+                // Missing from some exports, such as posts migrated from another site
+                let views = attribute("ViewCount").and_then(|v| v.parse::<u32>().ok());
+                questions.view_counts.push(views);
+                questions.creation_dates.push(creation_date);
+                // Attribution for the CC BY-SA license, the owner may be missing for deleted users
+                let author_id = attribute("OwnerUserId").and_then(|v| v.parse::<u32>().ok());
+                questions.author_ids.push(author_id);
+                questions
+                    .authors
+                    .push(attribute("OwnerDisplayName").map(String::from));
+                questions
+                    .licenses
+                    .push(attribute("ContentLicense").map(String::from));
+                if let Some((every, ref mut checkpoint)) = checkpoint {
+                    if questions.ids.len() >= every {
+                        let mut flushed = questions.take(by_site, blobs.is_some())?;
+                        checkpoint.flush(&mut flushed, index, read)?;
+                    }
+                }
+            } else if attribute("PostTypeId") == Some("2") {
+                // Dumps list posts by id, so an answer comes after the question that may have accepted it
+                let question =
+                    attribute("Id").and_then(|id| accepted.get(&format!("{}{}", prefix, id)));
+                if answers.is_none() && question.is_none() {
+                    return Ok(());
+                }
+                let html = attribute("Body").unwrap_or_default().trim();
+                let html = match scrubber {
+                    Some(ref scrubber) => scrubber.scrub(html),
+                    None => html.into(),
+                };
+                let body = text(&html);
+                if let Some(question) = question {
+                    accepted_bodies.insert(question.clone(), body.clone());
+                }
+                if let Some(ref mut answers) = answers {
+                    answers.push(attribute, body);
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    let mut df = questions.take(by_site, blobs.is_some())?;
+    if let Some((_, ref checkpoint)) = checkpoint {
+        if let Some(mut parsed) = checkpoint.parsed()? {
+            parsed.vstack_mut(&df)?;
+            df = parsed;
+        }
+    }
 
-            let row = df!("id" => &[id], "title" => &[title], "body" => &[body], "tags" => &[tags], "embeddings" => &[None::<Series>])?;
-            df.vstack_mut(&row)?;
+    // Only deleted users carry their name on the post, the dump lists everyone else separately
+    if let Some(users) = users {
+        let author_ids = df.column("author_id")?.u32()?.clone();
+        let users = users::load(&users, &author_ids.into_iter().flatten().collect())?;
+        let user = |id: Option<u32>| id.and_then(|id| users.get(&id));
+        let authors: StringChunked = author_ids
+            .into_iter()
+            .zip(df.column("author")?.str()?)
+            .map(|(id, author)| user(id).map(|(name, _)| name.as_str()).or(author))
+            .collect();
+        let reputations: Int32Chunked = author_ids
+            .into_iter()
+            .map(|id| user(id).map(|(_, r)| *r))
+            .collect();
+        df.with_column(authors.with_name("author").into_series())?;
+        df.with_column(reputations.with_name("reputation").into_series())?;
+    }
+
+    // Repeated ids would be embedded, and found by search, twice
+    let mut seen = HashMap::new();
+    let ids = df.column("id")?.str()?.clone();
+    for id in ids.into_iter().flatten() {
+        *seen.entry(id).or_insert(0) += 1;
+    }
+    let mut repeated: Vec<(&str, usize)> = seen.into_iter().filter(|&(_, n)| n > 1).collect();
+    repeated.sort();
+    if !repeated.is_empty() {
+        let listed = repeated
+            .iter()
+            .take(10)
+            .map(|(id, n)| format!("{} ({} times)", id, n))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let more = match repeated.len() > 10 {
+            true => format!(" and {} more", repeated.len() - 10),
+            false => String::new(),
+        };
+        match duplicates {
+            Duplicates::Last => println!(
+                "Found {} repeated ids, keeping the last question of each: {}{}",
+                repeated.len(),
+                listed,
+                more
+            ),
+            Duplicates::Error => {
+                polars_bail!(ComputeError: "{} repeated ids in {}: {}{}", repeated.len(), input.display(), listed, more)
+            }
         }
     }
+    let repeated = !repeated.is_empty();
+
+    let embeddings = vec![None::<Series>; df.height()];
+    let after = df.get_column_index("license").map_or(df.width(), |i| i + 1);
+    df.insert_column(after, Series::new("embeddings", embeddings))?;
+    if repeated {
+        df = df.unique_stable(Some(&["id".to_string()]), UniqueKeepStrategy::Last, None)?;
+    }
+    let mut df = sample.apply(df)?;
+    if accepted_answer {
+        let bodies: StringChunked = df
+            .column("id")?
+            .str()?
+            .into_iter()
+            .map(|id| {
+                id.and_then(|id| accepted_bodies.get(id))
+                    .map(String::as_str)
+            })
+            .collect();
+        df.with_column(bodies.with_name("accepted_answer").into_series())?;
+    }
+    if let Some(comments) = comments {
+        let questions = df.column("id")?.str()?.clone();
+        let top = comments::top(
+            &comments,
+            max_comments,
+            &questions.into_iter().flatten().collect(),
+        )?;
+        let comments: StringChunked = questions
+            .into_iter()
+            .map(|id| id.and_then(|id| top.get(id)).map(String::as_str))
+            .collect();
+        df.with_column(comments.with_name("comments").into_series())?;
+    }
+    if let Some(links) = links {
+        let questions = df.column("id")?.str()?.clone();
+        let links = links::load(&links, &questions.into_iter().flatten().collect())?;
+        let linked = |id: Option<&str>| id.and_then(|id| links.get(id));
+        let duplicates: StringChunked = questions
+            .into_iter()
+            .map(|id| linked(id).and_then(|l| l.duplicate_of.as_deref()))
+            .collect();
+        let related: Vec<Series> = questions
+            .into_iter()
+            .map(|id| {
+                let related = linked(id).map_or(&[][..], |l| &l.related[..]);
+                Series::new("", related)
+            })
+            .collect();
+        df.with_column(duplicates.with_name("duplicate_of").into_series())?;
+        df.with_column(Series::new("related_ids", related))?;
+    }
     println!("{}", df);
+    audit::changed(&output, df.height());
 
-    let mut file = std::fs::File::create(output).unwrap();
-    // Use the default zstd compression
-    ParquetWriter::new(&mut file).finish(&mut df)?;
+    if let (true, Some(answers)) = (merging, &mut answers) {
+        answers.keep(&output, df.column("id")?)?;
+    }
+    let mut df = match merging {
+        true => merge::upsert(df, &output)?,
+        false => df,
+    };
+    write_dataset(&mut df, &output)?;
+    meta.save(&output)?;
+    match answers {
+        Some(answers) => {
+            let kept = answers.write(&output, df.column("id")?)?;
+            println!("Kept {} answers", kept);
+        }
+        // The answers of an earlier parse go stale along with the questions they were parsed with
+        None if !merging => answers::Answers::clear(&output)?,
+        None => {}
+    }
+    if checkpoint.is_some() {
+        checkpoint::Checkpoint::clear(&output)?;
+    }
 
     println!("Finished writing");
 
@@ -0,0 +1,99 @@
+use crate::{audit, files, lineage};
+use polars::prelude::*;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Whether opening a dataset checks its files against the manifest, cleared by --no-verify
+pub static VERIFY: AtomicBool = AtomicBool::new(true);
+
+// SHA-256 of the Parquet files of a dataset by file name, kept in a `<dataset>.manifest.json` sidecar.
+// The metadata is left out, it is meant to be edited by hand and fails to parse when corrupted.
+fn path(dataset: impl AsRef<Path>) -> PathBuf {
+    let mut path = dataset.as_ref().as_os_str().to_owned();
+    path.push(".manifest.json");
+    path.into()
+}
+
+fn file(dataset: &Path, suffix: &str) -> PathBuf {
+    let mut path = dataset.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+fn load(dataset: &Path) -> PolarsResult<BTreeMap<String, String>> {
+    let path = path(dataset);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    serde_json::from_slice(&files::read(&path)?)
+        .map_err(|e| polars_err!(ComputeError: "invalid manifest in {}: {}", path.display(), e))
+}
+
+fn save(dataset: &Path, checksums: &BTreeMap<String, String>) -> PolarsResult<()> {
+    files::atomic(path(dataset), |file| {
+        serde_json::to_writer_pretty(file, checksums)
+            .map_err(|e| polars_err!(ComputeError: "failed to write the manifest: {}", e))
+    })
+}
+
+fn name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Record the checksum of `<dataset><suffix>` just written, `suffix` is empty for the dataset itself
+pub fn record(dataset: impl AsRef<Path>, suffix: &str) -> PolarsResult<()> {
+    let dataset = dataset.as_ref();
+    let written = file(dataset, suffix);
+    let mut checksums = load(dataset)?;
+    let previous = checksums.insert(name(&written), files::sha256(&written)?);
+    save(dataset, &checksums)?;
+    if suffix.is_empty() {
+        lineage::written(dataset);
+        audit::written(dataset, previous);
+    }
+    Ok(())
+}
+
+/// The recorded checksum of the dataset itself, `None` if ada didn't write it
+pub fn checksum(dataset: impl AsRef<Path>) -> PolarsResult<Option<String>> {
+    let dataset = dataset.as_ref();
+    if !path(dataset).exists() {
+        return Ok(None);
+    }
+    Ok(load(dataset)?.remove(&name(dataset)))
+}
+
+/// Drop the checksum of a sidecar that has been removed
+pub fn forget(dataset: impl AsRef<Path>, suffix: &str) -> PolarsResult<()> {
+    let dataset = dataset.as_ref();
+    let mut checksums = load(dataset)?;
+    if checksums.remove(&name(&file(dataset, suffix))).is_some() {
+        save(dataset, &checksums)?;
+    }
+    Ok(())
+}
+
+/// Check the files of `dataset` against their recorded checksums, datasets written elsewhere have none
+pub fn verify(dataset: impl AsRef<Path>) -> PolarsResult<()> {
+    if !VERIFY.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let dataset = dataset.as_ref();
+    let dir = dataset.parent().unwrap_or(Path::new(""));
+    for (name, expected) in load(dataset)? {
+        let path = dir.join(&name);
+        if path.exists() && files::sha256(&path)? != expected {
+            polars_bail!(
+                ComputeError: "{} does not match its checksum in {}, it was modified or corrupted since ada wrote it; pass --no-verify to open it anyway",
+                path.display(), self::path(dataset).display()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use regex::Regex;
+use std::sync::OnceLock;
+
+// The tags of the HTML Stack Exchange renders posts to, and comments
+fn tags() -> &'static Regex {
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+    TAGS.get_or_init(|| Regex::new(r"(?s)<!--.*?-->|<(/?)([a-zA-Z][a-zA-Z0-9]*)([^>]*)>").unwrap())
+}
+
+fn attribute(attributes: &str, name: &str) -> String {
+    let pattern = format!(r#"(?i)\b{}\s*=\s*(?:"([^"]*)"|'([^']*)')"#, name);
+    Regex::new(&pattern)
+        .unwrap()
+        .captures(attributes)
+        .and_then(|c| c.get(1).or(c.get(2)))
+        .map(|m| unescape(m.as_str()))
+        .unwrap_or_default()
+}
+
+pub fn unescape(text: &str) -> String {
+    quick_xml::escape::unescape_with(text, |entity| match entity {
+        "nbsp" => Some(" "),
+        "ndash" => Some("–"),
+        "mdash" => Some("—"),
+        "hellip" => Some("…"),
+        _ => None,
+    })
+    .map(|text| text.into_owned())
+    .unwrap_or_else(|_| text.to_string())
+}
+
+/// Convert the HTML of a post to Markdown, keeping the code blocks, lists, links and emphasis that stripping
+/// the tags loses. Text is left unescaped, so that inline math such as `$E = mc^2$` reads as written.
+pub fn from_html(html: &str) -> String {
+    // Blockquotes are written into a buffer of their own and quoted line by line when they close
+    let mut out = vec![String::new()];
+    let mut lists: Vec<Option<usize>> = vec![];
+    let mut links: Vec<String> = vec![];
+    let mut pre = false;
+    let mut last = 0;
+
+    for tag in tags().captures_iter(html) {
+        let whole = tag.get(0).unwrap();
+        text(out.last_mut().unwrap(), &html[last..whole.start()], pre);
+        last = whole.end();
+        let Some(name) = tag.get(2) else {
+            continue;
+        };
+        let closing = !tag[1].is_empty();
+        let buf = out.last_mut().unwrap();
+        match (name.as_str().to_ascii_lowercase().as_str(), closing) {
+            ("p" | "div", _) => block(buf),
+            ("br", _) => buf.push('\n'),
+            ("hr", _) => {
+                block(buf);
+                buf.push_str("---\n\n");
+            }
+            ("pre", false) => {
+                block(buf);
+                buf.push_str("```\n");
+                pre = true;
+            }
+            ("pre", true) => {
+                if !buf.ends_with('\n') {
+                    buf.push('\n');
+                }
+                buf.push_str("```\n\n");
+                pre = false;
+            }
+            ("code", _) if !pre => buf.push('`'),
+            ("em" | "i", _) => buf.push('*'),
+            ("strong" | "b", _) => buf.push_str("**"),
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                block(buf);
+                let level = name.as_str()[1..].parse().unwrap_or(1);
+                buf.push_str(&"#".repeat(level));
+                buf.push(' ');
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => block(buf),
+            ("a", false) => {
+                links.push(attribute(&tag[3], "href"));
+                buf.push('[');
+            }
+            ("a", true) => {
+                let href = links.pop().unwrap_or_default();
+                buf.push_str(&format!("]({})", href));
+            }
+            ("img", _) => buf.push_str(&format!(
+                "![{}]({})",
+                attribute(&tag[3], "alt"),
+                attribute(&tag[3], "src")
+            )),
+            // A nested list continues the item it is in
+            ("ul" | "ol", false) => {
+                if lists.is_empty() {
+                    block(buf);
+                }
+                lists.push(name.as_str().eq_ignore_ascii_case("ol").then_some(0));
+            }
+            ("ul" | "ol", true) => {
+                lists.pop();
+                if lists.is_empty() {
+                    block(buf);
+                }
+            }
+            ("li", false) => {
+                if !buf.is_empty() && !buf.ends_with('\n') {
+                    buf.push('\n');
+                }
+                buf.push_str(&"   ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        buf.push_str(&format!("{}. ", n));
+                    }
+                    _ => buf.push_str("- "),
+                }
+            }
+            ("blockquote", false) => {
+                block(buf);
+                out.push(String::new());
+            }
+            ("blockquote", true) if out.len() > 1 => {
+                let quoted = out.pop().unwrap();
+                let buf = out.last_mut().unwrap();
+                for line in quoted.trim().lines() {
+                    buf.push_str(if line.is_empty() { ">" } else { "> " });
+                    buf.push_str(line);
+                    buf.push('\n');
+                }
+                buf.push('\n');
+            }
+            _ => {}
+        }
+    }
+    text(out.last_mut().unwrap(), &html[last..], pre);
+    // Unbalanced blockquotes still keep their text
+    let markdown = out.concat();
+
+    let mut collapsed = String::with_capacity(markdown.len());
+    let mut blank = 0;
+    for line in markdown.trim().lines() {
+        let line = line.trim_end();
+        blank = if line.is_empty() { blank + 1 } else { 0 };
+        if blank < 2 {
+            collapsed.push_str(line);
+            collapsed.push('\n');
+        }
+    }
+    collapsed.trim_end().to_string()
+}
+
+// Append text found between tags, as is in a code block and with whitespace collapsed elsewhere
+fn text(buf: &mut String, text: &str, pre: bool) {
+    let text = unescape(text);
+    if pre {
+        buf.push_str(&text);
+        return;
+    }
+    let mut words = text.split_whitespace().peekable();
+    if words.peek().is_none() {
+        if !text.is_empty() && !buf.is_empty() && !buf.ends_with([' ', '\n']) {
+            buf.push(' ');
+        }
+        return;
+    }
+    if text.starts_with(char::is_whitespace) && !buf.is_empty() && !buf.ends_with([' ', '\n']) {
+        buf.push(' ');
+    }
+    buf.push_str(&words.collect::<Vec<_>>().join(" "));
+    if text.ends_with(char::is_whitespace) {
+        buf.push(' ');
+    }
+}
+
+// Start a new paragraph
+fn block(buf: &mut String) {
+    if buf.is_empty() || buf.ends_with("\n\n") {
+        return;
+    }
+    buf.push_str(if buf.ends_with('\n') { "\n" } else { "\n\n" });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::from_html;
+
+    #[test]
+    fn paragraphs_keep_emphasis_and_links() {
+        let html = r#"<p>An <em>important</em> <a href="https://x.org/?a=1&amp;b=2">link</a></p><p>Next</p>"#;
+        assert_eq!(
+            from_html(html),
+            "An *important* [link](https://x.org/?a=1&b=2)\n\nNext"
+        );
+    }
+
+    #[test]
+    fn code_blocks_are_kept_as_written() {
+        let html = "<p>Try</p><pre><code>let x = 1;\n  y &lt; 2\n</code></pre>";
+        assert_eq!(from_html(html), "Try\n\n```\nlet x = 1;\n  y < 2\n```");
+    }
+
+    #[test]
+    fn nested_lists_continue_their_item() {
+        let html = "<ol><li>one<ul><li>sub</li></ul></li><li>two</li></ol>";
+        assert_eq!(from_html(html), "1. one\n   - sub\n2. two");
+    }
+
+    #[test]
+    fn blockquotes_are_quoted_line_by_line() {
+        let html = "<blockquote><p>quoted</p><p>twice</p></blockquote><p>after</p>";
+        assert_eq!(from_html(html), "> quoted\n>\n> twice\n\nafter");
+    }
+
+    #[test]
+    fn math_is_left_unescaped() {
+        assert_eq!(
+            from_html("<p>$a &lt; b$ and $E = mc^2$</p>"),
+            "$a < b$ and $E = mc^2$"
+        );
+    }
+}
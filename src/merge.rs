@@ -0,0 +1,108 @@
+use crate::tags;
+use polars::prelude::*;
+use std::{collections::HashSet, path::Path};
+
+// The text that brew embeds, a row whose text is unchanged keeps its embeddings
+pub const TEXT: &[&str] = &["title", "body", "comments", "accepted_answer"];
+
+/// Merge freshly `parsed` rows into the dataset at `existing` by id: new ids are added, known ids take the
+/// parsed values, and ids missing from `parsed` are kept as they are. Columns only the existing dataset has,
+/// such as the embeddings or the quality, are kept for the rows whose text is unchanged and left null for
+/// the others, so that brew picks them up again.
+pub fn upsert(parsed: DataFrame, existing: &Path) -> PolarsResult<DataFrame> {
+    let old = LazyFrame::scan_parquet(existing, Default::default())?;
+    let old_schema = old.schema()?;
+    for name in ["id", "title", "body"] {
+        if !old_schema.contains(name) {
+            polars_bail!(ColumnNotFound: "{} has no `{}` column to merge into, pass --replace to overwrite it", existing.display(), name);
+        }
+    }
+    let mut upgraded = vec![col("id").cast(DataType::String)];
+    if old_schema.contains("tags") {
+        upgraded.push(tags::column(&old_schema).alias("tags"));
+    }
+    let old = old.with_columns(upgraded).collect()?;
+
+    let carried: Vec<String> = old_schema
+        .iter_names()
+        .filter(|name| name.as_str() == "embeddings" || !parsed.schema().contains(name))
+        .map(|name| name.to_string())
+        .collect();
+    let text: Vec<&str> = TEXT
+        .iter()
+        .copied()
+        .filter(|name| parsed.schema().contains(name))
+        .collect();
+
+    // The existing text is renamed out of the way, a text column it lacks was empty when it was brewed
+    let mut previous = vec![col("id"), lit(true).alias("matched")];
+    for name in &text {
+        previous.push(match old_schema.get(name) {
+            Some(_) => col(name).alias(&format!("previous_{}", name)),
+            None => lit(NULL)
+                .cast(DataType::String)
+                .alias(&format!("previous_{}", name)),
+        });
+    }
+    previous.extend(carried.iter().map(|name| col(name)));
+    let unchanged = text
+        .iter()
+        .map(|name| col(name).eq_missing(col(&format!("previous_{}", name))))
+        .fold(col("matched").fill_null(lit(false)), |a, b| a.and(b));
+
+    let merged = parsed
+        .lazy()
+        .drop(carried.iter().filter(|name| name.as_str() == "embeddings"))
+        .join(
+            old.clone().lazy().select(previous),
+            [col("id")],
+            [col("id")],
+            JoinArgs::new(JoinType::Left),
+        )
+        .with_column(unchanged.alias("unchanged"))
+        .collect()?;
+
+    let matched = merged.column("matched")?.is_not_null().sum().unwrap_or(0) as usize;
+    let unchanged = merged.column("unchanged")?.bool()?.sum().unwrap_or(0) as usize;
+    let kept: Vec<Expr> = carried
+        .iter()
+        .map(|name| {
+            let dtype = old_schema.get(name).unwrap().clone();
+            when(col("unchanged"))
+                .then(col(name))
+                .otherwise(lit(NULL).cast(dtype))
+                .alias(name)
+        })
+        .collect();
+    let mut scratch = vec!["matched".to_string(), "unchanged".to_string()];
+    scratch.extend(text.iter().map(|name| format!("previous_{}", name)));
+    let merged = merged.lazy().with_columns(kept).drop(scratch).collect()?;
+
+    // Rows the parse didn't see, shaped like the merged ones
+    let parsed_ids: HashSet<&str> = merged.column("id")?.str()?.into_iter().flatten().collect();
+    let missing: BooleanChunked = old
+        .column("id")?
+        .str()?
+        .into_iter()
+        .map(|id| !id.is_some_and(|id| parsed_ids.contains(id)))
+        .collect();
+    let shape: Vec<Expr> = merged
+        .schema()
+        .iter()
+        .map(|(name, dtype)| match old_schema.get(name) {
+            Some(_) => col(name).cast(dtype.clone()),
+            None => lit(NULL).cast(dtype.clone()).alias(name),
+        })
+        .collect();
+    let missing = old.filter(&missing)?.lazy().select(shape).collect()?;
+
+    println!(
+        "Merged into {}: {} new, {} changed, {} unchanged, {} kept from before",
+        existing.display(),
+        merged.height() - matched,
+        matched - unchanged,
+        unchanged,
+        missing.height()
+    );
+    merged.vstack(&missing)
+}
@@ -0,0 +1,183 @@
+use crate::{files, patch::Patch, provider::ProviderArgs, routes::Routes, scrub::ScrubArgs};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// Facts about a dataset that don't fit in its columns, kept in a `<dataset>.meta.json` sidecar
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Meta {
+    /// Embedding model as reported by the provider during brew
+    pub model: Option<String>,
+    /// Embedding model of every column brewed alongside `embeddings` with --model
+    pub models: BTreeMap<String, String>,
+    /// Models the rows of some tags were embedded with instead of `model`, naming theirs in a `model` column
+    pub routes: Option<Routes>,
+    /// Provider endpoint the embeddings were requested from
+    pub endpoint: Option<String>,
+    /// Directory of the blob store holding the original documents
+    pub blobs: Option<String>,
+    /// Regexes stripped from bodies before embedding
+    pub boilerplate: Vec<String>,
+    /// How many bodies share a leading or trailing line that brew strips, see --strip-repeated
+    pub strip_repeated: Option<usize>,
+    /// Host the corpus comes from, e.g. physics.stackexchange.com, none when its `site` column names several
+    pub site: Option<String>,
+    /// Link to a document with `{id}` in place of its id
+    pub url_template: Option<String>,
+    /// Redactions applied to the text before it was embedded, by parse-xml or brew, and to queries by search
+    pub scrub: Option<ScrubArgs>,
+    /// Whether brew scaled the vectors to unit length, and search the query vector with them
+    pub normalized: Option<bool>,
+    /// Correction files applied by patch, oldest first
+    pub patches: Vec<Patch>,
+    /// WebAssembly module that prepared the text brew embedded, and prepares queries for search
+    pub plugin: Option<String>,
+    /// Tags of the rows brew embeds, every row when empty, the physics tags of the original corpus when unset
+    pub brew_tags: Option<Vec<String>>,
+}
+
+// Datasets parsed before the site was recorded all come from here
+const LEGACY_SITE: &str = "physics.stackexchange.com";
+
+// Substitute the values of `column` for `{id}` in the template, and of the `site` column for `{site}`
+fn template(template: &str, column: &str) -> Expr {
+    // A dataset of several sites has ids of `<site>/<id>`, links take the id within the site
+    let id = match template.contains("{site}") && column == "id" {
+        true => col(column).str().replace(lit("^[^/]*/"), lit(""), false),
+        false => col(column).cast(DataType::String),
+    };
+    let text = |text: &str| {
+        let mut parts = text.split("{site}");
+        let first = lit(parts.next().unwrap_or_default().to_string());
+        parts.fold(first, |expr, part| {
+            expr + col("site") + lit(part.to_string())
+        })
+    };
+    let (prefix, suffix) = template.split_once("{id}").unwrap_or((template, ""));
+    text(prefix) + id + text(suffix)
+}
+
+impl Meta {
+    fn path(dataset: impl AsRef<Path>) -> PathBuf {
+        let mut path = dataset.as_ref().as_os_str().to_owned();
+        path.push(".meta.json");
+        path.into()
+    }
+
+    /// Load the metadata of `dataset`, empty if it has none
+    pub fn load(dataset: impl AsRef<Path>) -> PolarsResult<Self> {
+        let path = Self::path(dataset);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = files::open(&path)?;
+        serde_json::from_reader(file)
+            .map_err(|e| polars_err!(ComputeError: "invalid metadata in {}: {}", path.display(), e))
+    }
+
+    /// Fetch the metadata of the remote `dataset`, empty if it has none
+    pub async fn fetch(dataset: impl AsRef<Path>) -> PolarsResult<Self> {
+        let path = Self::path(dataset);
+        match files::fetch(&path).await? {
+            Some(bytes) => serde_json::from_slice(&bytes).map_err(
+                |e| polars_err!(ComputeError: "invalid metadata in {}: {}", path.display(), e),
+            ),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self, dataset: impl AsRef<Path>) -> PolarsResult<()> {
+        files::atomic(Self::path(dataset), |file| {
+            serde_json::to_writer_pretty(file, self)
+                .map_err(|e| polars_err!(ComputeError: "failed to write metadata: {}", e))
+        })
+    }
+
+    /// Record where the corpus comes from, `url_template` defaults to the question links of a Stack Exchange site
+    pub fn set_site(&mut self, site: String, url_template: Option<String>) -> PolarsResult<()> {
+        let url_template =
+            url_template.unwrap_or_else(|| format!("https://{}/questions/{{id}}", site));
+        if !url_template.contains("{id}") {
+            polars_bail!(ComputeError: "URL template `{}` has no `{{id}}` placeholder", url_template);
+        }
+        self.site = Some(site);
+        self.url_template = Some(url_template);
+        Ok(())
+    }
+
+    /// Record that the corpus comes from several sites, named in its `site` column
+    pub fn set_sites(&mut self, url_template: Option<String>) -> PolarsResult<()> {
+        let url_template = url_template.unwrap_or_else(|| "https://{site}/questions/{id}".into());
+        if !url_template.contains("{id}") {
+            polars_bail!(ComputeError: "URL template `{}` has no `{{id}}` placeholder", url_template);
+        }
+        self.site = None;
+        self.url_template = Some(url_template);
+        Ok(())
+    }
+
+    /// Link to another site or URL prefix than the recorded ones, for datasets parsed before they were recorded
+    pub fn relink(&mut self, site: Option<String>, url_prefix: Option<String>) -> PolarsResult<()> {
+        let url_template = url_prefix.map(|prefix| format!("{}{{id}}", prefix));
+        match (site, url_template) {
+            (Some(site), url_template) => self.set_site(site, url_template),
+            (None, Some(url_template)) => {
+                self.url_template = Some(url_template);
+                Ok(())
+            }
+            (None, None) => Ok(()),
+        }
+    }
+
+    // Whether the links are built from the `site` column
+    fn several_sites(&self) -> bool {
+        self.url_template
+            .as_ref()
+            .is_some_and(|t| t.contains("{site}"))
+    }
+
+    /// Link to every document
+    pub fn url(&self, schema: &Schema) -> Expr {
+        let url = match &self.url_template {
+            Some(t) => template(t, "id"),
+            None => template(&format!("https://{}/questions/{{id}}", LEGACY_SITE), "id"),
+        };
+        // Documents ingested into a corpus of another source carry links of their own
+        match schema.contains("url") {
+            true => coalesce(&[col("url"), url]),
+            false => url,
+        }
+    }
+
+    /// Link to the profile of the author of every document
+    pub fn author_url(&self) -> Expr {
+        let site = match &self.site {
+            Some(site) => site,
+            None if self.several_sites() => "{site}",
+            None => LEGACY_SITE,
+        };
+        template(&format!("https://{}/users/{{id}}", site), "author_id")
+    }
+
+    /// Make sure query embeddings of `model` from `provider` are comparable with the stored ones
+    pub fn check(&self, provider: &ProviderArgs, model: &str, force: bool) -> PolarsResult<()> {
+        match &self.model {
+            Some(recorded) if recorded == model => Ok(()),
+            _ if force => Ok(()),
+            Some(recorded) => polars_bail!(
+                ComputeError: "dataset was embedded with `{}` but {} serves `{}`, pass --force to use it anyway",
+                recorded, provider.endpoint(), model
+            ),
+            // Datasets brewed before metadata was recorded all come from the default endpoint
+            None if provider.is_default() => Ok(()),
+            None => polars_bail!(
+                ComputeError: "dataset has no metadata confirming its embedding model, pass --force to use {} anyway",
+                provider.endpoint()
+            ),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{lock, meta::Meta, tags, write_dataset};
+use clap::Args;
+use polars::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct MigrateArgs {
+    input: PathBuf,
+    output: PathBuf,
+}
+
+/// Bring a dataset written by an older version up to the current schema
+pub fn migrate(args: MigrateArgs) -> PolarsResult<()> {
+    let _locks = lock::rewrite(&args.input, &args.output)?;
+
+    let lf = LazyFrame::scan_parquet(&args.input, Default::default())?;
+    let schema = lf.schema()?;
+
+    let mut changes = Vec::new();
+    // Ids used to be u32 as parsed from SE dumps, other corpora need paths, UUIDs or arXiv ids
+    match schema.get("id") {
+        Some(DataType::String) => {}
+        Some(dtype) => {
+            changes.push(col("id").cast(DataType::String));
+            println!("Converting `id` from {} to str", dtype);
+        }
+        None => polars_bail!(ColumnNotFound: "dataset has no `id` column"),
+    }
+    // Tags used to be kept as the `<tag-a><tag-b>` string of the dumps, filtered by substring
+    if let Some(DataType::String) = schema.get("tags") {
+        changes.push(tags::column(&schema).alias("tags"));
+        println!("Converting `tags` from str to list[str]");
+    }
+
+    if changes.is_empty() {
+        println!("Dataset is up to date");
+        return Ok(());
+    }
+
+    let mut df = lf.with_columns(changes).collect()?;
+    // Read the metadata before a migration in place replaces the dataset
+    let meta = Meta::load(&args.input)?;
+
+    write_dataset(&mut df, &args.output)?;
+    meta.save(&args.output)?;
+
+    println!("Finished writing");
+
+    Ok(())
+}
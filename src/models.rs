@@ -0,0 +1,94 @@
+use crate::{
+    meta::Meta,
+    provider::{ProviderArgs, MOCK_DIMENSIONS},
+};
+use clap::Args;
+use polars::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct ModelsArgs {
+    #[command(flatten)]
+    provider: ProviderArgs,
+    /// Mark the embedding models compatible with the embeddings stored in this dataset
+    #[arg(long)]
+    dataset: Option<PathBuf>,
+}
+
+// Providers only list model ids, so dimensions and context limits come from the published model cards
+fn known(model: &str) -> (Option<u32>, Option<u32>) {
+    match model {
+        "text-embedding-3-large" => (Some(3072), Some(8191)),
+        "text-embedding-3-small" | "text-embedding-ada-002" => (Some(1536), Some(8191)),
+        "nomic-embed-text" => (Some(768), Some(8192)),
+        "mxbai-embed-large" => (Some(1024), Some(512)),
+        "all-minilm" => (Some(384), Some(256)),
+        "mock" => (Some(MOCK_DIMENSIONS as u32), None),
+        m if m.starts_with("gpt-4o") || m.starts_with("gpt-4-turbo") => (None, Some(128000)),
+        m if m.starts_with("gpt-3.5-turbo") => (None, Some(16385)),
+        m if m.starts_with("gpt-4") => (None, Some(8192)),
+        _ => (None, None),
+    }
+}
+
+/// List the models of a provider and what is known about them
+#[tokio::main]
+pub async fn models(args: ModelsArgs) -> PolarsResult<()> {
+    let Some(mut ids) = args
+        .provider
+        .models()
+        .await
+        .map_err(|e| polars_err!(ComputeError: "failed to list models: {}", e))?
+    else {
+        polars_bail!(ComputeError: "{} has no way to list its models", args.provider.endpoint());
+    };
+    ids.sort();
+
+    let recorded = match &args.dataset {
+        Some(dataset) => Meta::load(dataset)?.model,
+        None => None,
+    };
+
+    // Ollama tags the default variant of a model as `latest`
+    let names: Vec<&str> = ids
+        .iter()
+        .map(|id| id.strip_suffix(":latest").unwrap_or(id))
+        .collect();
+    let kinds: Vec<&str> = names
+        .iter()
+        .map(|n| {
+            if n.contains("embed") || n.contains("minilm") || *n == "mock" {
+                "embedding"
+            } else {
+                "chat"
+            }
+        })
+        .collect();
+    let (dimensions, context): (Vec<_>, Vec<_>) = names.iter().map(|n| known(n)).unzip();
+    let compatible: Vec<Option<bool>> = names
+        .iter()
+        .zip(&kinds)
+        .map(|(n, kind)| match (&recorded, *kind) {
+            (Some(recorded), "embedding") => Some(recorded == n),
+            _ => None,
+        })
+        .collect();
+
+    let mut df = df!(
+        "model" => &ids,
+        "kind" => &kinds,
+        "dimensions" => &dimensions,
+        "context" => &context,
+    )?;
+    if args.dataset.is_some() {
+        if recorded.is_none() {
+            println!("Dataset has no metadata recording its embedding model");
+        }
+        df.with_column(Series::new("compatible", &compatible))?;
+    }
+
+    std::env::set_var("POLARS_FMT_MAX_ROWS", "-1");
+    println!("{}", df);
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use crate::{files, jsonl, jsonl::Documents, scrub::ScrubArgs, store, tags};
+use clap::Args;
+use polars::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Args)]
+pub struct IngestDirArgs {
+    /// A directory searched for Markdown and text files, including its subdirectories
+    input: PathBuf,
+    /// The dataset to write, or its object store URL such as s3://bucket/notes.parquet
+    output: PathBuf,
+    /// Extensions of the files to ingest, may be repeated
+    #[arg(long = "extension", value_name = "EXTENSION", default_values_t = ["md".to_string(), "markdown".to_string(), "txt".to_string()])]
+    extensions: Vec<String>,
+    /// Tag every file with this, besides the names of the subdirectories it is in and the tags of its front
+    /// matter, may be repeated
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Link to a file with `{id}` in place of its path within the input [default: a file:// URL into the input]
+    #[arg(long, value_name = "TEMPLATE")]
+    url_template: Option<String>,
+    #[command(flatten)]
+    scrub: ScrubArgs,
+    /// Overwrite the output instead of merging into it, dropping its embeddings
+    #[arg(long)]
+    replace: bool,
+}
+
+/// A note split into the parts of a document
+struct Note {
+    title: Option<String>,
+    tags: Vec<String>,
+    body: String,
+}
+
+// A value of the front matter without its quotes
+fn unquote(value: &str) -> &str {
+    value.trim().trim_matches(|c| c == '"' || c == '\'')
+}
+
+fn parse(text: &str) -> Note {
+    let mut note = Note {
+        title: None,
+        tags: vec![],
+        body: String::new(),
+    };
+    // The front matter of static site generators and note apps, only its title and tags are read
+    let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut text = text.as_str();
+    if let Some(rest) = text.strip_prefix("---\n") {
+        if let Some(end) = rest.find("\n---") {
+            let mut lines = rest[..end].lines().peekable();
+            while let Some(line) = lines.next() {
+                match line.split_once(':') {
+                    Some(("title", value)) if !unquote(value).is_empty() => {
+                        note.title = Some(unquote(value).to_string())
+                    }
+                    Some(("tags", value)) if !value.trim().is_empty() => {
+                        let value = value.trim().trim_start_matches('[').trim_end_matches(']');
+                        note.tags.extend(
+                            value
+                                .split(',')
+                                .map(unquote)
+                                .filter(|t| !t.is_empty())
+                                .map(String::from),
+                        );
+                    }
+                    // A list of tags given one per line
+                    Some(("tags", _)) => {
+                        while let Some(item) =
+                            lines.peek().and_then(|l| l.trim().strip_prefix("- "))
+                        {
+                            note.tags.push(unquote(item).to_string());
+                            lines.next();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            text = rest[end + 4..].trim_start_matches('-');
+        }
+    }
+
+    // The first heading stands for the title when there is none, `# Title` or underlined with `===`
+    let lines: Vec<&str> = text.lines().collect();
+    let first = lines.iter().position(|l| !l.trim().is_empty());
+    let mut skip = 0;
+    if let Some(i) = first {
+        let line = lines[i].trim();
+        if let Some(heading) = line.strip_prefix("# ") {
+            skip = i + 1;
+            note.title.get_or_insert_with(|| heading.trim().to_string());
+        } else if lines
+            .get(i + 1)
+            .is_some_and(|l| l.trim().len() >= 3 && l.trim().chars().all(|c| c == '='))
+        {
+            skip = i + 2;
+            note.title.get_or_insert_with(|| line.to_string());
+        }
+    }
+    note.body = lines[skip..].join("\n").trim().to_string();
+    note
+}
+
+/// Ingest a directory of notes such as a Markdown wiki into a dataset brew and search take like a parsed dump,
+/// a row per file titled by its front matter, its first heading or its name
+pub fn ingest(args: IngestDirArgs) -> PolarsResult<()> {
+    // Ingested into a local copy of the output, to merge into what is already stored
+    if files::remote(&args.output) {
+        let url = args.output.clone();
+        return store::staged(&url, |local| {
+            ingest(IngestDirArgs {
+                output: local.to_path_buf(),
+                ..args
+            })
+        });
+    }
+    if !args.input.is_dir() {
+        polars_bail!(ComputeError: "{} is not a directory", args.input.display());
+    }
+    let extensions: Vec<&str> = args
+        .extensions
+        .iter()
+        .map(|e| e.trim_start_matches('.'))
+        .collect();
+    let (root, paths) = files::find(&args.input, &extensions)?;
+    if paths.is_empty() {
+        polars_bail!(ComputeError: "no .{} files in {}", extensions.join(", ."), args.input.display());
+    }
+    let url_template = match args.url_template {
+        Some(ref template) => template.clone(),
+        None => files::url_template(&root)?,
+    };
+
+    let mut documents = Documents::new(&args.scrub, false)?;
+    let mut empty = 0;
+    for path in &paths {
+        let relative = path.strip_prefix(&root).unwrap_or(path);
+        let bytes = files::read(path)?;
+        let note = parse(&String::from_utf8_lossy(&bytes));
+        if note.body.is_empty() {
+            empty += 1;
+            continue;
+        }
+        let title = note.title.unwrap_or_else(|| {
+            path.file_stem()
+                .map(|s| s.to_string_lossy().replace(['-', '_'], " "))
+                .unwrap_or_default()
+        });
+        let mut tags = args.tags.clone();
+        tags.extend(tags::of_directories(relative));
+        tags.extend(note.tags.iter().flat_map(|t| jsonl::split_tags(t)));
+        tags.sort();
+        tags.dedup();
+        let id = relative.to_string_lossy().replace('\\', "/");
+        documents.push(id, title, &note.body, tags);
+    }
+    println!("Read {} files", paths.len());
+    if empty > 0 {
+        println!("Skipped {} empty files", empty);
+    }
+    documents.write(&args.output, Some(url_template), &args.scrub, args.replace)
+}
@@ -0,0 +1,189 @@
+use crate::{files, lock, meta::Meta};
+use clap::Args;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+// Sidecars travelling with a dataset, the lock and the journal only matter to its writer, see store.rs for the
+// journal of a remote one
+pub const SIDECARS: &[&str] = &[
+    ".meta.json",
+    ".tokens.parquet",
+    ".vocab.parquet",
+    ".answers.parquet",
+    ".manifest.json",
+    ".tombstones.json",
+    ".audit.jsonl",
+];
+const MANIFEST: &str = "manifest.json";
+const BLOBS: &str = "blobs";
+const LEVEL: i32 = 19;
+
+#[derive(Args)]
+pub struct PackArgs {
+    input: PathBuf,
+    /// Artifact to write, e.g. corpus.adapack
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct UnpackArgs {
+    /// Artifact written by pack-release
+    input: PathBuf,
+    /// Directory to unpack the dataset into
+    #[arg(short, long, default_value = ".")]
+    dir: PathBuf,
+    /// Replace a dataset of the same name already in the directory
+    #[arg(long)]
+    force: bool,
+}
+
+/// Contents of an artifact, written first so that unpack can check every file against it
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    /// Version of ada that packed the dataset
+    version: String,
+    /// File name of the dataset, the sidecars are named after it
+    dataset: String,
+    /// SHA-256 of every file by its path in the artifact
+    files: BTreeMap<String, String>,
+}
+
+fn sidecar(dataset: &Path, suffix: &str) -> PathBuf {
+    let mut path = dataset.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// Bundle a dataset, its sidecars and the original documents it links to into one compressed artifact
+pub fn pack(args: PackArgs) -> PolarsResult<()> {
+    let _lock = lock::Lock::shared(&args.input)?;
+    let Some(dataset) = args.input.file_name().and_then(|n| n.to_str()) else {
+        polars_bail!(ComputeError: "{} does not name a dataset file", args.input.display());
+    };
+
+    let mut contents = vec![(args.input.clone(), dataset.to_string())];
+    for suffix in SIDECARS {
+        let path = sidecar(&args.input, suffix);
+        if path.exists() {
+            contents.push((path, format!("{}{}", dataset, suffix)));
+        }
+    }
+    // Only the documents of this dataset, the store may be shared with others
+    let meta = Meta::load(&args.input)?;
+    let lf = LazyFrame::scan_parquet(&args.input, Default::default())?;
+    if let (Some(dir), true) = (&meta.blobs, lf.schema()?.contains("blob")) {
+        let df = lf.select([col("blob").unique()]).collect()?;
+        for hash in df.column("blob")?.str()?.into_iter().flatten() {
+            let name = format!("{}/{}/{}", BLOBS, &hash[..2], hash);
+            contents.push((Path::new(dir).join(&hash[..2]).join(hash), name));
+        }
+    }
+
+    let mut manifest = Manifest {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        dataset: dataset.to_string(),
+        files: BTreeMap::new(),
+    };
+    for (path, name) in &contents {
+        manifest.files.insert(name.clone(), files::sha256(path)?);
+    }
+    let manifest = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| polars_err!(ComputeError: "failed to write the manifest: {}", e))?;
+
+    files::atomic(&args.output, |file| {
+        let mut tar = tar::Builder::new(zstd::Encoder::new(file, LEVEL)?);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, MANIFEST, manifest.as_slice())?;
+        for (path, name) in &contents {
+            tar.append_file(name, &mut files::open(path)?)?;
+        }
+        tar.into_inner()?.finish()?;
+        Ok(())
+    })?;
+
+    println!(
+        "Packed {} files into {}",
+        contents.len(),
+        args.output.display()
+    );
+
+    Ok(())
+}
+
+/// Unpack an artifact written by pack-release, checking every file against its manifest
+pub fn unpack(args: UnpackArgs) -> PolarsResult<()> {
+    let invalid = |reason: &str| polars_err!(ComputeError: "{} is not a valid artifact: {}", args.input.display(), reason);
+    let mut archive = tar::Archive::new(zstd::Decoder::new(files::open(&args.input)?)?);
+    let mut entries = archive.entries()?;
+
+    let mut manifest = String::new();
+    match entries.next() {
+        Some(entry)
+            if entry
+                .as_ref()
+                .is_ok_and(|e| e.path().is_ok_and(|p| p == Path::new(MANIFEST))) =>
+        {
+            entry?.read_to_string(&mut manifest)?;
+        }
+        _ => return Err(invalid("it does not start with a manifest")),
+    }
+    let manifest: Manifest =
+        serde_json::from_str(&manifest).map_err(|e| invalid(&e.to_string()))?;
+
+    let dataset = args.dir.join(&manifest.dataset);
+    if dataset.exists() && !args.force {
+        polars_bail!(ComputeError: "{} already exists, pass --force to replace it", dataset.display());
+    }
+    files::create_dir_all(&args.dir)?;
+    let _lock = lock::Lock::exclusive(&dataset)?;
+
+    let mut unpacked = 0;
+    for entry in entries {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let Some(expected) = manifest.files.get(&name) else {
+            return Err(invalid(&format!("`{}` is not in the manifest", name)));
+        };
+        // unpack_in refuses paths escaping the directory
+        if !entry.unpack_in(&args.dir)? {
+            return Err(invalid(&format!(
+                "`{}` points outside of the directory",
+                name
+            )));
+        }
+        if files::sha256(&args.dir.join(&name))? != *expected {
+            polars_bail!(ComputeError: "`{}` is corrupted, its checksum does not match the manifest", name);
+        }
+        unpacked += 1;
+    }
+    if unpacked != manifest.files.len() {
+        return Err(invalid("files listed in the manifest are missing"));
+    }
+
+    // Point the dataset at the original documents unpacked next to it
+    let mut meta = Meta::load(&dataset)?;
+    if meta.blobs.is_some() {
+        let dir = std::fs::canonicalize(args.dir.join(BLOBS))
+            .map_err(|e| files::with_path(e, &args.dir.join(BLOBS)))?;
+        meta.blobs = Some(dir.display().to_string());
+        meta.save(&dataset)?;
+    }
+
+    println!(
+        "Unpacked {} packed by ada {} into {}",
+        manifest.dataset,
+        manifest.version,
+        args.dir.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use crate::{dedup, embeddings, files, graph, lock, meta::Meta, routes, tombstones, vectors};
+use clap::Args;
+use polars::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+#[derive(Args)]
+pub struct ExportPairsArgs {
+    input: PathBuf,
+    /// JSON Lines file of `{"anchor": ..., "positive": ...}` pairs, as sentence-transformers loads for
+    /// MultipleNegativesRankingLoss
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Lowest similarity of the nearest neighbours paired with a question
+    #[arg(long, default_value_t = 0.9)]
+    min_similarity: f32,
+    /// Number of nearest neighbours considered for every question
+    #[arg(short, default_value_t = 1)]
+    k: usize,
+    /// Only pair the questions closed as duplicates with their original, see parse-xml --links
+    #[arg(long)]
+    no_similar: bool,
+    /// Convert embeddings stored with a foreign dtype (e.g. list[f64]) to list[f32] instead of failing
+    #[arg(long)]
+    cast_embeddings: bool,
+}
+
+/// Export pairs of questions asking the same thing as contrastive training data, the title of one as the anchor
+/// and the title and body of the other as the positive: duplicates linked by the dump, then nearest neighbours
+pub fn export_pairs(args: ExportPairsArgs) -> PolarsResult<()> {
+    let _lock = lock::Lock::shared(&args.input)?;
+    let lf = tombstones::live(
+        &args.input,
+        dedup::resolve(LazyFrame::scan_parquet(&args.input, Default::default())?)?,
+    )?;
+    let schema = lf.schema()?;
+    let links = schema.contains("duplicate_of");
+    if !links && args.no_similar {
+        polars_bail!(ColumnNotFound: "dataset has no `duplicate_of` column, parse it with --links to pair duplicates");
+    }
+    let mut columns = vec![col("id").cast(DataType::String), col("title"), col("body")];
+    if links {
+        columns.push(col("duplicate_of").cast(DataType::String));
+    }
+    if !args.no_similar {
+        let embeddings = embeddings(&schema, args.cast_embeddings)?;
+        let meta = Meta::load(&args.input)?;
+        routes::single_model(lf.clone(), embeddings.clone(), &meta, "export-pairs")?;
+        columns.push(embeddings.alias("embeddings"));
+    }
+    let df = lf.select(columns).collect()?;
+
+    let titles = df.column("title")?.str()?;
+    let bodies = df.column("body")?.str()?;
+    let text = |i: usize| {
+        format!(
+            "{}\n\n{}",
+            titles.get(i).unwrap_or_default(),
+            bodies.get(i).unwrap_or_default()
+        )
+    };
+
+    // A pair is kept once whichever way round it was found
+    let mut pairs = vec![];
+    let mut seen = HashSet::new();
+    let mut pair = |anchor: usize, positive: usize| {
+        let key = (anchor.min(positive), anchor.max(positive));
+        if anchor != positive && seen.insert(key) {
+            pairs.push((anchor, positive));
+            return true;
+        }
+        false
+    };
+
+    let mut duplicates = 0;
+    if links {
+        let ids = df.column("id")?.str()?;
+        let index: HashMap<&str, usize> = ids
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, id)| Some((id?, i)))
+            .collect();
+        for (i, original) in df.column("duplicate_of")?.str()?.into_iter().enumerate() {
+            if let Some(&j) = original.and_then(|id| index.get(id)) {
+                duplicates += usize::from(pair(i, j));
+            }
+        }
+    }
+
+    let mut similar = 0;
+    if !args.no_similar {
+        let (rows, vectors): (Vec<usize>, Vec<Vec<f32>>) = vectors(&df, "embeddings")?
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, v)| Some((i, v?)))
+            .unzip();
+        if vectors.is_empty() {
+            println!("No question is embedded yet, only pairing duplicates");
+        }
+        for (i, neighbours) in graph::knn(&vectors, args.k).into_iter().enumerate() {
+            for (j, score) in neighbours {
+                // Copies of the same text teach the model nothing
+                if score >= args.min_similarity && text(rows[i]) != text(rows[j]) {
+                    similar += usize::from(pair(rows[i], rows[j]));
+                }
+            }
+        }
+    }
+
+    let mut w = BufWriter::new(files::create(&args.output)?);
+    for &(anchor, positive) in &pairs {
+        let line = serde_json::json!({
+            "anchor": titles.get(anchor).unwrap_or_default(),
+            "positive": text(positive),
+        });
+        writeln!(w, "{}", line)?;
+    }
+    w.flush()?;
+
+    println!(
+        "Wrote {} pairs to {}: {} of duplicates, {} of neighbours",
+        pairs.len(),
+        args.output.display(),
+        duplicates,
+        similar
+    );
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use crate::{audit, files, lock, merge, meta::Meta, tags, write_dataset};
+use clap::Args;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::PathBuf,
+};
+
+#[derive(Args)]
+pub struct PatchArgs {
+    input: PathBuf,
+    /// JSON Lines of corrections, each an object with the `id` of a row and the new value of every field to fix,
+    /// e.g. {"id": "42", "title": "Why is the sky blue?", "tags": ["optics"]}
+    updates: PathBuf,
+}
+
+/// A correction file applied to a dataset, recorded in its metadata
+#[derive(Serialize, Deserialize)]
+pub struct Patch {
+    pub file: String,
+    pub sha256: String,
+    /// When it was applied, in UTC
+    pub applied: String,
+    /// The rows it changed, by field
+    pub rows: BTreeMap<String, usize>,
+    /// The rows whose embeddings it left to brew again
+    pub stale: usize,
+}
+
+// The corrections of every field by id, a later line wins over an earlier one
+fn read(args: &PatchArgs) -> PolarsResult<BTreeMap<String, HashMap<String, Value>>> {
+    let mut fields: BTreeMap<String, HashMap<String, Value>> = BTreeMap::new();
+    let text = files::read_to_string(&args.updates)?;
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |e: &dyn std::fmt::Display| polars_err!(ComputeError: "{}:{}: {}", args.updates.display(), n + 1, e);
+        let mut update: Map<String, Value> = serde_json::from_str(line).map_err(|e| invalid(&e))?;
+        let id = match update.remove("id") {
+            Some(Value::String(id)) => id,
+            Some(Value::Number(id)) => id.to_string(),
+            _ => return Err(invalid(&"expected an `id`")),
+        };
+        for (field, value) in update {
+            if field.starts_with("embeddings") {
+                return Err(invalid(&format!(
+                    "`{}` can't be patched, brew computes it",
+                    field
+                )));
+            }
+            fields.entry(field).or_default().insert(id.clone(), value);
+        }
+    }
+    Ok(fields)
+}
+
+// The values of a list column such as `tags`, given as arrays, or for tags as the `<tag-a><tag-b>` of the dumps
+fn list(field: &str, values: &[Option<&Value>], inner: &DataType) -> PolarsResult<Series> {
+    let new = values
+        .iter()
+        .map(|value| {
+            let items: Vec<Option<String>> = match value {
+                None | Some(Value::Null) => return Ok(None),
+                Some(Value::Array(items)) => items
+                    .iter()
+                    .map(|item| match item {
+                        Value::Null => None,
+                        Value::String(s) => Some(s.clone()),
+                        v => Some(v.to_string()),
+                    })
+                    .collect(),
+                Some(Value::String(s)) if field == "tags" => {
+                    tags::split(s).map(|tag| Some(tag.to_string())).collect()
+                }
+                Some(v) => {
+                    polars_bail!(ComputeError: "`{}` takes a list, not {}", field, v)
+                }
+            };
+            Ok(Some(Series::new("", items).strict_cast(inner)?))
+        })
+        .collect::<PolarsResult<Vec<_>>>()?;
+    // Rows left alone are all null, which doesn't tell the list what it holds
+    Series::new(field, new).cast(&DataType::List(Box::new(inner.clone())))
+}
+
+/// Apply field-level corrections by id, leaving the embeddings of rows whose text changed for brew to redo
+pub fn patch(args: PatchArgs) -> PolarsResult<()> {
+    let _locks = lock::rewrite(&args.input, &args.input)?;
+    let fields = read(&args)?;
+    let mut df = LazyFrame::scan_parquet(&args.input, Default::default())?.collect()?;
+    let schema = df.schema();
+    if let Some(field) = fields.keys().find(|field| !schema.contains(field)) {
+        polars_bail!(ColumnNotFound: "{} has no `{}` column to patch", args.input.display(), field);
+    }
+
+    let ids = df.column("id")?.cast(&DataType::String)?;
+    let ids = ids.str()?;
+    let known: BTreeSet<&str> = ids.into_iter().flatten().collect();
+    let unknown: BTreeSet<&str> = fields
+        .values()
+        .flat_map(|values| values.keys())
+        .map(String::as_str)
+        .filter(|id| !known.contains(id))
+        .collect();
+
+    let mut rows = BTreeMap::new();
+    let mut stale = BooleanChunked::full("stale", false, df.height());
+    let mut touched = BooleanChunked::full("touched", false, df.height());
+    for (field, values) in &fields {
+        let old = df.column(field)?.clone();
+        let mut patched = Vec::with_capacity(df.height());
+        let mut new = Vec::with_capacity(df.height());
+        for id in ids {
+            let value = id.and_then(|id| values.get(id));
+            patched.push(value.is_some());
+            new.push(value);
+        }
+        let new = match old.dtype() {
+            DataType::List(inner) => list(field, &new, inner)?,
+            dtype => {
+                let new: Vec<Option<String>> = new
+                    .into_iter()
+                    .map(|value| match value {
+                        None | Some(Value::Null) => None,
+                        Some(Value::String(s)) => Some(s.clone()),
+                        Some(v) => Some(v.to_string()),
+                    })
+                    .collect();
+                // Values are cast the way Polars casts strings, so a typo in a number fails rather than nulls it
+                Series::new(field, new).strict_cast(dtype)?
+            }
+        };
+        let patched = BooleanChunked::new(field, patched);
+        let changed = &patched & &!new.equal_missing(&old)?;
+        rows.insert(field.clone(), changed.sum().unwrap_or(0) as usize);
+        touched = &touched | &changed;
+        if merge::TEXT.contains(&field.as_str()) {
+            stale = &stale | &changed;
+        }
+        df.with_column(new.zip_with(&patched, &old)?)?;
+    }
+
+    let embeddings: Vec<String> = schema
+        .iter_names()
+        .filter(|name| name.starts_with("embeddings"))
+        .map(|name| name.to_string())
+        .collect();
+    for name in embeddings {
+        let old = df.column(&name)?;
+        let none = Series::full_null(&name, df.height(), old.dtype());
+        let kept = old.zip_with(&!&stale, &none)?;
+        df.with_column(kept)?;
+    }
+    let stale = stale.sum().unwrap_or(0) as usize;
+    audit::changed(&args.input, touched.sum().unwrap_or(0) as usize);
+    write_dataset(&mut df, &args.input)?;
+
+    let mut meta = Meta::load(&args.input)?;
+    meta.patches.push(Patch {
+        file: args.updates.display().to_string(),
+        sha256: files::sha256(&args.updates)?,
+        applied: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        rows: rows.clone(),
+        stale,
+    });
+    meta.save(&args.input)?;
+
+    for (field, n) in &rows {
+        println!("Patched {} in {} rows", field, n);
+    }
+    println!("{} rows to brew again", stale);
+    if !unknown.is_empty() {
+        println!("Skipped {} ids that are not in the dataset", unknown.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use crate::{files, jsonl::Documents, scrub::ScrubArgs, store, tags};
+use clap::{Args, ValueEnum};
+use polars::prelude::*;
+use regex::Regex;
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Split {
+    /// A row of every page
+    Page,
+    /// A row of every numbered section, such as `2.1 Boundary conditions`, and of the text before the first
+    Section,
+}
+
+#[derive(Args)]
+pub struct IngestPdfArgs {
+    /// A directory searched for PDF files, including its subdirectories, or a single PDF file
+    input: PathBuf,
+    /// The dataset to write, or its object store URL such as s3://bucket/notes.parquet
+    output: PathBuf,
+    #[arg(long, value_enum, default_value_t = Split::Page)]
+    split: Split,
+    /// Tag every row with this, besides the names of the subdirectories its file is in, may be repeated
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Skip rows of fewer characters, such as blank pages and title pages
+    #[arg(long, default_value_t = 200)]
+    min_chars: usize,
+    /// Link to a row with `{id}` in place of its id, the path of its file within the input followed by
+    /// `#page=<n>` [default: a file:// URL into the input directory]
+    #[arg(long, value_name = "TEMPLATE")]
+    url_template: Option<String>,
+    #[command(flatten)]
+    scrub: ScrubArgs,
+    /// Overwrite the output instead of merging into it, dropping its embeddings
+    #[arg(long)]
+    replace: bool,
+}
+
+// The text of every page of `pdf`, extracted by pdftotext of poppler-utils, which ends pages with a form feed
+fn pages(pdf: &Path) -> PolarsResult<Vec<String>> {
+    let output = Command::new("pdftotext")
+        .args(["-enc", "UTF-8", "-q"])
+        .arg(files::long(pdf))
+        .arg("-")
+        .output()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => polars_err!(ComputeError: "pdftotext was not found, install poppler-utils to ingest PDF files"),
+            _ => files::with_path(e, pdf),
+        })?;
+    if !output.status.success() {
+        polars_bail!(ComputeError: "pdftotext failed on {}: {}", pdf.display(), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut pages: Vec<String> = text.split('\x0c').map(String::from).collect();
+    // The last page ends with a form feed too
+    if pages.last().is_some_and(|p| p.trim().is_empty()) {
+        pages.pop();
+    }
+    Ok(pages)
+}
+
+// Lines broken to the width of the page are joined back into paragraphs, hyphenated words made whole again
+fn paragraphs(text: &str) -> String {
+    let mut out = String::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            if !out.is_empty() && !out.ends_with("\n\n") {
+                out.push_str("\n\n");
+            }
+            continue;
+        }
+        if out.ends_with('-') && !out.ends_with(" -") {
+            out.pop();
+        } else if !out.is_empty() && !out.ends_with('\n') {
+            out.push(' ');
+        }
+        out.push_str(line);
+    }
+    out.trim().to_string()
+}
+
+/// Extract the text of PDF files such as lecture notes and papers into a dataset brew and search take like a
+/// parsed dump, a row per page or per section
+pub fn ingest(args: IngestPdfArgs) -> PolarsResult<()> {
+    // Ingested into a local copy of the output, to merge into what is already stored
+    if files::remote(&args.output) {
+        let url = args.output.clone();
+        return store::staged(&url, |local| {
+            ingest(IngestPdfArgs {
+                output: local.to_path_buf(),
+                ..args
+            })
+        });
+    }
+    let (root, pdfs) = files::find(&args.input, &["pdf"])?;
+    if pdfs.is_empty() {
+        polars_bail!(ComputeError: "no PDF files in {}", args.input.display());
+    }
+    let url_template = match args.url_template {
+        Some(ref template) => template.clone(),
+        None => files::url_template(&root)?,
+    };
+    // Numbered headings on a line of their own, e.g. `3 Results` or `2.1. Boundary conditions`
+    let heading = Regex::new(r"^(\d{1,2}(?:\.\d{1,2})*)\.?\s+(\p{Lu}[^.:;]{1,80})$").unwrap();
+
+    let mut documents = Documents::new(&args.scrub, false)?;
+    let mut short = 0;
+    for pdf in &pdfs {
+        let relative = pdf.strip_prefix(&root).unwrap_or(pdf);
+        let id = relative.to_string_lossy().replace('\\', "/");
+        let name = pdf
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut tags = args.tags.clone();
+        tags.extend(tags::of_directories(relative));
+
+        // Every row as its id suffix, title and text
+        let mut rows: Vec<(String, String, String)> = vec![];
+        let pages = pages(pdf)?;
+        match args.split {
+            Split::Page => {
+                for (n, page) in pages.iter().enumerate() {
+                    rows.push((
+                        format!("#page={}", n + 1),
+                        format!("{}, page {}", name, n + 1),
+                        paragraphs(page),
+                    ));
+                }
+            }
+            Split::Section => {
+                let (mut page, mut title, mut text) = (1, name.clone(), String::new());
+                for (n, content) in pages.iter().enumerate() {
+                    for line in content.lines() {
+                        match heading.captures(line.trim()) {
+                            Some(captures) => {
+                                rows.push((format!("#page={}", page), title, paragraphs(&text)));
+                                page = n + 1;
+                                title = format!("{}, {} {}", name, &captures[1], &captures[2]);
+                                text.clear();
+                            }
+                            None => {
+                                text.push_str(line);
+                                text.push('\n');
+                            }
+                        }
+                    }
+                }
+                rows.push((format!("#page={}", page), title, paragraphs(&text)));
+                // Sections starting on the same page need ids of their own
+                for (i, row) in rows.iter_mut().enumerate() {
+                    row.0.push_str(&format!("&section={}", i));
+                }
+            }
+        }
+        let found = rows.len();
+        for (suffix, title, text) in rows {
+            if text.chars().count() < args.min_chars {
+                short += 1;
+                continue;
+            }
+            documents.push(format!("{}{}", id, suffix), title, &text, tags.clone());
+        }
+        println!("Extracted {} rows from {}", found, pdf.display());
+    }
+    if short > 0 {
+        println!(
+            "Skipped {} rows shorter than {} characters",
+            short, args.min_chars
+        );
+    }
+    documents.write(&args.output, Some(url_template), &args.scrub, args.replace)
+}
@@ -0,0 +1,320 @@
+use crate::{date, dot, files, tags, trace::Trace, vectors};
+use polars::{lazy::dsl::GetOutput, prelude::*};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Retrieval flow of search, read from a JSON file such as
+///
+/// ```json
+/// {
+///   "candidates": { "k": 200 },
+///   "filters": { "tags": ["quantum-mechanics"], "min_score": 1, "min_reputation": 100 },
+///   "rerank": { "weights": { "similarity": 1.0, "quality": 0.2, "score": 0.05 }, "mmr": 0.7 }
+/// }
+/// ```
+///
+/// Every stage is optional, the default ranks all rows by similarity alone
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Pipeline {
+    candidates: Candidates,
+    filters: Filters,
+    rerank: Rerank,
+    // Added by the search flags, such as --tag-boost and --quality-weight
+    #[serde(skip)]
+    boost: Option<Expr>,
+    // Where to write the trace of a query, see --trace-retrieval
+    #[serde(skip)]
+    trace: Option<(PathBuf, String)>,
+}
+
+/// Nearest neighbours of the query that the later stages work on
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Candidates {
+    k: Option<usize>,
+}
+
+/// Candidates to drop, every condition must hold for a candidate to be kept
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Filters {
+    /// Keep questions carrying any of these tags
+    tags: Vec<String>,
+    min_score: Option<i64>,
+    min_quality: Option<f64>,
+    /// Of the author, needs a dataset parsed with --users
+    min_reputation: Option<i64>,
+    min_views: Option<i64>,
+    /// Keep questions asked on or after this date, e.g. "2020-01-01"
+    after: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Rerank {
+    weights: Weights,
+    /// Trade relevance (1) for diversity (0) with maximal marginal relevance
+    mmr: Option<f64>,
+}
+
+/// Weights of the signals summed into the final score
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Weights {
+    similarity: f64,
+    quality: f64,
+    /// Applied to ln(1 + score), votes vary over orders of magnitude
+    score: f64,
+    /// Applied to ln(1 + reputation) of the author, for the same reason
+    reputation: f64,
+    /// Applied to ln(1 + view count), for the same reason
+    views: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            similarity: 1.0,
+            quality: 0.0,
+            score: 0.0,
+            reputation: 0.0,
+            views: 0.0,
+        }
+    }
+}
+
+// The similarity before reranking, kept while tracing
+const RAW: &str = "similarity_before_rerank";
+
+fn sorted(lf: LazyFrame) -> LazyFrame {
+    lf.sort(
+        "similarity",
+        SortOptions {
+            descending: true,
+            nulls_last: true,
+            ..Default::default()
+        },
+    )
+}
+
+fn views(schema: &Schema) -> PolarsResult<Expr> {
+    match schema.contains("view_count") {
+        true => Ok(col("view_count")),
+        false => {
+            polars_bail!(ColumnNotFound: "dataset has no `view_count` column, parse it again to record the views")
+        }
+    }
+}
+
+fn reputation(schema: &Schema) -> PolarsResult<Expr> {
+    match schema.contains("reputation") {
+        true => Ok(col("reputation")),
+        false => {
+            polars_bail!(ColumnNotFound: "dataset has no `reputation` column, parse it with --users Users.xml")
+        }
+    }
+}
+
+// Damp counts that vary over orders of magnitude, negative ones count as 0
+fn ln_1p(counts: Expr) -> Expr {
+    counts.cast(DataType::Float64).map(
+        |s| {
+            Ok(Some(
+                s.f64()?.apply_values(|v| v.max(0.0).ln_1p()).into_series(),
+            ))
+        },
+        GetOutput::from_type(DataType::Float64),
+    )
+}
+
+impl Pipeline {
+    /// Read and check the pipeline at `path`, before any query is embedded
+    pub fn load(path: &Path) -> PolarsResult<Self> {
+        let pipeline: Self = serde_json::from_slice(&files::read(path)?).map_err(
+            |e| polars_err!(ComputeError: "invalid pipeline in {}: {}", path.display(), e),
+        )?;
+        if let Some(ref after) = pipeline.filters.after {
+            date(after).map_err(|e| polars_err!(ComputeError: "{}: {}", path.display(), e))?;
+        }
+        match pipeline.rerank.mmr {
+            Some(l) if !(0.0..=1.0).contains(&l) => {
+                polars_bail!(ComputeError: "{}: mmr must be between 0 and 1", path.display())
+            }
+            // Diversifying every row would be quadratic in the size of the dataset
+            Some(_) if pipeline.candidates.k.is_none() => {
+                polars_bail!(ComputeError: "{}: mmr needs a number of candidates `k`", path.display())
+            }
+            _ => Ok(pipeline),
+        }
+    }
+
+    /// Check that the dataset has the columns and tags the stages need, before the query is embedded
+    pub fn check(&self, lf: LazyFrame, schema: &Schema) -> PolarsResult<()> {
+        // Building the stages is enough, they fail on the columns they miss
+        let _ = self.filter(lf.clone(), schema)?;
+        let _ = self.score(schema)?;
+        let tags = self.filters.tags.iter().map(String::as_str);
+        tags::check(lf, tags, "the filters of the pipeline")
+    }
+
+    /// Add `boost` to the final score
+    pub fn boost(mut self, boost: Expr) -> Self {
+        self.boost = Some(boost);
+        self
+    }
+
+    /// Record what every stage does with the rows of `query` to `path`
+    pub fn trace(mut self, path: PathBuf, query: &str) -> Self {
+        self.trace = Some((path, query.to_string()));
+        self
+    }
+
+    /// Rank the rows of `lf` from their similarity to the query through every stage
+    pub fn run(&self, lf: LazyFrame, schema: &Schema, embeddings: Expr) -> PolarsResult<LazyFrame> {
+        let Some((ref path, ref query)) = self.trace else {
+            let lf = self.filter(self.candidates(lf), schema)?;
+            return self.diversify(sorted(lf.with_column(self.score(schema)?)), embeddings);
+        };
+
+        // Every stage is collected to see what it did
+        let mut trace = Trace::new(query);
+        let all = lf.collect()?;
+        let candidates = self.candidates(all.clone().lazy()).collect()?;
+        trace.candidates(self.candidates.k, all.height(), &candidates)?;
+        let kept = self.filter(candidates.clone().lazy(), schema)?.collect()?;
+        trace.filters(&candidates, &kept)?;
+        let ranked = kept
+            .lazy()
+            .with_columns([col("similarity").alias(RAW), self.score(schema)?]);
+        let ranked = sorted(ranked).collect()?;
+        trace.rerank(&ranked, RAW)?;
+        let ranked = match self.rerank.mmr {
+            Some(_) => {
+                let diverse = self.diversify(ranked.lazy(), embeddings)?.collect()?;
+                trace.mmr(&diverse, RAW)?;
+                diverse
+            }
+            None => ranked,
+        };
+        trace.write(path)?;
+        Ok(ranked.drop(RAW)?.lazy())
+    }
+
+    /// Keep the `k` rows most similar to the query
+    fn candidates(&self, lf: LazyFrame) -> LazyFrame {
+        match self.candidates.k {
+            Some(k) => sorted(lf).limit(k as IdxSize),
+            None => lf,
+        }
+    }
+
+    fn filter(&self, lf: LazyFrame, schema: &Schema) -> PolarsResult<LazyFrame> {
+        let Filters {
+            tags,
+            min_score,
+            min_quality,
+            min_reputation,
+            min_views,
+            after,
+        } = &self.filters;
+        let mut kept = lit(true);
+        if !tags.is_empty() {
+            kept = kept.and(tags::any(schema, tags.iter().map(String::as_str)));
+        }
+        if let Some(min) = min_score {
+            kept = kept.and(col("score").gt_eq(lit(*min)));
+        }
+        if let Some(min) = min_quality {
+            if !schema.contains("quality") {
+                polars_bail!(ColumnNotFound: "dataset has no `quality` column, run the quality subcommand first");
+            }
+            kept = kept.and(col("quality").gt_eq(lit(*min)));
+        }
+        if let Some(min) = min_reputation {
+            kept = kept.and(reputation(schema)?.gt_eq(lit(*min)));
+        }
+        if let Some(min) = min_views {
+            kept = kept.and(views(schema)?.gt_eq(lit(*min)));
+        }
+        if let Some(after) = after {
+            if !schema.contains("creation_date") {
+                polars_bail!(ColumnNotFound: "dataset has no `creation_date` column, parse it again to record the dates");
+            }
+            let after = date(after).map_err(|e| polars_err!(ComputeError: "{}", e))?;
+            kept = kept.and(col("creation_date").gt_eq(lit(after)));
+        }
+        Ok(lf.filter(kept))
+    }
+
+    /// The final score of a candidate from its similarity and the weighted signals
+    fn score(&self, schema: &Schema) -> PolarsResult<Expr> {
+        let Weights {
+            similarity,
+            quality,
+            score,
+            reputation: weight,
+            views: views_weight,
+        } = self.rerank.weights;
+        let mut expr = lit(similarity) * col("similarity");
+        if quality != 0.0 {
+            if !schema.contains("quality") {
+                polars_bail!(ColumnNotFound: "dataset has no `quality` column, run the quality subcommand first");
+            }
+            expr = expr + lit(quality) * col("quality").fill_null(lit(0.0));
+        }
+        if score != 0.0 {
+            expr = expr + lit(score) * ln_1p(col("score"));
+        }
+        if weight != 0.0 {
+            expr = expr + lit(weight) * ln_1p(reputation(schema)?).fill_null(lit(0.0));
+        }
+        if views_weight != 0.0 {
+            expr = expr + lit(views_weight) * ln_1p(views(schema)?).fill_null(lit(0.0));
+        }
+        if let Some(ref boost) = self.boost {
+            expr = expr + boost.clone();
+        }
+        Ok(expr.alias("similarity"))
+    }
+
+    /// Reorder ranked rows by maximal marginal relevance, penalizing results close to those already picked
+    fn diversify(&self, lf: LazyFrame, embeddings: Expr) -> PolarsResult<LazyFrame> {
+        let Some(lambda) = self.rerank.mmr else {
+            return Ok(lf);
+        };
+        let df = lf.with_column(embeddings.alias("mmr")).collect()?;
+        let relevance = df.column("similarity")?.f64()?;
+        let vectors = vectors(&df, "mmr")?;
+
+        // Rows without an embedding can't be compared and stay at the end
+        let (mut left, unranked): (Vec<usize>, Vec<usize>) =
+            (0..df.height()).partition(|&i| relevance.get(i).is_some() && vectors[i].is_some());
+        let relevance: Vec<f64> = relevance.into_iter().map(|s| s.unwrap_or(0.0)).collect();
+        let mut picked: Vec<usize> = Vec::with_capacity(df.height());
+        // The highest similarity of every row to any picked row
+        let mut closest = vec![f64::NEG_INFINITY; df.height()];
+        while !left.is_empty() {
+            let marginal = |i: usize| match picked.is_empty() {
+                true => relevance[i],
+                false => lambda * relevance[i] - (1.0 - lambda) * closest[i],
+            };
+            // Ties go to the better ranked row
+            let (at, &next) = left
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| marginal(**b).total_cmp(&marginal(**a)))
+                .unwrap();
+            left.remove(at);
+            picked.push(next);
+            let next = vectors[next].as_deref().unwrap();
+            for &i in &left {
+                let v = vectors[i].as_deref().unwrap();
+                closest[i] = closest[i].max(dot(next, v) as f64);
+            }
+        }
+        let order = picked.into_iter().chain(unranked);
+        let order = IdxCa::from_vec("", order.map(|i| i as IdxSize).collect());
+        Ok(df.take(&order)?.drop("mmr")?.lazy())
+    }
+}
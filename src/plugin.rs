@@ -0,0 +1,171 @@
+use clap::Args;
+use polars::{lazy::dsl::GetOutput, prelude::*};
+use std::{path::PathBuf, sync::Arc};
+
+#[derive(Args, Clone)]
+pub struct PluginArgs {
+    /// WebAssembly module preparing the text, such as LaTeX handling for a field or anonymization, see plugin.rs
+    /// for what it exports. Remembered for the corpus in its metadata, so that
+    /// search prepares queries with the same module
+    #[arg(long, value_name = "FILE")]
+    plugin: Option<PathBuf>,
+}
+
+/// Where a module prepares text, one of the functions it exports. A module, built for `--features plugins`,
+/// exports its `memory`, an `alloc(len: i32) -> i32` returning where the host may write `len` bytes, and either
+/// or both of
+///
+/// - `preprocess_document(ptr: i32, len: i32) -> i64`, run by brew on the text of every row it embeds
+/// - `preprocess_query(ptr: i32, len: i32) -> i64`, run by search on the query
+///
+/// A hook is called with the UTF-8 text at `ptr` and returns where its result is, as `ptr << 32 | len`. The host
+/// allocates once per text and never frees, a module is expected to reuse its memory from one call to the next.
+#[derive(Clone, Copy)]
+pub enum Hook {
+    Document,
+    Query,
+}
+
+impl Hook {
+    fn export(self) -> &'static str {
+        match self {
+            Hook::Document => "preprocess_document",
+            Hook::Query => "preprocess_query",
+        }
+    }
+}
+
+impl PluginArgs {
+    /// The module given on the command line, else the one recorded for the corpus
+    pub fn path(&self, recorded: Option<&str>) -> Option<PathBuf> {
+        self.plugin.clone().or(recorded.map(PathBuf::from))
+    }
+
+    /// The `hook` of the module given on the command line, else of the one recorded for the corpus, none if there
+    /// is neither or the module doesn't export it
+    pub fn load(&self, recorded: Option<&str>, hook: Hook) -> PolarsResult<Option<Plugin>> {
+        match self.path(recorded) {
+            Some(path) => Plugin::load(&path, hook),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+pub use wasm::Plugin;
+
+#[cfg(not(feature = "plugins"))]
+pub enum Plugin {}
+
+#[cfg(not(feature = "plugins"))]
+impl Plugin {
+    pub fn load(path: &std::path::Path, hook: Hook) -> PolarsResult<Option<Self>> {
+        polars_bail!(ComputeError: "{} is a plugin to run {}, but ada was built without them; rebuild it with --features plugins", path.display(), hook.export())
+    }
+
+    pub fn apply(&self, _: &str) -> PolarsResult<String> {
+        match *self {}
+    }
+}
+
+impl Plugin {
+    /// Apply the hook to a string expression
+    pub fn expr(self: Arc<Self>, expr: Expr) -> Expr {
+        expr.map(
+            move |c| {
+                let texts = c
+                    .str()?
+                    .into_iter()
+                    .map(|t| t.map(|t| self.apply(t)).transpose())
+                    .collect::<PolarsResult<Vec<_>>>()?;
+                Ok(Some(StringChunked::from_iter(texts).into_series()))
+            },
+            GetOutput::same_type(),
+        )
+    }
+}
+
+#[cfg(feature = "plugins")]
+mod wasm {
+    use super::Hook;
+    use polars::prelude::*;
+    use std::{path::Path, sync::Mutex};
+    use wasmi::{Config, Engine, Linker, Memory, Module, Store, TypedFunc};
+
+    // Instructions a hook may run per text, so that a module stuck in a loop fails instead of hanging brew
+    const FUEL: u64 = 1 << 32;
+
+    pub struct Plugin {
+        name: String,
+        instance: Mutex<Instance>,
+    }
+
+    struct Instance {
+        store: Store<()>,
+        memory: Memory,
+        alloc: TypedFunc<i32, i32>,
+        hook: TypedFunc<(i32, i32), i64>,
+    }
+
+    impl Plugin {
+        /// The `hook` of the module at `path`, none if the module doesn't export it
+        pub fn load(path: &Path, hook: Hook) -> PolarsResult<Option<Self>> {
+            let failed = |e: wasmi::Error| polars_err!(ComputeError: "invalid plugin {}: {}", path.display(), e);
+            let mut config = Config::default();
+            config.consume_fuel(true);
+            let engine = Engine::new(&config);
+            let module = Module::new(&engine, &crate::files::read(path)?).map_err(failed)?;
+            if module.get_export(hook.export()).is_none() {
+                return Ok(None);
+            }
+            let mut store = Store::new(&engine, ());
+            let instance = Linker::new(&engine)
+                .instantiate(&mut store, &module)
+                .and_then(|pre| pre.start(&mut store))
+                .map_err(failed)?;
+            let missing = |name: &str| polars_err!(ComputeError: "plugin {} exports no `{}`", path.display(), name);
+            let memory = instance
+                .get_memory(&store, "memory")
+                .ok_or_else(|| missing("memory"))?;
+            let alloc = instance
+                .get_typed_func(&store, "alloc")
+                .map_err(|_| missing("alloc(i32) -> i32"))?;
+            let run = instance
+                .get_typed_func(&store, hook.export())
+                .map_err(|_| missing(&format!("{}(i32, i32) -> i64", hook.export())))?;
+            Ok(Some(Self {
+                name: format!("{}:{}", path.display(), hook.export()),
+                instance: Mutex::new(Instance {
+                    store,
+                    memory,
+                    alloc,
+                    hook: run,
+                }),
+            }))
+        }
+
+        pub fn apply(&self, text: &str) -> PolarsResult<String> {
+            let failed = |e: &dyn std::fmt::Display| polars_err!(ComputeError: "plugin {} failed: {}", self.name, e);
+            let mut instance = self.instance.lock().unwrap();
+            let Instance {
+                store,
+                memory,
+                alloc,
+                hook,
+            } = &mut *instance;
+            store.set_fuel(FUEL).map_err(|e| failed(&e))?;
+            let len = i32::try_from(text.len()).map_err(|e| failed(&e))?;
+            let ptr = alloc.call(&mut *store, len).map_err(|e| failed(&e))?;
+            memory
+                .write(&mut *store, ptr as u32 as usize, text.as_bytes())
+                .map_err(|e| failed(&e))?;
+            let result = hook.call(&mut *store, (ptr, len)).map_err(|e| failed(&e))? as u64;
+            let (ptr, len) = ((result >> 32) as usize, (result & 0xffff_ffff) as usize);
+            let bytes = memory
+                .data(&*store)
+                .get(ptr..ptr + len)
+                .ok_or_else(|| failed(&"returned text lies outside of its memory"))?;
+            String::from_utf8(bytes.to_vec()).map_err(|e| failed(&e))
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use async_openai::{
+    config::{AzureConfig, Config, OpenAIConfig},
+    error::OpenAIError,
+    types::CreateEmbeddingRequestArgs,
+    Client,
+};
+use clap::{Args, ValueEnum};
+use polars::prelude::*;
+
+// Where a local Ollama server serves its OpenAI-compatible API
+const OLLAMA_BASE: &str = "http://localhost:11434/v1";
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Provider {
+    Openai,
+    Azure,
+    /// Local Ollama server, through its OpenAI-compatible API
+    Ollama,
+    /// Deterministic word-hashing embeddings, for testing without network access
+    Mock,
+}
+
+pub const MOCK_DIMENSIONS: usize = 64;
+
+// Hash every word into a bucket, so that texts sharing words end up similar
+fn mock(input: &str) -> Vec<f32> {
+    let mut v = vec![0f32; MOCK_DIMENSIONS];
+    for word in input
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        // FNV-1a, stable across platforms and releases unlike the std hasher
+        let hash = word
+            .to_lowercase()
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |h, b| {
+                (h ^ b as u64).wrapping_mul(0x100000001b3)
+            });
+        v[(hash % MOCK_DIMENSIONS as u64) as usize] += 1.0;
+    }
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+    v
+}
+
+#[derive(Args, Clone)]
+pub struct ProviderArgs {
+    /// Service to request embeddings from
+    #[arg(long, value_enum, default_value_t = Provider::Openai)]
+    provider: Provider,
+    /// Base URL of the provider, e.g. https://<resource>.openai.azure.com for Azure
+    #[arg(long, value_name = "URL", required_if_eq("provider", "azure"))]
+    api_base: Option<String>,
+    /// Model to embed with, Azure picks it through the deployment instead
+    #[arg(long, default_value = "text-embedding-3-large")]
+    embedding_model: String,
+    /// Azure deployment serving the embedding model
+    #[arg(long, required_if_eq("provider", "azure"))]
+    deployment: Option<String>,
+    /// Azure API version
+    #[arg(long, default_value = "2024-02-01")]
+    api_version: String,
+}
+
+impl ProviderArgs {
+    /// Where embeddings come from, as recorded in the dataset metadata
+    pub fn endpoint(&self) -> String {
+        match self.provider {
+            Provider::Openai => self.api_base.as_deref().unwrap_or("openai").to_string(),
+            Provider::Azure => format!(
+                "azure:{}/{}",
+                self.api_base.as_deref().unwrap_or_default(),
+                self.deployment.as_deref().unwrap_or_default()
+            ),
+            Provider::Ollama => {
+                format!("ollama:{}", self.api_base.as_deref().unwrap_or(OLLAMA_BASE))
+            }
+            Provider::Mock => "mock".to_string(),
+        }
+    }
+
+    fn openai(&self) -> OpenAIConfig {
+        match self.provider {
+            Provider::Ollama => OpenAIConfig::new()
+                .with_api_base(self.api_base.as_deref().unwrap_or(OLLAMA_BASE))
+                .with_api_key("ollama"),
+            _ => match &self.api_base {
+                Some(base) => OpenAIConfig::new().with_api_base(base),
+                None => OpenAIConfig::new(),
+            },
+        }
+    }
+
+    /// Models the provider offers, `None` if it has no way to list them
+    pub async fn models(&self) -> Result<Option<Vec<String>>, OpenAIError> {
+        Ok(match self.provider {
+            Provider::Openai | Provider::Ollama => Some(
+                Client::with_config(self.openai())
+                    .models()
+                    .list()
+                    .await?
+                    .data
+                    .into_iter()
+                    .map(|m| m.id)
+                    .collect(),
+            ),
+            Provider::Azure => None,
+            Provider::Mock => Some(vec!["mock".to_string()]),
+        })
+    }
+
+    /// The same provider embedding with `model` instead
+    pub fn with_model(&self, model: &str) -> PolarsResult<Self> {
+        if self.provider == Provider::Azure && model != self.embedding_model {
+            polars_bail!(ComputeError: "Azure serves one model per deployment, brew every model from its own deployment instead");
+        }
+        Ok(Self {
+            embedding_model: model.to_string(),
+            ..self.clone()
+        })
+    }
+
+    /// The model requested from the provider
+    pub fn model(&self) -> &str {
+        &self.embedding_model
+    }
+
+    /// Whether this is the plain OpenAI endpoint that datasets without metadata were brewed with
+    pub fn is_default(&self) -> bool {
+        self.provider == Provider::Openai && self.api_base.is_none()
+    }
+
+    /// Embed `input`, returning the model the provider reports alongside the embedding.
+    /// The caller is responsible for keeping the input within MAX_TOKEN
+    pub async fn embed(self, input: String) -> Result<(String, Option<Vec<f32>>), OpenAIError> {
+        match self.provider {
+            Provider::Openai | Provider::Ollama => {
+                request(
+                    Client::with_config(self.openai()),
+                    &self.embedding_model,
+                    input,
+                )
+                .await
+            }
+            Provider::Azure => {
+                let config = AzureConfig::new()
+                    .with_api_base(self.api_base.unwrap_or_default())
+                    .with_deployment_id(self.deployment.unwrap_or_default())
+                    .with_api_version(&self.api_version);
+                request(Client::with_config(config), &self.embedding_model, input).await
+            }
+            Provider::Mock => Ok(("mock".to_string(), Some(mock(&input)))),
+        }
+    }
+}
+
+async fn request<C: Config>(
+    client: Client<C>,
+    model: &str,
+    input: String,
+) -> Result<(String, Option<Vec<f32>>), OpenAIError> {
+    let req = CreateEmbeddingRequestArgs::default()
+        .model(model)
+        .input(input)
+        .build()?;
+
+    let mut res = client.embeddings().create(req).await?;
+    Ok((res.model, res.data.pop().map(|x| x.embedding)))
+}
@@ -0,0 +1,141 @@
+use crate::{dot, lock, meta::Meta, tombstones, vectors, write_dataset};
+use clap::Args;
+use polars::prelude::*;
+use std::{collections::HashMap, path::PathBuf};
+
+#[derive(Args)]
+pub struct QualityArgs {
+    input: PathBuf,
+    output: PathBuf,
+}
+
+// Map the available values to their percentile rank in [0, 1], so that signals of different scales can be averaged
+pub fn percentile(values: &[Option<f64>]) -> Vec<Option<f64>> {
+    let mut sorted: Vec<f64> = values.iter().flatten().copied().collect();
+    sorted.sort_unstable_by(|a, b| a.total_cmp(b));
+    let n = (sorted.len().max(2) - 1) as f64;
+    values
+        .iter()
+        .map(|v| {
+            v.map(|v| {
+                let below = sorted.partition_point(|x| *x < v);
+                let equal = sorted.partition_point(|x| *x <= v) - below;
+                (below as f64 + (equal as f64 - 1.0) / 2.0) / n
+            })
+        })
+        .collect()
+}
+
+fn column(df: &DataFrame, name: &str) -> PolarsResult<Option<Vec<Option<f64>>>> {
+    if df.schema().contains(name) {
+        Ok(Some(
+            df.column(name)?
+                .cast(&DataType::Float64)?
+                .f64()?
+                .into_iter()
+                .collect(),
+        ))
+    } else {
+        println!("Column `{}` is missing, its signal is skipped", name);
+        Ok(None)
+    }
+}
+
+// Similarity to the normalized mean of all embeddings, posts near the center of the corpus tend to be on topic.
+// Rows brew routed to another model are compared with the mean of that model, as embeddings of different models
+// lie in different spaces.
+fn centrality(df: &DataFrame, live: &[bool], meta: &Meta) -> PolarsResult<Vec<Option<f64>>> {
+    let mut vectors = vectors(df, "embeddings")?;
+    vectors
+        .iter_mut()
+        .zip(live)
+        .filter(|(_, live)| !**live)
+        .for_each(|(v, _)| *v = None);
+    let models: Vec<Option<&str>> = match df.column("model") {
+        Ok(models) => models
+            .str()?
+            .into_iter()
+            .map(|m| m.or(meta.model.as_deref()))
+            .collect(),
+        Err(_) => vec![None; df.height()],
+    };
+    let mut means: HashMap<Option<&str>, Vec<f32>> = HashMap::new();
+    for (v, model) in vectors.iter().zip(&models) {
+        if let Some(v) = v {
+            let mean = means.entry(*model).or_insert_with(|| vec![0.0; v.len()]);
+            mean.iter_mut().zip(v).for_each(|(m, x)| *m += x);
+        }
+    }
+    for mean in means.values_mut() {
+        let norm = dot(mean, mean).sqrt().max(f32::EPSILON);
+        mean.iter_mut().for_each(|m| *m /= norm);
+    }
+
+    Ok(vectors
+        .iter()
+        .zip(&models)
+        .map(|(v, model)| v.as_ref().map(|v| dot(v, &means[model]) as f64))
+        .collect())
+}
+
+/// Score questions on proxy signals of quality into a `quality` column in [0, 1]
+pub fn quality(args: QualityArgs) -> PolarsResult<()> {
+    let _locks = lock::rewrite(&args.input, &args.output)?;
+    let mut df = LazyFrame::scan_parquet(&args.input, Default::default())?.collect()?;
+    // Read the metadata before scoring in place replaces the dataset
+    let meta = Meta::load(&args.input)?;
+    // Deleted rows are kept until compact, but neither scored nor counted in the scores of the others
+    let deleted = tombstones::load(&args.input)?;
+    let live: Vec<bool> = df
+        .column("id")?
+        .cast(&DataType::String)?
+        .str()?
+        .into_iter()
+        .map(|id| id.is_none_or(|id| !deleted.contains(id)))
+        .collect();
+
+    let length: Vec<Option<f64>> = df
+        .column("body")?
+        .str()?
+        .into_iter()
+        .map(|b| b.map(|b| b.chars().count() as f64))
+        .collect();
+
+    // Weight of each signal, missing signals are left out of the average of a row
+    let signals: Vec<(f64, Vec<Option<f64>>)> = [
+        (0.4, column(&df, "score")?),
+        (0.2, column(&df, "answer_count")?),
+        (0.2, Some(length)),
+        (0.2, Some(centrality(&df, &live, &meta)?)),
+    ]
+    .into_iter()
+    .filter_map(|(w, s)| {
+        let s: Vec<Option<f64>> = s?
+            .into_iter()
+            .zip(&live)
+            .map(|(v, live)| v.filter(|_| *live))
+            .collect();
+        Some((w, percentile(&s)))
+    })
+    .collect();
+
+    let quality: Float32Chunked = (0..df.height())
+        .map(|i| {
+            let (sum, weights) = signals
+                .iter()
+                .filter_map(|(w, s)| Some((w * s[i]?, *w)))
+                .fold((0.0, 0.0), |(a, b), (x, w)| (a + x, b + w));
+            (weights > 0.0).then(|| (sum / weights) as f32)
+        })
+        .collect();
+    df.with_column(quality.into_series().with_name("quality"))?;
+
+    println!("{}", df);
+
+    write_dataset(&mut df, &args.output)?;
+    meta.save(&args.output)?;
+
+    println!("Finished writing");
+
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use crate::{files, meta::Meta, results, tombstones, Cli, Commands};
+use clap::{Args, Parser};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+#[derive(Args)]
+pub struct RegressArgs {
+    input: PathBuf,
+    /// Recall of a known good run, as written by --update
+    #[arg(long)]
+    baseline: PathBuf,
+    /// Evaluation queries, one JSON object per line: {"query": "...", "relevant": ["42", ...]}
+    #[arg(long)]
+    queries: PathBuf,
+    /// Number of top results in which the relevant rows are looked for
+    #[arg(short, default_value_t = 10)]
+    k: usize,
+    /// Largest tolerated drop of the mean recall below the baseline
+    #[arg(long, default_value_t = 0.0)]
+    max_drop: f64,
+    /// Record the recall of this run as the new baseline instead of comparing against it
+    #[arg(long)]
+    update: bool,
+    /// Options passed on to every search, after `--`, e.g. `-- --provider ollama --quality-weight 0.1`
+    #[arg(last = true)]
+    search: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Query {
+    query: String,
+    relevant: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Baseline {
+    k: usize,
+    recall: f64,
+    queries: BTreeMap<String, f64>,
+}
+
+fn read_queries(args: &RegressArgs) -> PolarsResult<Vec<Query>> {
+    files::read_to_string(&args.queries)?
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(n, l)| {
+            serde_json::from_str(l).map_err(
+                |e| polars_err!(ComputeError: "{}:{}: {}", args.queries.display(), n + 1, e),
+            )
+        })
+        .collect()
+}
+
+// Share of the relevant rows found among the top k results of `query`, none if they were all deleted
+fn recall(
+    args: &RegressArgs,
+    meta: &Meta,
+    urls: &DataFrame,
+    deleted: &BTreeSet<String>,
+    query: &Query,
+) -> PolarsResult<Option<f64>> {
+    let input = args.input.to_string_lossy();
+    let command = ["ada", "search", &input, &query.query]
+        .into_iter()
+        .chain(args.search.iter().map(String::as_str));
+    let Commands::Search(search) = Cli::try_parse_from(command)
+        .map_err(|e| polars_err!(ComputeError: "{}", e))?
+        .command
+    else {
+        unreachable!()
+    };
+    let top = results(search)?.head(Some(args.k));
+    let top: Vec<&str> = top.column("id")?.str()?.into_iter().flatten().collect();
+
+    // Search shows links, so the relevant ids go through the same template. Deleted rows can't be found any
+    // more, they would only lower the recall
+    let relevant: Vec<String> = query
+        .relevant
+        .iter()
+        .map(|id| match id {
+            serde_json::Value::String(id) => id.clone(),
+            id => id.to_string(),
+        })
+        .filter(|id| !deleted.contains(id))
+        .collect();
+    if relevant.is_empty() && !query.relevant.is_empty() {
+        println!("Skipping `{}`, its relevant rows were deleted", query.query);
+        return Ok(None);
+    }
+    let relevant = df!("id" => relevant)?
+        .lazy()
+        .left_join(urls.clone().lazy(), col("id"), col("id"))
+        .select([meta.url(&urls.schema()).alias("id")])
+        .collect()?;
+    let relevant = relevant.column("id")?.str()?;
+    if relevant.is_empty() {
+        polars_bail!(ComputeError: "query `{}` lists no relevant ids", query.query);
+    }
+    let found = relevant
+        .into_iter()
+        .flatten()
+        .filter(|id| top.contains(id))
+        .count();
+    Ok(Some(found as f64 / relevant.len() as f64))
+}
+
+/// Measure recall@k over the evaluation queries and fail if it dropped below the baseline
+pub fn regress(args: RegressArgs) -> PolarsResult<()> {
+    let queries = read_queries(&args)?;
+    if queries.is_empty() {
+        polars_bail!(NoData: "no queries in {}", args.queries.display());
+    }
+    let meta = Meta::load(&args.input)?;
+    // The links of the rows that carry their own
+    let lf = LazyFrame::scan_parquet(&args.input, Default::default())?;
+    let urls = match lf.schema()?.contains("url") {
+        true => lf
+            .select([col("id").cast(DataType::String), col("url")])
+            .filter(col("url").is_not_null())
+            .collect()?,
+        false => DataFrame::new(vec![Series::new_empty("id", &DataType::String)])?,
+    };
+
+    let deleted = tombstones::load(&args.input)?;
+    let mut recalls = BTreeMap::new();
+    for query in &queries {
+        if let Some(recall) = recall(&args, &meta, &urls, &deleted, query)? {
+            recalls.insert(query.query.clone(), recall);
+        }
+    }
+    if recalls.is_empty() {
+        polars_bail!(NoData: "the relevant rows of every query were deleted");
+    }
+    let mean = recalls.values().sum::<f64>() / recalls.len() as f64;
+    println!(
+        "recall@{}: {:.4} over {} queries",
+        args.k,
+        mean,
+        recalls.len()
+    );
+
+    if args.update {
+        let baseline = Baseline {
+            k: args.k,
+            recall: mean,
+            queries: recalls,
+        };
+        files::atomic(&args.baseline, |file| {
+            serde_json::to_writer_pretty(file, &baseline)
+                .map_err(|e| polars_err!(ComputeError: "failed to write the baseline: {}", e))
+        })?;
+        println!("Recorded the baseline in {}", args.baseline.display());
+        return Ok(());
+    }
+
+    let baseline: Baseline = serde_json::from_slice(&files::read(&args.baseline)?).map_err(
+        |e| polars_err!(ComputeError: "invalid baseline in {}: {}", args.baseline.display(), e),
+    )?;
+    if baseline.k != args.k {
+        polars_bail!(ComputeError: "baseline measured recall@{}, not recall@{}", baseline.k, args.k);
+    }
+    for (query, recall) in &recalls {
+        match baseline.queries.get(query) {
+            Some(before) if recall < before => {
+                println!("Dropped from {:.2} to {:.2}: {}", before, recall, query)
+            }
+            None => println!("Not in the baseline: {}", query),
+            _ => {}
+        }
+    }
+    let drop = baseline.recall - mean;
+    if drop > args.max_drop {
+        polars_bail!(
+            ComputeError: "recall@{} dropped from {:.4} to {:.4}, more than --max-drop {}",
+            args.k, baseline.recall, mean, args.max_drop
+        );
+    }
+    println!(
+        "Within {} of the baseline recall of {:.4}",
+        args.max_drop, baseline.recall
+    );
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use crate::{files, meta::Meta, tags};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Embedding models for subsets of a corpus by tag, read from a JSON file such as
+///
+/// ```json
+/// { "routes": [{ "tags": ["programming", "python"], "model": "code-embedding-model" }] }
+/// ```
+///
+/// A row goes to the first route carrying any of its tags, and to the model of the provider otherwise
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Routes {
+    routes: Vec<Route>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Route {
+    tags: Vec<String>,
+    model: String,
+}
+
+impl Routes {
+    pub fn load(path: &Path) -> PolarsResult<Self> {
+        let routes: Self = serde_json::from_slice(&files::read(path)?).map_err(
+            |e| polars_err!(ComputeError: "invalid routes in {}: {}", path.display(), e),
+        )?;
+        if let Some(route) = routes.routes.iter().find(|r| r.tags.is_empty()) {
+            polars_bail!(ComputeError: "the route to `{}` in {} has no tags", route.model, path.display());
+        }
+        Ok(routes)
+    }
+
+    /// The tags of every route
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.routes
+            .iter()
+            .flat_map(|route| route.tags.iter().map(String::as_str))
+    }
+
+    /// The models rows are routed to, in the order of the routes
+    pub fn models(&self) -> Vec<&str> {
+        let mut models: Vec<&str> = vec![];
+        for route in &self.routes {
+            if !models.contains(&route.model.as_str()) {
+                models.push(&route.model);
+            }
+        }
+        models
+    }
+
+    /// The model every row is routed to, `default` for the rows no route takes
+    pub fn expr(&self, schema: &Schema, default: &str) -> Expr {
+        self.routes
+            .iter()
+            .rev()
+            .fold(lit(default.to_string()), |otherwise, route| {
+                let tags = tags::any(schema, route.tags.iter().map(String::as_str));
+                when(tags)
+                    .then(lit(route.model.clone()))
+                    .otherwise(otherwise)
+            })
+    }
+}
+
+/// The model the embedded rows of `lf` come from, failing if brew routed them to several, as `command` compares
+/// embeddings with each other and those of different models lie in different spaces. Rows without a `model`
+/// were embedded with the one of the dataset.
+pub fn single_model(
+    lf: LazyFrame,
+    embeddings: Expr,
+    meta: &Meta,
+    command: &str,
+) -> PolarsResult<Option<String>> {
+    if !lf.schema()?.contains("model") {
+        return Ok(meta.model.clone());
+    }
+    let model = match meta.model {
+        Some(ref model) => col("model").fill_null(lit(model.clone())),
+        None => col("model"),
+    };
+    let df = lf
+        .filter(embeddings.is_not_null())
+        .select([model.unique().drop_nulls().sort(false)])
+        .collect()?;
+    let models: Vec<&str> = df.column("model")?.str()?.into_iter().flatten().collect();
+    if models.len() > 1 {
+        polars_bail!(
+            ComputeError: "{} compares embeddings with each other, but the rows were routed to {} models whose embeddings can't be compared: {}",
+            command, models.len(), models.join(", ")
+        );
+    }
+    Ok(models.first().map(|m| m.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::single_model;
+    use crate::meta::Meta;
+    use polars::prelude::*;
+
+    fn vector(values: &[f32]) -> Option<Series> {
+        Some(Series::new("", values))
+    }
+
+    // `a` was embedded before routing, `b` routed to the model of the dataset, `d` not embedded yet
+    fn routed(c: &str) -> LazyFrame {
+        df!(
+            "id" => ["a", "b", "c", "d"],
+            "embeddings" => [vector(&[1.0]), vector(&[1.0]), vector(&[0.0, 1.0]), None],
+            "model" => [None, Some("general"), Some(c), Some("code")]
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    #[test]
+    fn single_model_fills_in_the_model_of_the_dataset() {
+        let meta = Meta {
+            model: Some("general".into()),
+            ..Default::default()
+        };
+        let model = single_model(routed("general"), col("embeddings"), &meta, "graph").unwrap();
+        assert_eq!(model.as_deref(), Some("general"));
+    }
+
+    #[test]
+    fn single_model_rejects_rows_of_several_models() {
+        let meta = Meta {
+            model: Some("general".into()),
+            ..Default::default()
+        };
+        let e = single_model(routed("code"), col("embeddings"), &meta, "graph").unwrap_err();
+        assert!(e.to_string().contains("code, general"), "{}", e);
+    }
+}
@@ -0,0 +1,47 @@
+use clap::Args;
+use polars::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+#[derive(Args, Clone, Copy)]
+pub struct SampleArgs {
+    /// Only take the first this many rows, to try a model on a small part of the corpus first
+    #[arg(long, value_name = "N", conflicts_with = "sample")]
+    pub limit: Option<usize>,
+    /// Only take this many rows picked at random
+    #[arg(long, value_name = "N")]
+    sample: Option<usize>,
+    /// Seed of the random pick of --sample, the same seed picks the same rows of the same input
+    #[arg(long, default_value_t = 0, requires = "sample")]
+    seed: u64,
+}
+
+impl SampleArgs {
+    pub fn is_set(&self) -> bool {
+        self.limit.is_some() || self.sample.is_some()
+    }
+
+    /// The rows kept of `len`, in order, none when all are
+    fn rows(&self, len: usize) -> Option<Vec<IdxSize>> {
+        let mut rows: Vec<IdxSize> = match (self.limit, self.sample) {
+            (Some(limit), _) if limit < len => (0..limit as IdxSize).collect(),
+            (_, Some(sample)) if sample < len => {
+                let mut rng = StdRng::seed_from_u64(self.seed);
+                rand::seq::index::sample(&mut rng, len, sample)
+                    .into_iter()
+                    .map(|i| i as IdxSize)
+                    .collect()
+            }
+            _ => return None,
+        };
+        rows.sort_unstable();
+        Some(rows)
+    }
+
+    /// `df` without the rows left out
+    pub fn apply(&self, df: DataFrame) -> PolarsResult<DataFrame> {
+        match self.rows(df.height()) {
+            Some(rows) => df.take(&IdxCa::from_vec("", rows)),
+            None => Ok(df),
+        }
+    }
+}
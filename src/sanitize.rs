@@ -0,0 +1,124 @@
+use clap::{Args, ValueEnum};
+use polars::prelude::*;
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+
+/// What becomes of the images of a body
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Images {
+    /// Left out
+    Drop,
+    /// Replaced by their alt text
+    Alt,
+    /// Kept as a Markdown image, or as their alt text and address in plain text
+    Link,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct SanitizeArgs {
+    /// Leave out code blocks and inline code
+    #[arg(long)]
+    drop_code: bool,
+    /// Leave out LaTeX between $, $$, \( \) or \[ \] delimiters, except in code
+    #[arg(long)]
+    drop_math: bool,
+    /// What becomes of images [default: link with --markdown, drop otherwise]
+    #[arg(long, value_enum)]
+    images: Option<Images>,
+    /// Leave out the elements of this HTML tag along with their contents, e.g. `table`, may be repeated
+    #[arg(long = "drop-tag", value_name = "TAG")]
+    drop_tags: Vec<String>,
+}
+
+impl SanitizeArgs {
+    /// Returns `None` if the body is converted as is, `markdown` telling how images are kept by default
+    pub fn sanitizer(&self, markdown: bool) -> PolarsResult<Option<Sanitizer>> {
+        let default = if markdown { Images::Link } else { Images::Drop };
+        let images = self.images.unwrap_or(default);
+        if !self.drop_code && !self.drop_math && images == default && self.drop_tags.is_empty() {
+            return Ok(None);
+        }
+        let mut tags = self.drop_tags.clone();
+        if self.drop_code {
+            tags.extend(["pre".to_string(), "code".to_string()]);
+        }
+        let elements = tags
+            .iter()
+            .map(|tag| {
+                if !tag.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    polars_bail!(ComputeError: "invalid tag `{}` to drop", tag);
+                }
+                Ok(format!(r"<{0}\b[^>]*/>|<{0}\b[^>]*>.*?</{0}\s*>", tag))
+            })
+            .collect::<PolarsResult<Vec<_>>>()?;
+        let elements = match elements.is_empty() {
+            true => None,
+            false => Some(Regex::new(&format!("(?is){}", elements.join("|"))).unwrap()),
+        };
+        Ok(Some(Sanitizer {
+            elements,
+            math: self.drop_math.then(|| {
+                Regex::new(r"(?s)\$\$.+?\$\$|\$[^$\n]+?\$|\\\[.+?\\\]|\\\(.+?\\\)").unwrap()
+            }),
+            code: Regex::new(r"(?is)<pre\b.*?</pre\s*>|<code\b.*?</code\s*>").unwrap(),
+            images: (images != default).then_some((images, markdown)),
+            img: Regex::new(r"(?i)<img\b[^>]*>").unwrap(),
+            alt: attribute("alt"),
+            src: attribute("src"),
+        }))
+    }
+}
+
+fn attribute(name: &str) -> Regex {
+    Regex::new(&format!(r#"(?i)\b{}\s*=\s*(?:"([^"]*)"|'([^']*)')"#, name)).unwrap()
+}
+
+pub struct Sanitizer {
+    elements: Option<Regex>,
+    math: Option<Regex>,
+    code: Regex,
+    images: Option<(Images, bool)>,
+    img: Regex,
+    alt: Regex,
+    src: Regex,
+}
+
+impl Sanitizer {
+    /// Apply the policy to the HTML of a body, before its tags are stripped or turned into Markdown
+    pub fn sanitize<'a>(&self, html: &'a str) -> Cow<'a, str> {
+        let mut html = match &self.elements {
+            Some(re) => re.replace_all(html, ""),
+            None => Cow::Borrowed(html),
+        };
+        if let Some(math) = &self.math {
+            // Dollars in code are shell variables and the like, not math
+            let mut kept = String::with_capacity(html.len());
+            let mut last = 0;
+            for m in self.code.find_iter(&html) {
+                kept.push_str(&math.replace_all(&html[last..m.start()], ""));
+                kept.push_str(m.as_str());
+                last = m.end();
+            }
+            kept.push_str(&math.replace_all(&html[last..], ""));
+            html = Cow::Owned(kept);
+        }
+        if let Some((images, markdown)) = self.images {
+            let value = |re: &Regex, tag: &str| {
+                re.captures(tag)
+                    .and_then(|c| c.get(1).or(c.get(2)))
+                    .map_or(String::new(), |m| m.as_str().to_string())
+            };
+            let replaced = self.img.replace_all(&html, |c: &Captures| {
+                let tag = &c[0];
+                match images {
+                    Images::Drop => String::new(),
+                    Images::Alt => value(&self.alt, tag),
+                    Images::Link if markdown => tag.to_string(),
+                    Images::Link => format!("{} {}", value(&self.alt, tag), value(&self.src, tag)),
+                }
+            });
+            html = Cow::Owned(replaced.into_owned());
+        }
+        html
+    }
+}
@@ -0,0 +1,107 @@
+use clap::{Args, ValueEnum};
+use polars::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+/// Built-in detectors for personal data and secrets
+#[derive(Clone, Copy, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Detector {
+    Email,
+    Phone,
+    Key,
+}
+
+impl Detector {
+    fn rule(self) -> (&'static str, &'static str) {
+        match self {
+            Detector::Email => (r"(?i)\b[A-Z0-9._%+-]+@[A-Z0-9.-]+\.[A-Z]{2,}\b", "[EMAIL]"),
+            // Require separators between the groups so that plain numerical constants are left alone
+            Detector::Phone => (
+                r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{2,4}\)|\b\d{2,4})[\s.-]\d{3,4}[\s.-]\d{3,4}\b",
+                "[PHONE]",
+            ),
+            Detector::Key => (
+                r"(?s)-----BEGIN [A-Z ]*PRIVATE KEY-----.*?-----END [A-Z ]*PRIVATE KEY-----|\b(?:sk-[A-Za-z0-9_-]{20,}|AKIA[0-9A-Z]{16}|gh[pousr]_[A-Za-z0-9]{36,}|xox[abprs]-[A-Za-z0-9-]{10,}|AIza[0-9A-Za-z_-]{35})\b|(?i)\bbearer\s+[A-Za-z0-9._~+/-]{16,}=*",
+                "[KEY]",
+            ),
+        }
+    }
+}
+
+#[derive(Args, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScrubArgs {
+    /// Redact personal data and secrets found by the given detectors
+    #[arg(long, value_enum, value_delimiter = ',')]
+    scrub: Vec<Detector>,
+    /// Additional regex whose matches are redacted, may be repeated
+    #[arg(long)]
+    scrub_pattern: Vec<String>,
+}
+
+impl ScrubArgs {
+    /// Add the detectors and patterns of `other` that are missing
+    pub fn union(mut self, other: &Self) -> Self {
+        for d in &other.scrub {
+            if !self.scrub.contains(d) {
+                self.scrub.push(*d);
+            }
+        }
+        for p in &other.scrub_pattern {
+            if !self.scrub_pattern.contains(p) {
+                self.scrub_pattern.push(p.clone());
+            }
+        }
+        self
+    }
+
+    /// Returns `None` if no scrubbing has been requested
+    pub fn scrubber(&self) -> PolarsResult<Option<Scrubber>> {
+        if self.scrub.is_empty() && self.scrub_pattern.is_empty() {
+            return Ok(None);
+        }
+        let rules = self
+            .scrub
+            .iter()
+            .map(|d| d.rule())
+            .chain(
+                self.scrub_pattern
+                    .iter()
+                    .map(|p| (p.as_str(), "[REDACTED]")),
+            )
+            .map(|(pattern, replacement)| {
+                Regex::new(pattern)
+                    .map(|re| (re, replacement))
+                    .map_err(|e| polars_err!(ComputeError: "invalid scrub pattern: {}", e))
+            })
+            .collect::<PolarsResult<_>>()?;
+        Ok(Some(Scrubber { rules }))
+    }
+}
+
+pub struct Scrubber {
+    rules: Vec<(Regex, &'static str)>,
+}
+
+impl Scrubber {
+    pub fn scrub<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.rules
+            .iter()
+            .fold(Cow::Borrowed(text), |text, (re, replacement)| {
+                match re.replace_all(&text, *replacement) {
+                    Cow::Borrowed(_) => text,
+                    Cow::Owned(s) => Cow::Owned(s),
+                }
+            })
+    }
+
+    /// Apply the scrubber to a string expression
+    pub fn expr(self: Arc<Self>, expr: Expr) -> Expr {
+        expr.map(
+            move |c| Ok(Some(c.str()?.apply_values(|t| self.scrub(t)).into_series())),
+            GetOutput::same_type(),
+        )
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{files, results, run, Cli, Commands};
+use clap::Parser;
+use polars::prelude::*;
+use std::path::Path;
+
+const FIXTURE: &str = include_str!("../fixtures/Posts.xml");
+
+fn command(args: &[&str]) -> PolarsResult<Commands> {
+    Cli::try_parse_from(std::iter::once("ada").chain(args.iter().copied()))
+        .map(|cli| cli.command)
+        .map_err(|e| polars_err!(ComputeError: "{}", e))
+}
+
+fn ids(dataset: &str, filter: Expr) -> PolarsResult<Vec<String>> {
+    let df = LazyFrame::scan_parquet(dataset, Default::default())?
+        .filter(filter)
+        .select([col("id")])
+        .collect()?;
+    Ok(df
+        .column("id")?
+        .str()?
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect())
+}
+
+fn check(step: &str, expected: &[&str], actual: &[String]) -> PolarsResult<()> {
+    if actual != expected {
+        polars_bail!(ComputeError: "selftest failed at {}: expected {:?}, got {:?}", step, expected, actual);
+    }
+    println!("==> {} ok", step);
+    Ok(())
+}
+
+fn steps(dir: &Path) -> PolarsResult<()> {
+    let xml = dir.join("Posts.xml");
+    files::write(&xml, FIXTURE)?;
+    let xml = xml.to_str().unwrap();
+    let dataset = dir.join("posts.parquet");
+    let dataset = dataset.to_str().unwrap();
+
+    run(command(&["parse-xml", xml, dataset])?)?;
+    // The answer and the negatively scored question are left out
+    check(
+        "parse-xml",
+        &["1", "2", "4", "5"],
+        &ids(dataset, lit(true))?,
+    )?;
+
+    run(command(&["brew", dataset, dataset, "--provider", "mock"])?)?;
+    // Only questions carrying one of the brewed tags are embedded
+    check(
+        "brew",
+        &["1", "2", "4"],
+        &ids(dataset, col("embeddings").is_not_null())?,
+    )?;
+
+    let Commands::Search(args) = command(&[
+        "search",
+        dataset,
+        "entropy of an isolated system",
+        "--provider",
+        "mock",
+    ])?
+    else {
+        unreachable!()
+    };
+    let top = results(args)?.head(Some(1));
+    let top: Vec<String> = top
+        .column("id")?
+        .str()?
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect();
+    check(
+        "search",
+        &["https://physics.stackexchange.com/questions/2"],
+        &top,
+    )
+}
+
+/// Run the pipeline on the bundled fixture in a scratch directory
+pub fn selftest() -> PolarsResult<()> {
+    let dir = std::env::temp_dir().join(format!("ada-selftest-{}", std::process::id()));
+    files::create_dir_all(&dir)?;
+    let result = steps(&dir);
+    std::fs::remove_dir_all(&dir)?;
+    result?;
+
+    println!("Selftest passed");
+
+    Ok(())
+}
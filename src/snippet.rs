@@ -0,0 +1,70 @@
+use polars::{lazy::dsl::GetOutput, prelude::*};
+use std::collections::HashSet;
+
+// Sentences shown per result and the characters kept of each
+const SENTENCES: usize = 2;
+const MAX_CHARS: usize = 200;
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "are", "was", "with", "that", "this", "what", "why", "how", "does", "not",
+    "from", "can", "its", "has", "have", "but", "which", "when", "there", "into", "about",
+];
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 2)
+        .map(|w| w.to_lowercase())
+        .filter(|w| !STOPWORDS.contains(&w.as_str()))
+}
+
+fn sentences(body: &str) -> impl Iterator<Item = &str> {
+    body.split_inclusive(['.', '!', '?', '\n'])
+        .map(str::trim)
+        .filter(|s| s.chars().any(char::is_alphanumeric))
+}
+
+/// The sentences of `body` sharing the most words with the query, in the order they appear
+pub fn snippet(body: &str, query: &HashSet<String>) -> String {
+    let mut scored: Vec<(usize, usize, &str)> = sentences(body)
+        .enumerate()
+        .map(|(i, s)| {
+            let matched: HashSet<String> = words(s).filter(|w| query.contains(w)).collect();
+            (matched.len(), i, s)
+        })
+        .collect();
+    // The best scores first, the earlier sentence on a tie
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    let best = scored
+        .iter()
+        .take_while(|(score, ..)| *score > 0)
+        .take(SENTENCES);
+    let mut best: Vec<_> = match best.clone().count() {
+        // Nothing in common with the query, the opening says the most about a question
+        0 => scored.iter().filter(|(_, i, _)| *i == 0).collect(),
+        _ => best.collect(),
+    };
+    best.sort_by_key(|(_, i, _)| *i);
+    best.iter()
+        .map(|(_, _, s)| match s.char_indices().nth(MAX_CHARS) {
+            Some((end, _)) => format!("{}…", &s[..end]),
+            None => s.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" … ")
+}
+
+/// Snippet of every body matching `query`
+pub fn expr(body: Expr, query: &str) -> Expr {
+    let query: HashSet<String> = words(query).collect();
+    body.map(
+        move |b| {
+            Ok(Some(
+                b.str()?
+                    .into_iter()
+                    .map(|b| b.map(|b| snippet(b, &query)))
+                    .collect::<StringChunked>()
+                    .into_series(),
+            ))
+        },
+        GetOutput::from_type(DataType::String),
+    )
+}
@@ -0,0 +1,206 @@
+use crate::{files, manifest, ROW_GROUP_SIZE};
+use polars::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+const MAX_DISTANCE: usize = 2;
+// Words seen less often are likely typos themselves
+const MIN_COUNT: u64 = 2;
+
+/// SymSpell-style corrector over the vocabulary of a dataset
+pub struct Speller {
+    counts: HashMap<String, u64>,
+    // Every string reachable from a vocabulary word by up to MAX_DISTANCE deletions
+    deletes: HashMap<String, Vec<String>>,
+}
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphabetic())
+        .filter(|w| w.chars().count() > 1)
+        .map(|w| w.to_lowercase())
+}
+
+fn deletes(word: &str) -> HashSet<String> {
+    let mut all = HashSet::from([word.to_string()]);
+    let mut frontier = vec![word.to_string()];
+    for _ in 0..MAX_DISTANCE {
+        let mut next = Vec::new();
+        for w in &frontier {
+            let chars: Vec<char> = w.chars().collect();
+            for i in 0..chars.len() {
+                let d: String = chars[..i].iter().chain(&chars[i + 1..]).collect();
+                if all.insert(d.clone()) {
+                    next.push(d);
+                }
+            }
+        }
+        frontier = next;
+    }
+    all
+}
+
+// Optimal string alignment distance, i.e. Levenshtein with adjacent transpositions
+pub fn distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+impl Speller {
+    fn path(dataset: impl AsRef<Path>) -> PathBuf {
+        let mut path = dataset.as_ref().as_os_str().to_owned();
+        path.push(".vocab.parquet");
+        path.into()
+    }
+
+    // Whether the vocabulary sidecar was mined since the dataset was last written
+    fn fresh(dataset: impl AsRef<Path>) -> PolarsResult<bool> {
+        match (
+            std::fs::metadata(Self::path(&dataset)),
+            std::fs::metadata(&dataset),
+        ) {
+            (Ok(sidecar), Ok(data)) => Ok(sidecar.modified()? >= data.modified()?),
+            _ => Ok(false),
+        }
+    }
+
+    // Count the words of the titles and bodies of the dataset, a row group at a time so that only the counts are
+    // held, within brew --max-memory
+    fn mine(dataset: impl AsRef<Path>) -> PolarsResult<HashMap<String, u64>> {
+        let dataset = dataset.as_ref();
+        println!("Mining the vocabulary of {}", dataset.display());
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        let mut count = |df: DataFrame| -> PolarsResult<()> {
+            for column in ["title", "body"] {
+                for text in df.column(column)?.str()?.into_iter().flatten() {
+                    for word in words(text) {
+                        *counts.entry(word).or_default() += 1;
+                    }
+                }
+            }
+            Ok(())
+        };
+        match files::remote(dataset) {
+            // Only searched, a shared corpus is read by ranges where it is stored
+            true => count(
+                LazyFrame::scan_parquet(dataset, Default::default())?
+                    .select([col("title"), col("body")])
+                    .collect()?,
+            )?,
+            false => {
+                let runtime = tokio::runtime::Runtime::new()?;
+                let mut reader = ParquetReader::new(files::open(dataset)?)
+                    .with_columns(Some(vec!["title".into(), "body".into()]))
+                    .batched(ROW_GROUP_SIZE)?;
+                while let Some(batches) = runtime.block_on(reader.next_batches(1))? {
+                    if batches.is_empty() {
+                        break;
+                    }
+                    batches.into_iter().try_for_each(&mut count)?;
+                }
+            }
+        }
+        counts.retain(|_, c| *c >= MIN_COUNT);
+        Ok(counts)
+    }
+
+    /// Mine the vocabulary of the dataset into its sidecar unless it is up to date, for search to load. Only
+    /// called by brew --vocabulary, holding the exclusive lock of the dataset.
+    pub fn write(dataset: impl AsRef<Path>) -> PolarsResult<()> {
+        if Self::fresh(&dataset)? {
+            return Ok(());
+        }
+        let counts = Self::mine(&dataset)?;
+        let (w, c): (Vec<&str>, Vec<u64>) = counts.iter().map(|(w, c)| (w.as_str(), *c)).unzip();
+        let mut df = df!("word" => w, "count" => c)?;
+        files::atomic(Self::path(&dataset), |file| {
+            ParquetWriter::new(file).finish(&mut df)?;
+            Ok(())
+        })?;
+        manifest::record(&dataset, ".vocab.parquet")
+    }
+
+    /// Load the vocabulary sidecar of the dataset, or mine the vocabulary in memory if it is missing or
+    /// outdated, as a reader leaves the dataset and its sidecars as they are
+    pub fn load(dataset: impl AsRef<Path>) -> PolarsResult<Self> {
+        let counts: HashMap<String, u64> = match Self::fresh(&dataset)? {
+            true => {
+                let df = ParquetReader::new(files::open(Self::path(&dataset))?).finish()?;
+                df.column("word")?
+                    .str()?
+                    .into_iter()
+                    .zip(df.column("count")?.u64()?)
+                    .filter_map(|(w, c)| Some((w?.to_string(), c?)))
+                    .collect()
+            }
+            false => {
+                println!("No vocabulary up to date, brew --vocabulary mines it once for every search");
+                Self::mine(&dataset)?
+            }
+        };
+
+        let mut index: HashMap<String, Vec<String>> = HashMap::new();
+        for word in counts.keys() {
+            for d in deletes(word) {
+                index.entry(d).or_default().push(word.clone());
+            }
+        }
+
+        Ok(Self {
+            counts,
+            deletes: index,
+        })
+    }
+
+    fn best(&self, word: &str) -> Option<&str> {
+        deletes(word)
+            .iter()
+            .filter_map(|d| self.deletes.get(d))
+            .flatten()
+            .map(|candidate| (distance(word, candidate), candidate))
+            .filter(|(d, _)| *d <= MAX_DISTANCE)
+            // Closest first, the most frequent among equally close candidates
+            .min_by(|a, b| a.0.cmp(&b.0).then(self.counts[b.1].cmp(&self.counts[a.1])))
+            .map(|(_, candidate)| candidate.as_str())
+    }
+
+    /// Replace every word unknown to the corpus with its closest known spelling
+    pub fn correct(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut word = String::new();
+        for c in text.chars().chain(std::iter::once(' ')) {
+            if c.is_alphabetic() {
+                word.push(c);
+                continue;
+            }
+            let lower = word.to_lowercase();
+            match self.best(&lower) {
+                Some(fixed) if word.chars().count() > 2 && !self.counts.contains_key(&lower) => {
+                    out.push_str(fixed)
+                }
+                _ => out.push_str(&word),
+            }
+            word.clear();
+            out.push(c);
+        }
+        out.pop();
+        out
+    }
+}
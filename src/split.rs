@@ -0,0 +1,145 @@
+use crate::{dedup, lock, meta::Meta, tags, tombstones, write_dataset};
+use clap::Args;
+use polars::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::{collections::HashMap, path::PathBuf};
+
+#[derive(Args)]
+pub struct SplitArgs {
+    input: PathBuf,
+    /// The dataset of the training rows
+    train: PathBuf,
+    /// The dataset of the evaluation rows
+    eval: PathBuf,
+    /// Share of the rows that go to the evaluation set
+    #[arg(long, default_value_t = 0.1)]
+    eval_frac: f64,
+    /// Keep the share of every value of this column in both sets, e.g. `tags` or `site`. A row of several tags
+    /// counts towards its rarest one, so that small tags reach the evaluation set too
+    #[arg(long, value_name = "COLUMN")]
+    stratify: Option<String>,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+// The stratum of every row, none for rows without a value
+fn strata(df: &DataFrame, column: &str) -> PolarsResult<Vec<Option<String>>> {
+    let Ok(values) = df.column(column) else {
+        polars_bail!(ColumnNotFound: "dataset has no `{}` column to stratify by", column);
+    };
+    let DataType::List(_) = values.dtype() else {
+        let values = values.cast(&DataType::String)?;
+        return Ok(values
+            .str()?
+            .into_iter()
+            .map(|v| v.map(String::from))
+            .collect());
+    };
+    let values = values
+        .list()?
+        .cast(&DataType::List(Box::new(DataType::String)))?;
+    let rows: Vec<Vec<String>> = values
+        .list()?
+        .into_iter()
+        .map(|row| {
+            row.map(|row| {
+                let row = row
+                    .str()
+                    .map(|s| s.into_iter().flatten().map(String::from).collect());
+                row.unwrap_or_default()
+            })
+            .unwrap_or_default()
+        })
+        .collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for value in rows.iter().flatten() {
+        *counts.entry(value).or_default() += 1;
+    }
+    Ok(rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .min_by_key(|value| (counts[value.as_str()], value.as_str()))
+                .cloned()
+        })
+        .collect())
+}
+
+/// Split a dataset into disjoint training and evaluation sets, at random within every stratum
+pub fn split(args: SplitArgs) -> PolarsResult<()> {
+    if !(0.0..=1.0).contains(&args.eval_frac) {
+        polars_bail!(ComputeError: "--eval-frac {} is not a share between 0 and 1", args.eval_frac);
+    }
+    if args.train == args.eval || args.train == args.input || args.eval == args.input {
+        polars_bail!(ComputeError: "the input, training and evaluation datasets must be three different files");
+    }
+    let _locks = [
+        lock::Lock::shared(&args.input)?,
+        lock::Lock::exclusive(&args.train)?,
+        lock::Lock::exclusive(&args.eval)?,
+    ];
+
+    let lf = LazyFrame::scan_parquet(&args.input, Default::default())?;
+    let schema = lf.schema()?;
+    let lf = match schema.get("tags") {
+        Some(DataType::String) => lf.with_column(tags::column(&schema).alias("tags")),
+        _ => lf,
+    };
+    // Every row keeps a copy of the embedding it shares, as the row storing it may land in the other set
+    let lf = match schema.contains(dedup::COLUMN) {
+        true => dedup::resolve(lf)?
+            .with_column(lit(NULL).cast(DataType::String).alias(dedup::COLUMN)),
+        false => lf,
+    };
+    let df = tombstones::live(&args.input, lf)?.collect()?;
+
+    // Rows grouped by stratum in order of first appearance, a single one when not stratifying
+    let mut groups: Vec<Vec<IdxSize>> = vec![];
+    match args.stratify {
+        Some(ref column) => {
+            let mut index = HashMap::new();
+            for (row, stratum) in strata(&df, column)?.into_iter().enumerate() {
+                let group = *index.entry(stratum).or_insert_with(|| {
+                    groups.push(vec![]);
+                    groups.len() - 1
+                });
+                groups[group].push(row as IdxSize);
+            }
+        }
+        None => groups.push((0..df.height() as IdxSize).collect()),
+    }
+
+    // Rounded on the running total, so that many small strata don't each round up to an evaluation row
+    let mut rng = StdRng::seed_from_u64(args.seed);
+    let (mut train, mut eval) = (vec![], vec![]);
+    let mut seen = 0;
+    for mut group in groups.iter().cloned() {
+        group.shuffle(&mut rng);
+        seen += group.len();
+        let wanted = (seen as f64 * args.eval_frac).round() as usize - eval.len();
+        let taken = wanted.min(group.len());
+        eval.extend_from_slice(&group[..taken]);
+        train.extend_from_slice(&group[taken..]);
+    }
+    train.sort_unstable();
+    eval.sort_unstable();
+
+    let meta = Meta::load(&args.input)?;
+    for (rows, output) in [(&train, &args.train), (&eval, &args.eval)] {
+        let mut df = df.take(&IdxCa::from_vec("", rows.clone()))?;
+        write_dataset(&mut df, output)?;
+        meta.save(output)?;
+    }
+    println!(
+        "Wrote {} training rows to {} and {} evaluation rows to {}",
+        train.len(),
+        args.train.display(),
+        eval.len(),
+        args.eval.display()
+    );
+    if let Some(column) = args.stratify {
+        println!("Stratified by `{}` over {} strata", column, groups.len());
+    }
+
+    Ok(())
+}
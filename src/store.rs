@@ -0,0 +1,141 @@
+use crate::{audit, files, pack::SIDECARS};
+use object_store::{path::Path as ObjectPath, ObjectStore};
+use polars::prelude::*;
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::io::AsyncWriteExt;
+
+// Transferred a block at a time, so that datasets larger than memory go through
+const BLOCK: usize = 64 << 20;
+
+// The store holding `url` and the location of the object in it
+async fn object(url: &str) -> PolarsResult<(Arc<dyn ObjectStore>, ObjectPath)> {
+    let (location, store) = polars::io::cloud::build_object_store(url, None).await?;
+    let path = ObjectPath::from_url_path(&location.prefix)
+        .map_err(|e| polars_err!(ComputeError: "invalid location {}: {}", url, e))?;
+    Ok((store, path))
+}
+
+fn failed(url: &str, e: object_store::Error) -> PolarsError {
+    polars_err!(ComputeError: "failed to transfer {}: {}", url, e)
+}
+
+// Copy the object at `url` to `local`, returning whether there was one
+async fn download(url: &str, local: &Path) -> PolarsResult<bool> {
+    let (store, path) = object(url).await?;
+    let size = match store.head(&path).await {
+        Ok(meta) => meta.size,
+        Err(object_store::Error::NotFound { .. }) => return Ok(false),
+        Err(e) => return Err(failed(url, e)),
+    };
+    let mut file = files::create(local)?;
+    for start in (0..size).step_by(BLOCK) {
+        let block = store
+            .get_range(&path, start..(start + BLOCK).min(size))
+            .await
+            .map_err(|e| failed(url, e))?;
+        file.write_all(&block)
+            .map_err(|e| files::with_path(e, local))?;
+    }
+    Ok(true)
+}
+
+// Copy `local` to the object at `url` in parts, aborting the upload if a part fails after its retries
+async fn upload(local: &Path, url: &str) -> PolarsResult<()> {
+    let (store, path) = object(url).await?;
+    let (id, mut writer) = store
+        .put_multipart(&path)
+        .await
+        .map_err(|e| failed(url, e))?;
+    let mut file = files::open(local)?;
+    let mut buf = vec![0; BLOCK];
+    let written: std::io::Result<()> = async {
+        loop {
+            match file.read(&mut buf)? {
+                0 => break,
+                n => writer.write_all(&buf[..n]).await?,
+            }
+        }
+        writer.shutdown().await
+    }
+    .await;
+    if let Err(e) = written {
+        // Leaves no orphaned parts to pay for
+        let _ = store.abort_multipart(&path, &id).await;
+        polars_bail!(ComputeError: "failed to upload {}: {}", url, e);
+    }
+    Ok(())
+}
+
+async fn delete(url: &str) -> PolarsResult<()> {
+    let (store, path) = object(url).await?;
+    match store.delete(&path).await {
+        Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+        Err(e) => Err(failed(url, e)),
+    }
+}
+
+// Staged along with the sidecars, though not packed: a brew of a remote dataset interrupted midway resumes from
+// the journal it stored
+const JOURNAL: &str = ".journal.jsonl";
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// Run `write` on a local copy of the dataset at the object store URL `url`, along with its sidecars, then
+/// store what it wrote back at `url`. The copy lives in a temporary directory, removed afterwards.
+pub fn staged(url: &Path, write: impl FnOnce(&Path) -> PolarsResult<()>) -> PolarsResult<()> {
+    let name = url
+        .file_name()
+        .ok_or_else(|| polars_err!(ComputeError: "{} names no file", url.display()))?;
+    let dir = std::env::temp_dir().join(format!("ada-{}", std::process::id()));
+    files::create_dir_all(&dir)?;
+    let local = dir.join(name);
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    let result = (|| {
+        let sidecars = || SIDECARS.iter().copied().chain(std::iter::once(JOURNAL));
+        for suffix in std::iter::once("").chain(sidecars()) {
+            let remote = suffixed(url, suffix).to_string_lossy().into_owned();
+            runtime.block_on(download(&remote, &suffixed(&local, suffix)))?;
+        }
+        if let Err(e) = write(&local) {
+            // The embeddings paid for so far are kept for the next run to resume from
+            let journal = suffixed(&local, JOURNAL);
+            if journal.exists() {
+                let remote = suffixed(url, JOURNAL).to_string_lossy().into_owned();
+                if let Err(e) = runtime.block_on(upload(&journal, &remote)) {
+                    println!(
+                        "Warning: failed to store the journal of {}: {}",
+                        url.display(),
+                        e
+                    );
+                }
+            }
+            return Err(e);
+        }
+        // Logged before the upload, the log travels with the dataset
+        audit::record(None)?;
+        // The dataset goes last, so that an interrupted upload leaves the previous one rather than a new one with stale sidecars
+        for suffix in sidecars().chain(std::iter::once("")) {
+            let remote = suffixed(url, suffix).to_string_lossy().into_owned();
+            let path = suffixed(&local, suffix);
+            match path.exists() {
+                true => runtime.block_on(upload(&path, &remote))?,
+                // A sidecar the command removed, the dataset itself is only missing if nothing was written
+                false if !suffix.is_empty() => runtime.block_on(delete(&remote))?,
+                false => {}
+            }
+        }
+        println!("Stored {}", url.display());
+        Ok(())
+    })();
+    let _ = std::fs::remove_dir_all(files::long(&dir));
+    result
+}
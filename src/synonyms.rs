@@ -0,0 +1,61 @@
+use crate::files;
+use polars::prelude::*;
+use regex::{Captures, Regex};
+use std::{collections::HashMap, path::Path};
+
+/// Aliases of domain terms, e.g. `QM = quantum mechanics`, spelled out in queries
+pub struct Synonyms {
+    expansions: HashMap<String, String>,
+    // Matches any alias as a whole word
+    aliases: Regex,
+}
+
+impl Synonyms {
+    /// Read `alias = expansion` lines, skipping blank lines and `#` comments
+    pub fn load(path: &Path) -> PolarsResult<Self> {
+        let mut expansions = HashMap::new();
+        for (n, line) in files::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=').map(|(a, e)| (a.trim(), e.trim())) {
+                Some((alias, expansion)) if !alias.is_empty() && !expansion.is_empty() => {
+                    expansions.insert(alias.to_lowercase(), expansion.to_string());
+                }
+                _ => {
+                    polars_bail!(ComputeError: "{}:{}: expected `alias = expansion`", path.display(), n + 1)
+                }
+            }
+        }
+
+        // Longest first, so that `EM field` wins over `EM`
+        let mut aliases: Vec<&String> = expansions.keys().collect();
+        aliases.sort_by_key(|a| std::cmp::Reverse(a.len()));
+        let pattern = aliases
+            .iter()
+            .map(|a| regex::escape(a))
+            .collect::<Vec<_>>()
+            .join("|");
+        let aliases = Regex::new(&format!(r"(?i)\b(?:{})\b", pattern)).map_err(
+            |e| polars_err!(ComputeError: "invalid synonyms in {}: {}", path.display(), e),
+        )?;
+
+        Ok(Self {
+            expansions,
+            aliases,
+        })
+    }
+
+    /// Replace every alias in `text` by its expansion
+    pub fn expand(&self, text: &str) -> String {
+        if self.expansions.is_empty() {
+            return text.to_string();
+        }
+        self.aliases
+            .replace_all(text, |c: &Captures| {
+                self.expansions[&c[0].to_lowercase()].clone()
+            })
+            .into_owned()
+    }
+}
@@ -0,0 +1,87 @@
+use crate::spell;
+use polars::prelude::*;
+use std::path::Path;
+
+/// Split tags written as `<tag-a><tag-b>`, the way Stack Exchange dumps list them
+pub fn split(tags: &str) -> impl Iterator<Item = &str> {
+    tags.split(['<', '>']).filter(|t| !t.is_empty())
+}
+
+/// Tags of a file ingested from a directory tree, the names of the subdirectories at `relative` it is in, e.g.
+/// `quantum-notes` for `Quantum Notes/lecture-1.pdf`
+pub fn of_directories(relative: &Path) -> Vec<String> {
+    relative
+        .parent()
+        .into_iter()
+        .flat_map(|d| d.iter())
+        .map(|d| d.to_string_lossy().to_lowercase().replace(' ', "-"))
+        .collect()
+}
+
+/// The tags of every row as a list, also for datasets written when they were kept as a `<tag-a><tag-b>` string
+pub fn column(schema: &Schema) -> Expr {
+    match schema.get("tags") {
+        Some(DataType::String) => col("tags")
+            .str()
+            .strip_chars(lit("<>"))
+            .str()
+            .split(lit("><")),
+        _ => col("tags"),
+    }
+}
+
+/// Whether a row carries any of `tags` exactly, rows without tags carry none
+pub fn any<'a>(schema: &Schema, tags: impl IntoIterator<Item = &'a str>) -> Expr {
+    tags.into_iter()
+        .map(|tag| column(schema).list().contains(lit(tag.to_string())))
+        .reduce(|a, b| a.or(b))
+        .unwrap_or(lit(false))
+        .fill_null(lit(false))
+}
+
+/// Fail on the tags of `wanted` that no row of `lf` carries, suggesting the closest ones it does. `given` names
+/// where they come from, such as `--tag`.
+pub fn check<'a>(
+    lf: LazyFrame,
+    wanted: impl IntoIterator<Item = &'a str>,
+    given: &str,
+) -> PolarsResult<()> {
+    let wanted: Vec<&str> = wanted.into_iter().collect();
+    if wanted.is_empty() {
+        return Ok(());
+    }
+    let schema = lf.schema()?;
+    let known = lf
+        .select([column(&schema).alias("tag")])
+        .explode([col("tag")])
+        .unique(None, UniqueKeepStrategy::Any)
+        .collect()?;
+    let known: Vec<&str> = known.column("tag")?.str()?.into_iter().flatten().collect();
+    for tag in wanted {
+        if known.contains(&tag) {
+            continue;
+        }
+        // Typos first, then the tags it is part of, such as `quantum` of `quantum-mechanics`
+        let mut close: Vec<(usize, &str)> = known
+            .iter()
+            .map(|&k| (spell::distance(tag, k), k))
+            .filter(|&(d, k)| d <= (tag.len() / 3).max(2) || k.contains(tag))
+            .collect();
+        close.sort();
+        let close: Vec<String> = close
+            .iter()
+            .take(3)
+            .map(|(_, k)| format!("<{}>", k))
+            .collect();
+        match close.is_empty() {
+            true => {
+                polars_bail!(ComputeError: "no question is tagged <{}>, given in {}", tag, given)
+            }
+            false => polars_bail!(
+                ComputeError: "no question is tagged <{}>, given in {}; did you mean {}?",
+                tag, given, close.join(", ")
+            ),
+        }
+    }
+    Ok(())
+}
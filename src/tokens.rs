@@ -0,0 +1,72 @@
+use crate::{files, manifest};
+use polars::prelude::*;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+/// Hex encoded SHA-256 of the text, used to key values derived from the content
+pub fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// cl100k token counts keyed by content hash, persisted as a sidecar Parquet next to the dataset
+#[derive(Default)]
+pub struct TokenCache {
+    counts: HashMap<String, u32>,
+}
+
+impl TokenCache {
+    /// Sidecar path of the cache belonging to the given dataset
+    pub fn path(dataset: impl AsRef<Path>) -> PathBuf {
+        let mut path = dataset.as_ref().as_os_str().to_owned();
+        path.push(".tokens.parquet");
+        path.into()
+    }
+
+    /// Load the cache of the given datasets, missing sidecars are treated as empty
+    pub fn load<P: AsRef<Path>>(datasets: &[P]) -> PolarsResult<Self> {
+        let mut cache = Self::default();
+        for path in datasets.iter().map(Self::path).filter(|p| p.exists()) {
+            let df = ParquetReader::new(files::open(path)?).finish()?;
+            cache.counts.extend(
+                df.column("hash")?
+                    .str()?
+                    .into_iter()
+                    .zip(df.column("tokens")?.u32()?)
+                    .filter_map(|(hash, tokens)| Some((hash?.to_string(), tokens?))),
+            );
+        }
+        Ok(cache)
+    }
+
+    pub fn save(&self, dataset: impl AsRef<Path>) -> PolarsResult<()> {
+        let (hashes, tokens): (Vec<&str>, Vec<u32>) =
+            self.counts.iter().map(|(h, t)| (h.as_str(), *t)).unzip();
+        let mut df = df!("hash" => hashes, "tokens" => tokens)?;
+        files::atomic(Self::path(&dataset), |file| {
+            ParquetWriter::new(file).finish(&mut df)?;
+            Ok(())
+        })?;
+        manifest::record(dataset, ".tokens.parquet")
+    }
+
+    /// Count the tokens of all texts missing from the cache in parallel
+    pub fn fill<'a>(&mut self, texts: impl Iterator<Item = &'a str>) {
+        let tokenizer = tiktoken_rs::cl100k_base().unwrap();
+        let texts: Vec<&str> = texts.collect();
+        let missing: Vec<(String, u32)> = texts
+            .into_par_iter()
+            .map(|text| (content_hash(text), text))
+            .filter(|(hash, _)| !self.counts.contains_key(hash))
+            .map(|(hash, text)| (hash, tokenizer.encode_ordinary(text).len() as u32))
+            .collect();
+        self.counts.extend(missing);
+    }
+
+    pub fn get(&self, text: &str) -> Option<u32> {
+        self.counts.get(&content_hash(text)).copied()
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{answers, audit, dedup, files, lock, write_dataset};
+use clap::Args;
+use polars::{lazy::dsl::GetOutput, prelude::*};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+#[derive(Args)]
+pub struct DeleteArgs {
+    input: PathBuf,
+    /// File listing the ids to delete, one per line
+    #[arg(long, value_name = "FILE")]
+    ids: PathBuf,
+}
+
+#[derive(Args)]
+pub struct CompactArgs {
+    input: PathBuf,
+}
+
+// Ids deleted from a dataset but still in its Parquet file until compact, kept in a `<dataset>.tombstones.json` sidecar
+fn path(dataset: impl AsRef<Path>) -> PathBuf {
+    let mut path = dataset.as_ref().as_os_str().to_owned();
+    path.push(".tombstones.json");
+    path.into()
+}
+
+/// The ids deleted from `dataset`, empty if there are none
+pub fn load(dataset: impl AsRef<Path>) -> PolarsResult<BTreeSet<String>> {
+    let path = path(dataset);
+    if !path.exists() {
+        return Ok(BTreeSet::new());
+    }
+    serde_json::from_slice(&files::read(&path)?)
+        .map_err(|e| polars_err!(ComputeError: "invalid tombstones in {}: {}", path.display(), e))
+}
+
+/// The ids deleted from the remote `dataset`, empty if there are none
+pub async fn fetch(dataset: impl AsRef<Path>) -> PolarsResult<BTreeSet<String>> {
+    let path = path(dataset);
+    match files::fetch(&path).await? {
+        Some(bytes) => serde_json::from_slice(&bytes).map_err(
+            |e| polars_err!(ComputeError: "invalid tombstones in {}: {}", path.display(), e),
+        ),
+        None => Ok(BTreeSet::new()),
+    }
+}
+
+/// Leave the rows deleted from `dataset` out of `lf`
+pub fn live(dataset: impl AsRef<Path>, lf: LazyFrame) -> PolarsResult<LazyFrame> {
+    exclude(load(dataset)?, lf)
+}
+
+/// Leave the `deleted` rows out of `lf`
+pub fn exclude(deleted: BTreeSet<String>, lf: LazyFrame) -> PolarsResult<LazyFrame> {
+    if deleted.is_empty() {
+        return Ok(lf);
+    }
+    // Flagged in a column first, Polars can't push an opaque function down into the scan as a predicate
+    let tombstone = col("id").cast(DataType::String).map(
+        move |s| {
+            Ok(Some(
+                s.str()?
+                    .into_iter()
+                    .map(|id| id.is_some_and(|id| deleted.contains(id)))
+                    .collect::<BooleanChunked>()
+                    .into_series(),
+            ))
+        },
+        GetOutput::from_type(DataType::Boolean),
+    );
+    Ok(lf
+        .with_column(tombstone.alias("tombstone"))
+        .filter(col("tombstone").not())
+        .drop(["tombstone"]))
+}
+
+/// Hide rows from search and exports until the dataset is compacted
+pub fn delete(args: DeleteArgs) -> PolarsResult<()> {
+    let _lock = lock::Lock::exclusive(&args.input)?;
+    let ids: BTreeSet<String> = files::read_to_string(&args.ids)?
+        .lines()
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+        .collect();
+
+    let known = LazyFrame::scan_parquet(&args.input, Default::default())?
+        .select([col("id").cast(DataType::String)])
+        .collect()?;
+    let known: BTreeSet<&str> = known.column("id")?.str()?.into_iter().flatten().collect();
+    let unknown = ids.iter().filter(|id| !known.contains(id.as_str())).count();
+
+    let mut deleted = load(&args.input)?;
+    let before = deleted.len();
+    deleted.extend(ids.into_iter().filter(|id| known.contains(id.as_str())));
+    files::atomic(path(&args.input), |file| {
+        serde_json::to_writer_pretty(file, &deleted)
+            .map_err(|e| polars_err!(ComputeError: "failed to write the tombstones: {}", e))
+    })?;
+    audit::changed(&args.input, deleted.len() - before);
+    println!(
+        "Deleted {} rows, {} in total until compact",
+        deleted.len() - before,
+        deleted.len()
+    );
+    if unknown > 0 {
+        println!("Skipped {} ids that are not in the dataset", unknown);
+    }
+    Ok(())
+}
+
+// `lf` with the rows sharing the embedding of a `deleted` row given a copy of it as their own, as it is about
+// to be dropped, see brew --dedup
+fn adopt(lf: LazyFrame, deleted: &BTreeSet<String>) -> PolarsResult<LazyFrame> {
+    let schema = lf.schema()?;
+    let Some(dtype) = schema
+        .get("embeddings")
+        .filter(|_| schema.contains(dedup::COLUMN))
+    else {
+        return Ok(lf);
+    };
+    let deleted = Series::new("", deleted.iter().cloned().collect::<Vec<_>>());
+    let orphaned = col(dedup::COLUMN).is_in(lit(deleted)).fill_null(lit(false));
+    let shared = col(dedup::COLUMN).is_not_null().and(orphaned.clone().not());
+    Ok(dedup::resolve(lf)?.with_columns([
+        // The rows still sharing a live row's embedding store none, as before
+        when(shared)
+            .then(lit(NULL).cast(dtype.clone()))
+            .otherwise(col("embeddings"))
+            .alias("embeddings"),
+        when(orphaned)
+            .then(lit(NULL).cast(DataType::String))
+            .otherwise(col(dedup::COLUMN))
+            .alias(dedup::COLUMN),
+    ]))
+}
+
+/// Rewrite the dataset without its deleted rows and their answers
+pub fn compact(args: CompactArgs) -> PolarsResult<()> {
+    let _locks = lock::rewrite(&args.input, &args.input)?;
+    let deleted = load(&args.input)?;
+    if deleted.is_empty() {
+        println!("Nothing to compact");
+        return Ok(());
+    }
+
+    let lf = LazyFrame::scan_parquet(&args.input, Default::default())?;
+    let mut df = exclude(deleted.clone(), adopt(lf, &deleted)?)?.collect()?;
+    write_dataset(&mut df, &args.input)?;
+    answers::drop(&args.input, &deleted)?;
+    std::fs::remove_file(files::long(&path(&args.input)))
+        .map_err(|e| files::with_path(e, &path(&args.input)))?;
+    audit::changed(&args.input, deleted.len());
+    println!("Dropped {} rows, {} left", deleted.len(), df.height());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adopt, exclude};
+    use crate::dedup;
+    use polars::prelude::*;
+    use std::collections::BTreeSet;
+
+    fn vector(values: &[f32]) -> Option<Series> {
+        Some(Series::new("", values))
+    }
+
+    // `b` shares the embedding of `a`, `d` the one of `c`
+    fn shared() -> LazyFrame {
+        df!(
+            "id" => ["a", "b", "c", "d"],
+            "embeddings" => [vector(&[1.0, 0.0]), None, vector(&[0.0, 1.0]), None],
+            dedup::COLUMN => [None, Some("a"), None, Some("c")]
+        )
+        .unwrap()
+        .lazy()
+    }
+
+    fn embeddings(df: &DataFrame) -> Vec<Option<Vec<f32>>> {
+        df.column("embeddings")
+            .unwrap()
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|e| e.map(|e| e.f32().unwrap().into_no_null_iter().collect()))
+            .collect()
+    }
+
+    #[test]
+    fn resolve_fills_in_shared_embeddings() {
+        let df = dedup::resolve(shared()).unwrap().collect().unwrap();
+        assert_eq!(
+            embeddings(&df),
+            [
+                Some(vec![1.0, 0.0]),
+                Some(vec![1.0, 0.0]),
+                Some(vec![0.0, 1.0]),
+                Some(vec![0.0, 1.0])
+            ]
+        );
+    }
+
+    #[test]
+    fn compacting_an_owner_gives_its_embedding_to_the_rows_sharing_it() {
+        let deleted = BTreeSet::from(["a".to_string()]);
+        let df = exclude(deleted.clone(), adopt(shared(), &deleted).unwrap())
+            .unwrap()
+            .collect()
+            .unwrap();
+        let ids: Vec<_> = df
+            .column("id")
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_no_null_iter()
+            .collect();
+        assert_eq!(ids, ["b", "c", "d"]);
+        // `b` stores a copy, `d` still shares the embedding of `c`
+        assert_eq!(
+            embeddings(&df),
+            [Some(vec![1.0, 0.0]), Some(vec![0.0, 1.0]), None]
+        );
+        let sharing: Vec<_> = df
+            .column(dedup::COLUMN)
+            .unwrap()
+            .str()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(sharing, [None, None, Some("c")]);
+        let resolved = dedup::resolve(df.lazy()).unwrap().collect().unwrap();
+        assert_eq!(
+            embeddings(&resolved),
+            [
+                Some(vec![1.0, 0.0]),
+                Some(vec![0.0, 1.0]),
+                Some(vec![0.0, 1.0])
+            ]
+        );
+    }
+
+    #[test]
+    fn compacting_a_row_sharing_an_embedding_leaves_its_owner() {
+        let deleted = BTreeSet::from(["b".to_string()]);
+        let df = exclude(deleted.clone(), adopt(shared(), &deleted).unwrap())
+            .unwrap()
+            .collect()
+            .unwrap();
+        assert_eq!(
+            embeddings(&df),
+            [Some(vec![1.0, 0.0]), Some(vec![0.0, 1.0]), None]
+        );
+    }
+}
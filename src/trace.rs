@@ -0,0 +1,144 @@
+use crate::files;
+use polars::prelude::*;
+use serde::Serialize;
+use std::{collections::HashSet, path::Path};
+
+// Rows recorded of every stage, the most similar or best ranked, so that a pipeline without `candidates.k`
+// doesn't write the whole corpus into the trace
+const MAX_ROWS: usize = 1000;
+
+/// What every stage of a search pipeline did with the rows of a query, written by --trace-retrieval
+#[derive(Serialize)]
+pub struct Trace<'a> {
+    query: &'a str,
+    stages: Vec<Stage>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "stage", rename_all = "lowercase")]
+enum Stage {
+    /// The nearest neighbours by similarity, and how many rows were left out
+    Candidates {
+        k: Option<usize>,
+        admitted: Vec<Row>,
+        removed: usize,
+        #[serde(skip_serializing_if = "is_zero")]
+        omitted: usize,
+    },
+    /// The candidates dropped by the filters
+    Filters {
+        removed: Vec<Row>,
+        #[serde(skip_serializing_if = "is_zero")]
+        omitted: usize,
+    },
+    /// The candidates left, in the order of their final score
+    Rerank {
+        ranked: Vec<Row>,
+        #[serde(skip_serializing_if = "is_zero")]
+        omitted: usize,
+    },
+    /// The same, reordered for diversity
+    Mmr {
+        ranked: Vec<Row>,
+        #[serde(skip_serializing_if = "is_zero")]
+        omitted: usize,
+    },
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+// The first MAX_ROWS of `rows`, and how many more were left out
+fn capped(mut rows: Vec<Row>) -> (Vec<Row>, usize) {
+    let omitted = rows.len().saturating_sub(MAX_ROWS);
+    rows.truncate(MAX_ROWS);
+    (rows, omitted)
+}
+
+// `df` from the most to the least similar
+fn by_similarity(df: &DataFrame) -> PolarsResult<DataFrame> {
+    df.sort(["similarity"], true, true)
+}
+
+#[derive(Serialize)]
+struct Row {
+    id: String,
+    similarity: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<f64>,
+}
+
+// The rows of `df` with their similarity and, once reranked, their final score
+fn rows(df: &DataFrame, similarity: &str, score: Option<&str>) -> PolarsResult<Vec<Row>> {
+    let ids = df.column("id")?.cast(&DataType::String)?;
+    let similarities = df.column(similarity)?.f64()?;
+    let scores = score.map(|s| df.column(s)?.f64().cloned()).transpose()?;
+    Ok(ids
+        .str()?
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| Row {
+            id: id.unwrap_or_default().to_string(),
+            similarity: similarities.get(i),
+            score: scores.as_ref().and_then(|s| s.get(i)),
+        })
+        .collect())
+}
+
+impl<'a> Trace<'a> {
+    pub fn new(query: &'a str) -> Self {
+        Self {
+            query,
+            stages: vec![],
+        }
+    }
+
+    pub fn candidates(
+        &mut self,
+        k: Option<usize>,
+        all: usize,
+        admitted: &DataFrame,
+    ) -> PolarsResult<()> {
+        let (admitted_rows, omitted) = capped(rows(&by_similarity(admitted)?, "similarity", None)?);
+        self.stages.push(Stage::Candidates {
+            k,
+            admitted: admitted_rows,
+            removed: all - admitted.height(),
+            omitted,
+        });
+        Ok(())
+    }
+
+    pub fn filters(&mut self, candidates: &DataFrame, kept: &DataFrame) -> PolarsResult<()> {
+        let kept = kept.column("id")?.cast(&DataType::String)?;
+        let kept: HashSet<&str> = kept.str()?.into_iter().flatten().collect();
+        let removed = rows(&by_similarity(candidates)?, "similarity", None)?
+            .into_iter()
+            .filter(|r| !kept.contains(r.id.as_str()))
+            .collect();
+        let (removed, omitted) = capped(removed);
+        self.stages.push(Stage::Filters { removed, omitted });
+        Ok(())
+    }
+
+    /// Record the ranked rows, with their similarity before reranking in `similarity`
+    pub fn rerank(&mut self, ranked: &DataFrame, similarity: &str) -> PolarsResult<()> {
+        let (ranked, omitted) = capped(rows(ranked, similarity, Some("similarity"))?);
+        self.stages.push(Stage::Rerank { ranked, omitted });
+        Ok(())
+    }
+
+    pub fn mmr(&mut self, ranked: &DataFrame, similarity: &str) -> PolarsResult<()> {
+        let (ranked, omitted) = capped(rows(ranked, similarity, Some("similarity"))?);
+        self.stages.push(Stage::Mmr { ranked, omitted });
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> PolarsResult<()> {
+        files::atomic(path, |file| {
+            serde_json::to_writer_pretty(file, self)
+                .map_err(|e| polars_err!(ComputeError: "failed to write the trace: {}", e))
+        })
+    }
+}
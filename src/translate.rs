@@ -0,0 +1,107 @@
+use async_openai::{
+    config::OpenAIConfig,
+    types::{
+        ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
+        CreateChatCompletionRequestArgs,
+    },
+    Client,
+};
+use clap::Args;
+use polars::prelude::*;
+use whatlang::Lang;
+
+#[derive(Args)]
+pub struct TranslateArgs {
+    /// Language of the query, `auto` to detect it. Non-English queries are translated before embedding
+    #[arg(long, value_name = "auto|LANG")]
+    query_lang: Option<String>,
+    /// Chat model used for translating the query
+    #[arg(long, default_value = "gpt-3.5-turbo", requires = "query_lang")]
+    translate_model: String,
+    /// Base URL of an OpenAI-compatible provider to translate with instead of OpenAI
+    #[arg(long, value_name = "URL", requires = "query_lang")]
+    translate_api_base: Option<String>,
+}
+
+// The language name of the query if it needs translating, None for English or an undetectable language
+fn source_language(query_lang: &str, text: &str) -> Option<String> {
+    if query_lang != "auto" {
+        return match Lang::from_code(query_lang) {
+            Some(Lang::Eng) => None,
+            Some(lang) => Some(lang.eng_name().to_string()),
+            None if query_lang.eq_ignore_ascii_case("en")
+                || query_lang.eq_ignore_ascii_case("english") =>
+            {
+                None
+            }
+            None => Some(query_lang.to_string()),
+        };
+    }
+    // Short queries are hard to tell apart, only trust confident guesses
+    match whatlang::detect(text) {
+        Some(info) if info.is_reliable() && info.lang() != Lang::Eng => {
+            Some(info.lang().eng_name().to_string())
+        }
+        _ => None,
+    }
+}
+
+impl TranslateArgs {
+    /// Translate `text` into English as requested by `--query-lang`, returning it unchanged otherwise
+    pub async fn translate(&self, text: String) -> PolarsResult<String> {
+        let Some(lang) = self
+            .query_lang
+            .as_deref()
+            .and_then(|query_lang| source_language(query_lang, &text))
+        else {
+            return Ok(text);
+        };
+
+        let mut config = OpenAIConfig::new();
+        if let Some(base) = &self.translate_api_base {
+            config = config.with_api_base(base);
+        }
+        let client = Client::with_config(config);
+
+        let map_err = |e| polars_err!(ComputeError: "failed to translate the query: {}", e);
+        let req = CreateChatCompletionRequestArgs::default()
+            .model(&self.translate_model)
+            .temperature(0.0)
+            .messages([
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(format!(
+                        "Translate the user's search query from {} into English. \
+                         Keep technical terms and formulas as they are and reply with the translation only.",
+                        lang
+                    ))
+                    .build()
+                    .map_err(map_err)?
+                    .into(),
+                ChatCompletionRequestUserMessageArgs::default()
+                    .content(text)
+                    .build()
+                    .map_err(map_err)?
+                    .into(),
+            ])
+            .build()
+            .map_err(map_err)?;
+
+        let translated = client
+            .chat()
+            .create(req)
+            .await
+            .map_err(map_err)?
+            .choices
+            .pop()
+            .and_then(|c| c.message.content)
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty());
+        match translated {
+            Some(translated) => {
+                println!("Translated query from {}: {}", lang, translated);
+                Ok(translated)
+            }
+            None => polars_bail!(ComputeError: "no translation returned for the query"),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{files, lock, tags, tombstones};
+use clap::{Args, ValueEnum};
+use polars::prelude::*;
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Args)]
+pub struct TrendsArgs {
+    input: PathBuf,
+    /// Where to write the monthly volumes per tag
+    #[arg(short, long)]
+    output: PathBuf,
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+    /// Number of trailing months compared against the rest of the history to find emerging tags
+    #[arg(long, default_value_t = 12)]
+    recent: usize,
+}
+
+/// Report the monthly question volume of every tag and the tags gaining the most ground
+pub fn trends(args: TrendsArgs) -> PolarsResult<()> {
+    let _lock = lock::Lock::shared(&args.input)?;
+    let lf = tombstones::live(
+        &args.input,
+        LazyFrame::scan_parquet(&args.input, Default::default())?,
+    )?;
+    let schema = lf.schema()?;
+    if !schema.contains("creation_date") {
+        polars_bail!(ColumnNotFound: "dataset has no `creation_date` column, parse it again to record the dates");
+    }
+
+    let mut df = lf
+        .select([
+            col("creation_date").dt().strftime("%Y-%m").alias("month"),
+            tags::column(&schema).alias("tag"),
+        ])
+        .explode([col("tag")])
+        .group_by([col("month"), col("tag")])
+        .agg([col("tag").count().alias("count")])
+        .with_column(
+            (col("count").cast(DataType::Float64)
+                / col("count")
+                    .sum()
+                    .over([col("month")])
+                    .cast(DataType::Float64))
+            .alias("share"),
+        )
+        .sort_by_exprs([col("month"), col("tag")], [false, false], false, false)
+        .collect()?;
+
+    let mut file = files::create(&args.output)?;
+    match args.format {
+        Format::Csv => CsvWriter::new(&mut file).finish(&mut df)?,
+        Format::Json => crate::json::write_records(&df, &mut file)?,
+    }
+    println!(
+        "Wrote {} monthly tag volumes to {}",
+        df.height(),
+        args.output.display()
+    );
+
+    let months = df.column("month")?.drop_nulls().unique()?.sort(false, true);
+    if months.len() <= args.recent {
+        println!(
+            "Not enough history to compare the last {} months",
+            args.recent
+        );
+        return Ok(());
+    }
+    let cutoff = months
+        .str()?
+        .get(months.len() - args.recent)
+        .unwrap_or_default();
+
+    // A tag emerges when its share of all tag occurrences grows in the recent period
+    let recent = col("month").gt_eq(lit(cutoff));
+    let emerging = df
+        .lazy()
+        .group_by([col("tag")])
+        .agg([
+            col("count").sum().alias("total"),
+            col("count").filter(recent.clone()).sum().alias("recent"),
+            col("count").filter(recent.not()).sum().alias("earlier"),
+        ])
+        .with_columns([
+            (col("recent").cast(DataType::Float64) / col("recent").sum().cast(DataType::Float64))
+                .alias("recent_share"),
+            (col("earlier").cast(DataType::Float64) / col("earlier").sum().cast(DataType::Float64))
+                .alias("earlier_share"),
+        ])
+        .with_column((col("recent_share") - col("earlier_share")).alias("growth"))
+        .sort(
+            "growth",
+            SortOptions {
+                descending: true,
+                nulls_last: true,
+                ..Default::default()
+            },
+        )
+        .limit(10)
+        .collect()?;
+
+    println!("Emerging tags since {}:", cutoff);
+    println!("{}", emerging);
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+use crate::xml;
+use polars::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+/// Display name and reputation of the users in `wanted`, from a Users.xml dump
+pub fn load(path: &Path, wanted: &HashSet<u32>) -> PolarsResult<HashMap<u32, (String, i32)>> {
+    let mut users = HashMap::new();
+    xml::rows(path, "Users.xml", |row| {
+        let Some(id) = row.get("Id").and_then(|v| v.parse().ok()) else {
+            return Ok(());
+        };
+        if wanted.contains(&id) {
+            let name = row.get("DisplayName").unwrap_or_default().to_string();
+            let reputation = row
+                .get("Reputation")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(0);
+            users.insert(id, (name, reputation));
+        }
+        Ok(())
+    })?;
+    Ok(users)
+}
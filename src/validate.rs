@@ -0,0 +1,217 @@
+use crate::{dedup, embedding_dtype, lock, tombstones};
+use clap::Args;
+use polars::prelude::*;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+#[derive(Args)]
+pub struct ValidateArgs {
+    input: PathBuf,
+    /// Also fail on rows without embeddings, e.g. to check a dataset is fully brewed before publishing it
+    #[arg(long)]
+    require_embeddings: bool,
+}
+
+// The dtypes other commands expect of the columns parse-xml writes, when they are present
+fn expected(name: &str) -> Option<&'static [DataType]> {
+    use DataType::*;
+    Some(match name {
+        "id" | "title" | "body" => &[String],
+        "score" => &[Int32, Int64],
+        "answer_count" | "view_count" => &[UInt32, UInt64, Int32, Int64],
+        "site" | "author" | "license" | "url" | "comments" | "accepted_answer" => &[String],
+        _ => return None,
+    })
+}
+
+// Every way a dataset can be wrong, listed in the report
+#[derive(Default)]
+struct Report {
+    problems: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl Report {
+    fn check_embeddings(&mut self, df: &DataFrame, name: &str, required: bool) -> PolarsResult<()> {
+        let (mut missing, mut invalid) = (0, 0);
+        let mut dimensions: BTreeMap<usize, usize> = BTreeMap::new();
+        for embedding in df.column(name)?.list()? {
+            let Some(embedding) = embedding else {
+                missing += 1;
+                continue;
+            };
+            let embedding = embedding.cast(&DataType::Float32)?;
+            let values = embedding.f32()?;
+            if values.null_count() > 0 || values.into_iter().flatten().any(|v| !v.is_finite()) {
+                invalid += 1;
+            }
+            *dimensions.entry(embedding.len()).or_default() += 1;
+        }
+        if dimensions.len() > 1 {
+            let counts: Vec<String> = dimensions
+                .iter()
+                .map(|(d, n)| format!("{} rows of {}", n, d))
+                .collect();
+            self.problems.push(format!(
+                "`{}` mixes dimensions: {}",
+                name,
+                counts.join(", ")
+            ));
+        }
+        if dimensions.contains_key(&0) {
+            self.problems
+                .push(format!("`{}` holds {} empty vectors", name, dimensions[&0]));
+        }
+        if invalid > 0 {
+            self.problems.push(format!(
+                "`{}` holds {} vectors with NaN, infinite or null values",
+                name, invalid
+            ));
+        }
+        if missing > 0 {
+            let message = format!(
+                "`{}` is null for {} rows, brew has yet to embed them",
+                name, missing
+            );
+            match required {
+                true => self.problems.push(message),
+                false => self.warnings.push(message),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check the schema and contents of a dataset, failing with a report of everything found wrong
+pub fn validate(args: ValidateArgs) -> PolarsResult<()> {
+    let _lock = lock::Lock::shared(&args.input)?;
+    let lf = LazyFrame::scan_parquet(&args.input, Default::default())?;
+    let schema = lf.schema()?;
+    let mut report = Report::default();
+
+    for name in ["id", "title", "body", "embeddings"] {
+        if !schema.contains(name) {
+            report
+                .problems
+                .push(format!("the `{}` column is missing", name));
+        }
+    }
+    let mut embeddings = vec![];
+    for (name, dtype) in schema.iter() {
+        let name = name.as_str();
+        if name == "embeddings" || name.starts_with("embeddings_") {
+            match dtype {
+                DataType::List(inner) if matches!(**inner, DataType::Float32 | DataType::Null) => {
+                    embeddings.push(name)
+                }
+                dtype => report.problems.push(format!(
+                    "`{}` is {}, expected {}; convert it with --cast-embeddings",
+                    name,
+                    dtype,
+                    embedding_dtype()
+                )),
+            }
+            continue;
+        }
+        let wanted = match name {
+            "tags" if matches!(dtype, DataType::List(inner) if **inner == DataType::String) => {
+                continue
+            }
+            // Datasets written before tags were lists keep the string of the dumps until migrated
+            "tags" => {
+                if *dtype == DataType::String {
+                    report
+                        .warnings
+                        .push("`tags` is kept as a string, run migrate to make it a list".into());
+                    continue;
+                }
+                "list[str]".to_string()
+            }
+            "creation_date" if matches!(dtype, DataType::Datetime(..)) => continue,
+            "creation_date" => "datetime".to_string(),
+            name => match expected(name) {
+                Some(dtypes) if !dtypes.contains(dtype) => dtypes
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+                _ => continue,
+            },
+        };
+        report
+            .problems
+            .push(format!("`{}` is {}, expected {}", name, dtype, wanted));
+    }
+
+    let mut columns: Vec<Expr> = embeddings.iter().map(|name| col(name)).collect();
+    if schema.contains("id") {
+        columns.push(col("id").cast(DataType::String));
+    }
+    if schema.get("body") == Some(&DataType::String) {
+        columns.push(col("body"));
+    }
+    let lf = tombstones::live(&args.input, dedup::resolve(lf)?)?;
+    let df = lf.select(columns).collect()?;
+    println!("{} rows", df.height());
+
+    if let Ok(ids) = df.column("id") {
+        let ids = ids.str()?;
+        if ids.null_count() > 0 {
+            report
+                .problems
+                .push(format!("{} rows have no id", ids.null_count()));
+        }
+        let mut seen: HashMap<&str, usize> = HashMap::new();
+        for id in ids.into_iter().flatten() {
+            *seen.entry(id).or_default() += 1;
+        }
+        let mut repeated: Vec<&str> = seen
+            .into_iter()
+            .filter(|&(_, n)| n > 1)
+            .map(|(id, _)| id)
+            .collect();
+        repeated.sort();
+        if !repeated.is_empty() {
+            let more = match repeated.len() > 10 {
+                true => format!(" and {} more", repeated.len() - 10),
+                false => String::new(),
+            };
+            report.problems.push(format!(
+                "{} ids are repeated: {}{}",
+                repeated.len(),
+                repeated[..repeated.len().min(10)].join(", "),
+                more
+            ));
+        }
+    }
+    if let Ok(bodies) = df.column("body") {
+        let empty = bodies
+            .str()?
+            .into_iter()
+            .filter(|body| body.is_none_or(|b| b.trim().is_empty()))
+            .count();
+        if empty > 0 {
+            report
+                .problems
+                .push(format!("{} rows have an empty body", empty));
+        }
+    }
+    for name in embeddings {
+        report.check_embeddings(&df, name, args.require_embeddings)?;
+    }
+
+    for warning in &report.warnings {
+        println!("Warning: {}", warning);
+    }
+    for problem in &report.problems {
+        println!("Problem: {}", problem);
+    }
+    if !report.problems.is_empty() {
+        polars_bail!(ComputeError: "{} failed {} checks", args.input.display(), report.problems.len());
+    }
+    println!("{} is valid", args.input.display());
+
+    Ok(())
+}
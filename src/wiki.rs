@@ -0,0 +1,453 @@
+use crate::{files, jsonl::Documents, markdown, scrub::ScrubArgs, store};
+use bzip2::read::MultiBzDecoder;
+use clap::Args;
+use polars::prelude::*;
+use quick_xml::events::Event;
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+#[derive(Args)]
+pub struct IngestWikiArgs {
+    /// A dump of the articles of a wiki, such as enwiki-latest-pages-articles.xml.bz2 of dumps.wikimedia.org,
+    /// compressed or not
+    input: PathBuf,
+    /// The dataset to write, or its object store URL such as s3://bucket/wiki.parquet
+    output: PathBuf,
+    /// Most characters of a row: sections are split at their paragraphs to fit, as long texts embed poorly
+    #[arg(long, default_value_t = 2000)]
+    chunk_chars: usize,
+    /// Skip rows of fewer characters, such as stubs and sections holding only a table
+    #[arg(long, default_value_t = 200)]
+    min_chars: usize,
+    /// Sections left out of every article, may be repeated
+    #[arg(long = "skip-section", value_name = "HEADING", default_values_t = ["See also", "References", "Notes", "External links", "Further reading", "Bibliography", "Sources"].map(String::from))]
+    skip_sections: Vec<String>,
+    /// Only ingest the first this many articles, to try a model on a small part of the dump first
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+    /// Link to a row with `{id}` in place of its id, the title of its article followed by the anchor of its
+    /// section [default: the article on the wiki the dump comes from]
+    #[arg(long, value_name = "TEMPLATE")]
+    url_template: Option<String>,
+    #[command(flatten)]
+    scrub: ScrubArgs,
+    /// Overwrite the output instead of merging into it, dropping its embeddings
+    #[arg(long)]
+    replace: bool,
+}
+
+/// An article of the dump, still in wikitext
+struct Page {
+    title: String,
+    namespace: String,
+    redirect: bool,
+    text: String,
+}
+
+// Call `each` on every page of the dump, pulling one at a time as dumps are far larger than memory, and
+// return the base URL of the wiki given by its `<siteinfo>`
+fn pages(
+    input: &std::path::Path,
+    reader: impl BufRead,
+    mut each: impl FnMut(Page) -> PolarsResult<bool>,
+) -> PolarsResult<Option<String>> {
+    let mut reader = quick_xml::Reader::from_reader(reader);
+    let invalid = |reader: &quick_xml::Reader<_>, e: &dyn std::fmt::Display| polars_err!(ComputeError: "{} is not valid XML at byte {}: {}", input.display(), reader.buffer_position(), e);
+
+    let mut base = None;
+    let mut page = None;
+    // The text read of the innermost element
+    let mut text = String::new();
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => return Ok(base),
+            Ok(Event::Start(e)) => {
+                text.clear();
+                if e.name().as_ref() == b"page" {
+                    page = Some(Page {
+                        title: String::new(),
+                        namespace: String::new(),
+                        redirect: false,
+                        text: String::new(),
+                    });
+                }
+            }
+            Ok(Event::Empty(e)) => {
+                if let (b"redirect", Some(page)) = (e.name().as_ref(), page.as_mut()) {
+                    page.redirect = true;
+                }
+            }
+            Ok(Event::Text(e)) => {
+                text.push_str(&e.unescape().map_err(|e| invalid(&reader, &e))?);
+            }
+            Ok(Event::End(e)) if e.name().as_ref() == b"page" => {
+                let page = page.take();
+                if !page.map(&mut each).transpose()?.unwrap_or(true) {
+                    return Ok(base);
+                }
+            }
+            Ok(Event::End(e)) => {
+                let text = std::mem::take(&mut text);
+                match (e.name().as_ref(), page.as_mut()) {
+                    (b"base", None) => base = Some(text),
+                    (b"title", Some(page)) => page.title = text,
+                    (b"ns", Some(page)) => page.namespace = text,
+                    (b"text", Some(page)) => page.text = text,
+                    _ => {}
+                }
+            }
+            Ok(_) => {}
+            Err(e) => return Err(invalid(&reader, &e)),
+        }
+        buf.clear();
+    }
+}
+
+fn regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+// `text` without what lies between `open` and the `close` matching it, nested or not, such as templates
+fn strip_nested(text: &str, open: &str, close: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let (mut depth, mut rest) = (0, text);
+    while !rest.is_empty() {
+        if rest.starts_with(open) {
+            depth += 1;
+            rest = &rest[open.len()..];
+        } else if depth > 0 && rest.starts_with(close) {
+            depth -= 1;
+            rest = &rest[close.len()..];
+        } else {
+            let c = rest.chars().next().unwrap();
+            if depth == 0 {
+                out.push(c);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    out
+}
+
+// Language codes of the wikis an article links to its translations on
+fn interwiki() -> &'static Regex {
+    static INTERWIKI: OnceLock<Regex> = OnceLock::new();
+    regex(&INTERWIKI, r"^[a-z]{2,3}(?:-[a-z]+)*$|^simple$")
+}
+
+// Internal links replaced by their label, links to files and other wikis dropped and categories collected
+fn links(text: &str, categories: &mut Vec<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("[[") {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 2..];
+        // Captions of images hold links of their own
+        let (mut depth, mut end) = (1, None);
+        let mut i = 0;
+        while i + 1 < rest.len() {
+            match &rest.as_bytes()[i..i + 2] {
+                b"[[" => depth += 1,
+                b"]]" => depth -= 1,
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            }
+            if depth == 0 {
+                end = Some(i);
+                break;
+            }
+            i += 2;
+        }
+        let Some(end) = end else {
+            break;
+        };
+        let link = &rest[..end];
+        rest = &rest[end + 2..];
+        let (target, label) = link.split_once('|').unwrap_or((link, link));
+        // A leading colon links to a category or file page instead of filing the article in it or showing it
+        if let Some(target) = target.strip_prefix(':') {
+            out.push_str(if label == link { target } else { label });
+            continue;
+        }
+        let namespace = target
+            .split_once(':')
+            .map(|(namespace, name)| (namespace.trim(), name));
+        match namespace {
+            Some((namespace, name)) if namespace.eq_ignore_ascii_case("category") => {
+                categories.push(name.trim().to_lowercase().replace([' ', '_'], "-"))
+            }
+            Some((namespace, _))
+                if ["file", "image", "media"]
+                    .iter()
+                    .any(|n| namespace.eq_ignore_ascii_case(n)) => {}
+            // The same article on other wikis, e.g. [[de:Entropie]] or [[zh-yue:熵]]
+            Some((namespace, _)) if interwiki().is_match(namespace) => {}
+            // Everything else is an article, such as [[Batman: Arkham Asylum]] or [[Mission:Impossible]]
+            _ => out.push_str(label),
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The plain text of `wikitext` as (heading level, heading, text) sections, the lead under no heading, and the
+/// categories of the article
+fn plain(wikitext: &str) -> (Vec<(usize, String, String)>, Vec<String>) {
+    static REFS: OnceLock<Regex> = OnceLock::new();
+    static EXTERNAL: OnceLock<Regex> = OnceLock::new();
+    static TAGS: OnceLock<Regex> = OnceLock::new();
+    static HEADING: OnceLock<Regex> = OnceLock::new();
+    static BLANK: OnceLock<Regex> = OnceLock::new();
+    // Comments, footnotes and the markup such as math and code whose source reads poorly as text
+    let refs = regex(
+        &REFS,
+        r"(?s)<!--.*?-->|<ref[^>/]*/>|<(ref|gallery|math|syntaxhighlight|source|timeline)\b[^>]*>.*?</(ref|gallery|math|syntaxhighlight|source|timeline)>",
+    );
+    let text = refs.replace_all(wikitext, "");
+    let text = strip_nested(&text, "{{", "}}");
+    let text = strip_nested(&text, "{|", "|}");
+    let mut categories = vec![];
+    let text = links(&text, &mut categories);
+    let text = regex(&EXTERNAL, r"\[(?:https?:)?//[^\s\]]*\s*([^\]]*)\]").replace_all(&text, "$1");
+    let text = regex(&TAGS, r"<[^>]*>").replace_all(&text, "");
+    let text = markdown::unescape(&text.replace("'''", "").replace("''", ""));
+
+    let heading = regex(&HEADING, r"^(={2,6})\s*(.+?)\s*={2,6}\s*$");
+    let mut sections = vec![(0, String::new(), String::new())];
+    for line in text.lines() {
+        match heading.captures(line) {
+            Some(captures) => {
+                sections.push((captures[1].len(), captures[2].to_string(), String::new()))
+            }
+            None => {
+                let body = &mut sections.last_mut().unwrap().2;
+                // Items of lists and indented lines read as lines of their own
+                let line = line.trim_start_matches(['*', '#', ':', ';']).trim();
+                body.push_str(line);
+                body.push('\n');
+            }
+        }
+    }
+    let blank = regex(&BLANK, r"\n{3,}");
+    for (_, _, body) in &mut sections {
+        *body = blank.replace_all(body.trim(), "\n\n").into_owned();
+    }
+    (sections, categories)
+}
+
+// `text` in chunks of at most `max` characters, split between paragraphs, or between words for a paragraph too
+// long on its own
+fn chunks(text: &str, max: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut chunk = String::new();
+    for paragraph in text.split("\n\n") {
+        let mut paragraph = paragraph;
+        loop {
+            let len = chunk.chars().count();
+            let room = max.saturating_sub(len + 2 * usize::from(len > 0));
+            if paragraph.chars().count() <= room {
+                if len > 0 {
+                    chunk.push_str("\n\n");
+                }
+                chunk.push_str(paragraph);
+                break;
+            }
+            if len > 0 {
+                chunks.push(std::mem::take(&mut chunk));
+                continue;
+            }
+            // Up to the character past the limit, which may be the space ending the last word that fits
+            let (cut, end) = paragraph
+                .char_indices()
+                .nth(max)
+                .map_or((paragraph.len(), paragraph.len()), |(i, c)| {
+                    (i, i + c.len_utf8())
+                });
+            let cut = paragraph[..end]
+                .rfind(char::is_whitespace)
+                .filter(|&i| i > 0)
+                .unwrap_or(cut);
+            chunks.push(paragraph[..cut].trim_end().to_string());
+            paragraph = paragraph[cut..].trim_start();
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Ingest the articles of a wiki dump into a dataset brew and search take like a parsed dump, a row per section
+/// of an article or per part of a long one, tagged with the categories of the article
+pub fn ingest(args: IngestWikiArgs) -> PolarsResult<()> {
+    // Ingested into a local copy of the output, to merge into what is already stored
+    if files::remote(&args.output) {
+        let url = args.output.clone();
+        return store::staged(&url, |local| {
+            ingest(IngestWikiArgs {
+                output: local.to_path_buf(),
+                ..args
+            })
+        });
+    }
+    if args.chunk_chars == 0 {
+        polars_bail!(ComputeError: "--chunk-chars must be at least 1");
+    }
+    let file = files::open(&args.input)?;
+    let compressed = args
+        .input
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("bz2"));
+    // Dumps are published compressed in many streams, so that parts can be read on their own
+    let reader: Box<dyn BufRead> = match compressed {
+        true => Box::new(BufReader::new(MultiBzDecoder::new(file))),
+        false => Box::new(BufReader::new(file)),
+    };
+
+    let mut documents = Documents::new(&args.scrub, false)?;
+    let (mut articles, mut short, mut skipped) = (0, 0, 0);
+    let base = pages(&args.input, reader, |page| {
+        // Talk pages, user pages, templates and the like are left out, as are redirects to articles
+        if page.namespace != "0" || page.redirect {
+            skipped += 1;
+            return Ok(true);
+        }
+        let (sections, categories) = plain(&page.text);
+        let article = page.title.replace(' ', "_");
+        // Headings within their parent sections, and anchors repeated as the wiki numbers them
+        let mut path: Vec<(usize, String)> = vec![];
+        let mut anchors: HashMap<String, usize> = HashMap::new();
+        for (level, heading, text) in sections {
+            path.retain(|(l, _)| *l < level);
+            if level > 0 {
+                path.push((level, heading.clone()));
+            }
+            // Counted for skipped sections too, as the wiki numbers them
+            let mut anchor = heading.replace(' ', "_");
+            let seen = anchors.entry(anchor.clone()).or_default();
+            *seen += 1;
+            if *seen > 1 {
+                anchor = format!("{}_{}", anchor, seen);
+            }
+            if path
+                .iter()
+                .any(|(_, h)| args.skip_sections.iter().any(|s| s.eq_ignore_ascii_case(h)))
+            {
+                continue;
+            }
+            let title: Vec<&str> = std::iter::once(page.title.as_str())
+                .chain(path.iter().map(|(_, h)| h.as_str()))
+                .collect();
+            for (n, chunk) in chunks(&text, args.chunk_chars).into_iter().enumerate() {
+                if chunk.chars().count() < args.min_chars {
+                    short += 1;
+                    continue;
+                }
+                let mut id = article.clone();
+                match (level > 0, n) {
+                    (true, 0) => id = format!("{}#{}", id, anchor),
+                    (true, n) => id = format!("{}#{}&part={}", id, anchor, n + 1),
+                    (false, 0) => {}
+                    (false, n) => id = format!("{}#part={}", id, n + 1),
+                }
+                documents.push(id, title.join(", "), &chunk, categories.clone());
+            }
+        }
+        articles += 1;
+        if articles % 10_000 == 0 {
+            println!("Ingested {} articles", articles);
+        }
+        Ok(args.limit.is_none_or(|limit| articles < limit))
+    })?;
+    println!(
+        "Ingested {} articles, skipped {} redirects and pages of other namespaces",
+        articles, skipped
+    );
+    if short > 0 {
+        println!(
+            "Skipped {} rows shorter than {} characters",
+            short, args.min_chars
+        );
+    }
+    // The base of the wiki is the URL of its main page, e.g. https://en.wikipedia.org/wiki/Main_Page
+    let url_template = args.url_template.or_else(|| {
+        let base = base?;
+        Some(format!("{}/{{id}}", &base[..base.rfind('/')?]))
+    });
+    documents.write(&args.output, url_template, &args.scrub, args.replace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{chunks, links, plain};
+
+    #[test]
+    fn links_keep_the_text_of_articles() {
+        let mut categories = vec![];
+        let text = links(
+            "[[Batman: Arkham Asylum]], [[Mission:Impossible]] and [[Cat|cat]]s",
+            &mut categories,
+        );
+        assert_eq!(text, "Batman: Arkham Asylum, Mission:Impossible and cats");
+        assert!(categories.is_empty());
+    }
+
+    #[test]
+    fn links_drop_files_and_other_wikis_and_collect_categories() {
+        let mut categories = vec![];
+        let text = links(
+            "A[[File:Clausius.jpg|thumb|[[Rudolf Clausius]] in 1865]]B[[de:Entropie]][[zh-yue:熵]]\
+             [[Category:Physical quantities|Entropy]][[:Category:Physics]]",
+            &mut categories,
+        );
+        assert_eq!(text, "ABCategory:Physics");
+        assert_eq!(categories, ["physical-quantities"]);
+    }
+
+    #[test]
+    fn plain_strips_markup_into_sections() {
+        let wikitext = "{{Infobox|name={{nested|x}}}}\n'''Entropy''' is a [[disorder (physics)|measure]]\
+            <ref name=\"a\">{{cite book|title=X}}</ref> of [https://example.org disorder].<!-- note -->\n\
+            {| class=\"wikitable\"\n| a || b\n|}\n\
+            == History ==\n* Carnot &amp; Clausius\n=== Later ===\nMore.\n[[Category:Thermodynamics]]";
+        let (sections, categories) = plain(wikitext);
+        assert_eq!(
+            sections,
+            [
+                (
+                    0,
+                    String::new(),
+                    "Entropy is a measure of disorder.".to_string()
+                ),
+                (2, "History".to_string(), "Carnot & Clausius".to_string()),
+                (3, "Later".to_string(), "More.".to_string()),
+            ]
+        );
+        assert_eq!(categories, ["thermodynamics"]);
+    }
+
+    #[test]
+    fn chunks_split_between_paragraphs() {
+        let text = "aaaa bbbb\n\ncccc\n\ndddd eeee ffff";
+        assert_eq!(chunks(text, 16), ["aaaa bbbb\n\ncccc", "dddd eeee ffff"]);
+        assert_eq!(chunks(text, 100), [text]);
+    }
+
+    #[test]
+    fn chunks_split_long_paragraphs_between_words() {
+        let text = "one two three four five six";
+        let parts = chunks(text, 10);
+        assert_eq!(parts, ["one two", "three four", "five six"]);
+        assert!(parts.iter().all(|p| p.chars().count() <= 10));
+        // A word longer than a chunk is cut
+        assert_eq!(chunks("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+    }
+}
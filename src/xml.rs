@@ -0,0 +1,155 @@
+use crate::files;
+use polars::prelude::*;
+use quick_xml::events::Event;
+use sevenz_rust::{Password, SevenZReader};
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// Attributes of a `<row>` of a Stack Exchange dump, unescaped
+pub struct Row(HashMap<Vec<u8>, String>);
+
+impl Row {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name.as_bytes()).map(String::as_str)
+    }
+}
+
+/// The dumps of Posts.xml at `input` with the site each comes from: a single file or a directory holding
+/// Posts.xml is one dump of no known site, while a directory of Stack Exchange archives, e.g.
+/// math.stackexchange.com.7z, or of directories named after their site, or a glob matching either, holds one
+/// dump per site.
+pub fn dumps(input: &Path) -> PolarsResult<Vec<(Option<String>, PathBuf)>> {
+    let pattern = input.to_string_lossy();
+    let paths: Vec<PathBuf> = match pattern.contains(['*', '?', '[']) {
+        true => glob::glob(&pattern)
+            .map_err(|e| polars_err!(ComputeError: "invalid pattern {}: {}", pattern, e))?
+            .collect::<Result<_, _>>()
+            .map_err(|e| polars_err!(ComputeError: "failed to read {}: {}", e.path().display(), e.error()))?,
+        false if input.is_dir() && !input.join("Posts.xml").exists() => {
+            let entries = std::fs::read_dir(files::long(input)).map_err(|e| files::with_path(e, input))?;
+            let mut paths = entries
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| files::with_path(e, input))?;
+            paths.sort();
+            paths
+        }
+        false => return Ok(vec![(None, posts(input))]),
+    };
+
+    let dumps: Vec<_> = paths
+        .into_iter()
+        .filter_map(|path| {
+            let archive = path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("7z"));
+            let (site, path) = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) if archive => (name[..name.len() - 3].to_string(), path),
+                // Posts.xml matched by the pattern itself, named after its directory
+                Some(name) if name.eq_ignore_ascii_case("Posts.xml") => {
+                    (path.parent()?.file_name()?.to_str()?.to_string(), path)
+                }
+                Some(name) if path.join("Posts.xml").exists() => {
+                    (name.to_string(), path.join("Posts.xml"))
+                }
+                _ => return None,
+            };
+            // Stack Overflow is too large for one archive and ships as stackoverflow.com-Posts.7z
+            let site = site
+                .strip_suffix("-Posts")
+                .map(String::from)
+                .unwrap_or(site);
+            Some((Some(site), path))
+        })
+        .collect();
+    if dumps.is_empty() {
+        polars_bail!(ComputeError: "found no Posts.xml or .7z archive of a site in {}", input.display());
+    }
+    Ok(dumps)
+}
+
+// The Posts.xml in a directory holding a dump, or the file itself
+fn posts(input: &Path) -> PathBuf {
+    match input.is_dir() {
+        true => input.join("Posts.xml"),
+        false => input.to_path_buf(),
+    }
+}
+
+/// Call `each` on every `<row>` of the dump at `path`, pulling one row at a time so that dumps far larger
+/// than memory can be read. A `.7z` archive as published by Stack Exchange is decompressed on the fly,
+/// reading the file called `member` in it, e.g. Posts.xml.
+pub fn rows(
+    path: &Path,
+    member: &str,
+    mut each: impl FnMut(&Row) -> PolarsResult<()>,
+) -> PolarsResult<()> {
+    if !path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("7z"))
+    {
+        return read(path, BufReader::new(files::open(path)?), &mut each);
+    }
+
+    let failed = |e: sevenz_rust::Error| polars_err!(ComputeError: "failed to read {}: {}", path.display(), e);
+    let file = files::open(path)?;
+    let len = file.metadata()?.len();
+    let mut archive = SevenZReader::new(file, len, Password::empty()).map_err(failed)?;
+    let (mut found, mut result) = (false, Ok(()));
+    archive
+        .for_each_entries(|entry, reader| {
+            if found {
+                return Ok(false);
+            }
+            let name = Path::new(entry.name()).file_name().unwrap_or_default();
+            if !name.eq_ignore_ascii_case(member) {
+                // Entries are decompressed in sequence, so the ones before have to be read through
+                std::io::copy(reader, &mut std::io::sink())?;
+                return Ok(true);
+            }
+            found = true;
+            result = read(&path.join(member), BufReader::new(reader), &mut each);
+            Ok(false)
+        })
+        .map_err(failed)?;
+    if !found {
+        polars_bail!(ComputeError: "{} has no {}", path.display(), member);
+    }
+    result
+}
+
+fn read(
+    path: &Path,
+    reader: impl BufRead,
+    each: &mut impl FnMut(&Row) -> PolarsResult<()>,
+) -> PolarsResult<()> {
+    let mut reader = quick_xml::Reader::from_reader(reader);
+    let invalid = |reader: &quick_xml::Reader<_>, e: &dyn std::fmt::Display| polars_err!(ComputeError: "{} is not valid XML at byte {}: {}", path.display(), reader.buffer_position(), e);
+
+    let mut buf = Vec::new();
+    loop {
+        let row = match reader.read_event_into(&mut buf) {
+            Ok(Event::Eof) => return Ok(()),
+            Ok(Event::Empty(e) | Event::Start(e)) if e.name().as_ref() == b"row" => e,
+            Ok(_) => {
+                buf.clear();
+                continue;
+            }
+            Err(e) => return Err(invalid(&reader, &e)),
+        };
+        let mut node = HashMap::new();
+        for a in row.attributes() {
+            let a = a.map_err(|e| invalid(&reader, &e))?;
+            // Whitespace written out literally is normalized to spaces as XML requires, &#xA; stays a newline
+            let raw = std::str::from_utf8(&a.value).map_err(|e| invalid(&reader, &e))?;
+            let raw = raw.replace("\r\n", " ").replace(['\t', '\n', '\r'], " ");
+            let value = quick_xml::escape::unescape(&raw).map_err(|e| invalid(&reader, &e))?;
+            node.insert(a.key.as_ref().to_vec(), value.into_owned());
+        }
+        buf.clear();
+        each(&Row(node))?;
+    }
+}